@@ -0,0 +1,72 @@
+use std::str::FromStr;
+use image::{DynamicImage, Frame};
+use crate::{apply, detect, output_size, resize, CropParams, CropRect, Error, Result};
+
+/// How the crop of an animation is determined from its frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FrameCrop {
+    /// Crop every frame to the union of the rectangles detected in each frame
+    #[default]
+    Union,
+    /// Crop each frame to its own detected rectangle, resizing to the size of the union
+    PerFrame
+}
+
+impl FromStr for FrameCrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(FrameCrop::Union),
+            "per-frame" => Ok(FrameCrop::PerFrame),
+            _ => Err(format!("unknown frame crop '{}', expected union or per-frame", s))
+        }
+    }
+}
+
+/// Detect the crop rectangle of each frame of an animation, along with the union of all of them.
+///
+/// Frames in which no edges are detected are left out of the union, failing with
+/// [`Error::NoEdges`] only if no edges are detected in any frame.
+pub fn detect_frames(frames: &[Frame], params: &CropParams) -> Result<(Vec<Option<CropRect>>, CropRect)> {
+    let rects: Vec<_> = frames.iter()
+        .map(|frame| detect(&DynamicImage::ImageRgba8(frame.buffer().clone()), &params.detection))
+        .collect();
+    let union = rects.iter()
+        .flatten()
+        .copied()
+        .reduce(|a, b| a.union(&b))
+        .ok_or(Error::NoEdges)?;
+    Ok((rects, union))
+}
+
+/// Crop and downscale every frame of an animation consistently, restoring the original aspect ratio
+pub fn process_frames(frames: Vec<Frame>, params: &CropParams, crop: FrameCrop) -> Result<Vec<Frame>> {
+    let (rects, union) = detect_frames(&frames, params)?;
+    let Some(first) = frames.first() else {
+        return Ok(frames);
+    };
+    let (width, height) = output_size(first.buffer().width(), first.buffer().height(), &union, params);
+
+    Ok(frames.into_iter().zip(rects).map(|(frame, rect)| {
+        let delay = frame.delay();
+        let img = DynamicImage::ImageRgba8(frame.into_buffer());
+        let processed = match (crop, rect) {
+            (FrameCrop::PerFrame, Some(rect)) => {
+                resize(&apply(&img, &rect, params), width, height, params)
+            },
+            _ => apply(&img, &union, params)
+        };
+        Frame::from_parts(processed.to_rgba8(), 0, 0, delay)
+    }).collect())
+}
+
+/// Crop and downscale every frame of an animation to the given rectangle, restoring the original
+/// aspect ratio
+pub fn crop_frames(frames: Vec<Frame>, rect: &CropRect, params: &CropParams) -> Vec<Frame> {
+    frames.into_iter().map(|frame| {
+        let delay = frame.delay();
+        let processed = apply(&DynamicImage::ImageRgba8(frame.into_buffer()), rect, params);
+        Frame::from_parts(processed.to_rgba8(), 0, 0, delay)
+    }).collect()
+}
@@ -0,0 +1,164 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use flate2::read::DeflateDecoder;
+use image::ImageResult;
+
+/// Extensions of ZIP archives, including comic book archives
+const EXTENSIONS: [&str; 2] = ["zip", "cbz"];
+
+/// Signatures of the records of a ZIP archive
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_DIRECTORY: u32 = 0x0605_4B50;
+
+/// Compression methods of entries
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// Flag of an entry marking that it is encrypted, and that its name is UTF-8
+const ENCRYPTED_FLAG: u16 = 0x0001;
+const UTF8_FLAG: u16 = 0x0800;
+
+/// Version of the ZIP specification needed to extract stored entries
+const VERSION: u16 = 20;
+
+/// File in an archive, with its data decompressed
+pub struct Entry {
+    /// Path within the archive, with `/` separating folders, which end with `/`
+    pub name: String,
+    pub data: Vec<u8>,
+    /// Modification time and date in MS-DOS format
+    pub modified: [u8; 4]
+}
+
+/// Check whether a path has the extension of a ZIP archive
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|archive| ext.eq_ignore_ascii_case(archive)))
+}
+
+/// Read and decompress every entry of a ZIP archive, in the order of its central directory.
+/// Entries must be stored or deflated and not encrypted, and ZIP64 archives are not supported.
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let data = fs::read(path)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("ZIP: {}", message));
+    let bytes = |at: usize, length: usize| data.get(at..at + length).ok_or_else(|| invalid("archive is truncated"));
+    let u16_at = |at: usize| bytes(at, 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at = |at: usize| bytes(at, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    // End of central directory record is last, followed by a comment of up to 64 KiB
+    let end = (data.len().saturating_sub(22 + 0xFFFF)..=data.len().saturating_sub(22)).rev()
+        .find(|&at| u32_at(at).ok() == Some(END_OF_DIRECTORY))
+        .ok_or_else(|| invalid("end of central directory not found"))?;
+    let (count, offset) = (u16_at(end + 10)?, u32_at(end + 16)?);
+    if count == 0xFFFF || offset == 0xFFFF_FFFF {
+        return Err(invalid("ZIP64 archives are not supported"));
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut at = offset as usize;
+    for _ in 0..count {
+        if u32_at(at)? != CENTRAL_HEADER {
+            return Err(invalid("central directory is corrupt"));
+        }
+        let (flags, method) = (u16_at(at + 8)?, u16_at(at + 10)?);
+        let modified = bytes(at + 12, 4)?.try_into().expect("slice has 4 bytes");
+        let (crc, compressed, size) = (u32_at(at + 16)?, u32_at(at + 20)? as usize, u32_at(at + 24)? as usize);
+        let (name_length, extra_length, comment_length) = (u16_at(at + 28)? as usize, u16_at(at + 30)? as usize, u16_at(at + 32)? as usize);
+        let local = u32_at(at + 42)? as usize;
+        let name = String::from_utf8_lossy(bytes(at + 46, name_length)?).into_owned();
+        at += 46 + name_length + extra_length + comment_length;
+
+        // Sizes and offsets beyond 32 bits are only given in ZIP64 extra fields
+        if [compressed, size, local].contains(&0xFFFF_FFFF) {
+            return Err(invalid(&format!("{} is a ZIP64 entry, which is not supported", name)));
+        }
+        if flags & ENCRYPTED_FLAG != 0 {
+            return Err(invalid(&format!("{} is encrypted", name)));
+        }
+        if u32_at(local)? != LOCAL_HEADER {
+            return Err(invalid(&format!("local header of {} is corrupt", name)));
+        }
+        let start = local + 30 + u16_at(local + 26)? as usize + u16_at(local + 28)? as usize;
+        let stored = bytes(start, compressed)?;
+        let data = match method {
+            STORED => stored.to_vec(),
+            DEFLATED => {
+                let mut data = Vec::with_capacity(size);
+                DeflateDecoder::new(stored).read_to_end(&mut data)
+                    .map_err(|err| invalid(&format!("{}: {}", name, err)))?;
+                data
+            },
+            _ => return Err(invalid(&format!("{} uses unsupported compression method {}", name, method)))
+        };
+        if data.len() != size || crc32fast::hash(&data) != crc {
+            return Err(invalid(&format!("{} is corrupt", name)));
+        }
+        entries.push(Entry { name, data, modified });
+    }
+    Ok(entries)
+}
+
+/// Write entries to a new ZIP archive in order, storing them without compression as images are
+/// already compressed
+pub fn write(entries: &[Entry], path: &Path) -> ImageResult<()> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "ZIP: archive is too large without ZIP64");
+    if entries.len() >= 0xFFFF {
+        return Err(too_large().into());
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut directory = Vec::new();
+    let mut offset = 0usize;
+    for entry in entries {
+        // Sizes of 0xFFFFFFFF mark that the size is given in a ZIP64 extra field
+        let size = u32::try_from(entry.data.len()).ok().filter(|&size| size != 0xFFFF_FFFF).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("ZIP: {} is too large without ZIP64", entry.name))
+        })?;
+        let local = u32::try_from(offset).map_err(|_| too_large())?;
+        let name = entry.name.as_bytes();
+        let name_length = u16::try_from(name.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("ZIP: name of {} is too long", entry.name))
+        })?;
+        // Fields shared by the local and central headers, from the version needed to the lengths
+        let mut fields = Vec::with_capacity(26);
+        fields.extend(VERSION.to_le_bytes());
+        fields.extend(UTF8_FLAG.to_le_bytes());
+        fields.extend(STORED.to_le_bytes());
+        fields.extend(entry.modified);
+        fields.extend(crc32fast::hash(&entry.data).to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend(name_length.to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+
+        writer.write_all(&LOCAL_HEADER.to_le_bytes())?;
+        writer.write_all(&fields)?;
+        writer.write_all(name)?;
+        writer.write_all(&entry.data)?;
+        offset += 4 + fields.len() + name.len() + entry.data.len();
+
+        directory.extend(CENTRAL_HEADER.to_le_bytes());
+        directory.extend(VERSION.to_le_bytes());
+        directory.extend(fields);
+        // Comment length, disk number, and internal and external attributes
+        directory.extend([0; 10]);
+        directory.extend(local.to_le_bytes());
+        directory.extend(name);
+    }
+
+    let offset = u32::try_from(offset).map_err(|_| too_large())?;
+    let directory_length = u32::try_from(directory.len()).map_err(|_| too_large())?;
+    writer.write_all(&directory)?;
+    writer.write_all(&END_OF_DIRECTORY.to_le_bytes())?;
+    // Disk numbers, as the archive is not split
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&(entries.len() as u16).to_le_bytes())?;
+    writer.write_all(&(entries.len() as u16).to_le_bytes())?;
+    writer.write_all(&directory_length.to_le_bytes())?;
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
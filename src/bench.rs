@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+use clap::Args;
+use cpar::{EdgeDetection, Sides};
+use crate::{open, svg};
+
+#[derive(Args)]
+/// Detect the crop of a sample image over a sweep of thresholds and percentiles, reporting each
+/// crop and how long it took to find, to choose parameters suiting a scanner
+pub struct Bench {
+    /// Sample image to detect crops of
+    sample: PathBuf,
+    /// Thresholds to sweep, as 8-bit levels, percentages or fractions
+    #[clap(short, long, value_delimiter = ',', default_value = "200,230,245,250,255", value_parser = parse_level)]
+    thresholds: Vec<Level>,
+    /// Percentiles to sweep
+    #[clap(
+        short, long, value_delimiter = ',', default_value = "80,90,95,100",
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    percentiles: Vec<u8>,
+    /// Scan every Nth row and column for the boundary, as with --sample-stride
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    sample_stride: u32,
+    /// Decode the sample without applying its EXIF orientation
+    #[clap(long)]
+    no_auto_orient: bool
+}
+
+/// Threshold as given, along with its value on the 16-bit scale
+#[derive(Clone)]
+struct Level {
+    text: String,
+    value: u16
+}
+
+/// Parse a threshold, keeping its text to report it as given
+fn parse_level(s: &str) -> Result<Level, String> {
+    cpar::parse_level(s).map(|value| Level { text: s.to_string(), value })
+}
+
+/// Run the benchmark, printing a table of results to stdout
+pub fn run(bench: Bench) -> ExitCode {
+    let start = Instant::now();
+    let img = match open(&bench.sample, !bench.no_auto_orient, svg::DEFAULT_DPI) {
+        Ok((img, _)) => img,
+        Err(err) => {
+            eprintln!("Error: {}: {}", bench.sample.display(), err);
+            return ExitCode::from(crate::FATAL);
+        }
+    };
+    println!("Decoded {}x{} image in {:.1}ms", img.width(), img.height(), start.elapsed().as_secs_f64() * 1000.0);
+
+    println!("{:>10} {:>10}  {:<24} {:>8} {:>10}", "threshold", "percentile", "crop", "kept", "time");
+    let area = img.width() as f64 * img.height() as f64;
+    for threshold in &bench.thresholds {
+        for &percentile in &bench.percentiles {
+            let detection = EdgeDetection {
+                threshold: Sides::all(threshold.value),
+                percentile: Sides::all(percentile),
+                sample_stride: bench.sample_stride,
+                ..EdgeDetection::default()
+            };
+            let start = Instant::now();
+            let rect = cpar::detect(&img, &detection);
+            let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+            let (crop, kept) = match rect {
+                Some(rect) => (rect.to_string(), format!("{:.1}%", 100.0 * rect.width as f64 * rect.height as f64 / area)),
+                None => ("no edges".to_string(), "-".to_string())
+            };
+            println!("{:>10} {:>10}  {:<24} {:>8} {:>8.1}ms", threshold.text, percentile, crop, kept, elapsed);
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,104 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use clap::{ArgMatches, Command};
+use clap::error::ErrorKind;
+use log::{error, warn};
+use rayon::prelude::*;
+use crate::report::{self, Detection};
+use crate::{cloud, config, lock, logging, overrides, pipe, FATAL};
+
+/// Detect the crop of each source image without writing any images, printing them to stdout as
+/// JSON in the form `cpar apply` reads. Multipage and animated images are given the union of the
+/// crops of their pages or frames. Exits with 1 if any image failed.
+pub fn detect(argv: &[OsString], command: &Command, matches: &ArgMatches) -> ExitCode {
+    let parsed = config::parse(argv, command, matches, false).and_then(|mut args| crate::load_files(&mut args).map(|()| args));
+    let args = match parsed {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(FATAL);
+        }
+    };
+    logging::init(args.quiet, args.verbose, args.log_format);
+    let params = crate::crop_params(&args);
+    let sources = match crate::find_sources(&args) {
+        Ok(sources) => sources,
+        Err(err) => {
+            error!(error:% = err; "Error: {}", err);
+            return ExitCode::from(FATAL);
+        }
+    };
+
+    let detections: Vec<Detection> = crate::worker_pool(&args).install(|| sources.par_iter().map(|source| {
+        let rect = lock::detect(&source.path, &args, &overrides::params(&args, &params, &source.path)).and_then(|rects| {
+            rects.into_iter().reduce(|a, b| a.union(&b)).ok_or(cpar::Error::NoEdges)
+        });
+        match rect {
+            Ok(rect) => Detection { source: source.path.clone(), crop: Some(rect.into()), error: None },
+            Err(err) => {
+                error!(image:% = source.path.display(), error:% = err; "Failed {}: {}", source.path.display(), err);
+                Detection { source: source.path.clone(), crop: None, error: Some(err.to_string()) }
+            }
+        }
+    }).collect());
+
+    let mut stdout = io::stdout().lock();
+    if let Err(err) = serde_json::to_writer_pretty(&mut stdout, &detections).map_err(io::Error::from)
+        .and_then(|()| writeln!(stdout)) {
+        error!(error:% = err; "Error: {}", err);
+        return ExitCode::from(FATAL);
+    }
+    match detections.iter().any(|detection| detection.error.is_some()) {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS
+    }
+}
+
+/// Crop source images to the crops listed in a JSON file by `cpar detect`, or by a report, instead
+/// of detecting their edges. Sources listed without a crop are skipped.
+pub fn apply(argv: &[OsString], command: &Command, matches: &ArgMatches) -> ExitCode {
+    let mut command = command.clone();
+    let mut args = match config::parse(argv, &command, matches, true) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(FATAL);
+        }
+    };
+    if args.source.len() != 1 {
+        command.error(ErrorKind::WrongNumberOfValues, "a single file of crops must be given").exit();
+    }
+    if args.files_from || args.apply_crops || args.lock_crop.is_some() || args.watch || args.split {
+        command.error(
+            ErrorKind::ArgumentConflict,
+            "crops are read from the file given, so --files-from, --apply-crops, --lock-crop, --watch and --split cannot be used"
+        ).exit();
+    }
+    if pipe::is_stdio(&args.output) || cloud::is_url(&args.output) {
+        command.error(ErrorKind::InvalidValue, "output must be a folder").exit();
+    }
+    let detections = match report::read_detections(&args.source[0]) {
+        Ok(detections) => detections,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(FATAL);
+        }
+    };
+
+    logging::init(args.quiet, args.verbose, args.log_format);
+    args.source.clear();
+    for detection in detections {
+        match detection.crop.filter(|crop| crop.width > 0 && crop.height > 0) {
+            Some(crop) => {
+                args.source.push(detection.source.clone());
+                args.boxes.insert(detection.source, crop.into());
+            },
+            None => warn!(image:% = detection.source.display(); "Skipped {}: no crop listed", detection.source.display())
+        }
+    }
+    crate::execute(args).unwrap_or_else(|err| {
+        error!(error:% = err; "Error: {}", err);
+        ExitCode::from(FATAL)
+    })
+}
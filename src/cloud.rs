@@ -0,0 +1,274 @@
+use std::path::Path;
+use std::process::ExitCode;
+use cpar::CropParams;
+use crate::Cpar;
+
+/// Schemes of object storage URLs, of Amazon S3 and Google Cloud Storage
+const SCHEMES: [&str; 2] = ["s3://", "gs://"];
+
+/// Check whether a source or output path is the URL of a prefix in object storage
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+/// Process images under object storage prefixes or local sources, writing them under an object
+/// storage prefix or the output folder.
+///
+/// Objects are streamed through memory by the `aws` and `gcloud` command line tools, which use
+/// their own configured credentials, and are never staged on disk. Exits with 1 if any image
+/// failed.
+#[cfg(feature = "cloud")]
+pub fn run(args: &Cpar, params: &CropParams, pool: &rayon::ThreadPool) -> cpar::Result<ExitCode> {
+    use std::io;
+    use log::{error, info, warn};
+    use rayon::prelude::*;
+    use crate::save::Existing;
+    use store::Processed;
+
+    crate::pipe::check(args)?;
+    if args.watch {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "watching does not support object storage").into());
+    }
+    let output = args.output.to_str().filter(|_| is_url(&args.output));
+    if output.is_some() && args.existing() == Existing::Rename {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "renaming is not supported in object storage").into());
+    }
+
+    // List source objects and files, and the objects already under the output prefix
+    let mut objects = Vec::new();
+    for source in &args.source {
+        match source.to_str().filter(|_| is_url(source)) {
+            Some(url) => objects.extend(store::list(url)?),
+            None => objects.extend(crate::sources::expand(std::slice::from_ref(source))?.into_iter().map(|file| {
+                store::Object { source: file.path.to_string_lossy().into_owned(), relative: file.relative.to_string_lossy().into_owned() }
+            }))
+        }
+    }
+    let existing = match (output, args.existing()) {
+        (Some(url), Existing::Fail | Existing::Skip) => store::list(url)?.into_iter().map(|object| object.source).collect(),
+        _ => Default::default()
+    };
+
+    let failures = pool.install(|| objects.par_iter().filter(|object| {
+        let image = object.source.as_str();
+        match store::process(object, output, &existing, args, params) {
+            Ok(Processed::Saved(dest)) => info!(image, dest = dest.as_str(); "Finished {} as {}", image, dest),
+            Ok(Processed::Reported) => {},
+            Ok(Processed::Existing) => info!(image; "Skipped {}: output exists", image),
+            Ok(Processed::Flagged(flag)) => warn!(image, flag = flag.as_str(); "Skipped {}: {}", image, flag),
+            Err(err) => {
+                error!(image = object.source.as_str(), error:% = err; "Failed {}: {}", object.source, err);
+                return true;
+            }
+        }
+        false
+    }).count());
+    Ok(match failures {
+        0 => ExitCode::SUCCESS,
+        _ => {
+            error!("{} of {} images failed", failures, objects.len());
+            ExitCode::FAILURE
+        }
+    })
+}
+
+/// Fail to process images in object storage, as support for it was not built
+#[cfg(not(feature = "cloud"))]
+pub fn run(_: &Cpar, _: &CropParams, _: &rayon::ThreadPool) -> cpar::Result<ExitCode> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "object storage requires building with the cloud feature").into())
+}
+
+#[cfg(feature = "cloud")]
+mod store {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::io::{self, Cursor, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use cpar::CropParams;
+    use crate::guard::Blank;
+    use crate::pipe::{self, Detected};
+    use crate::save::{self, Existing, SaveOptions};
+    use crate::{dpi, hook, sources, xmp, Cpar};
+
+    /// Source image, as the URL of an object or the path of a file, and its path relative to the
+    /// prefix or folder it was found under
+    pub struct Object {
+        pub source: String,
+        pub relative: String
+    }
+
+    /// List images under a prefix as a folder, or the image a URL refers to
+    pub fn list(prefix: &str) -> io::Result<Vec<Object>> {
+        if sources::is_image(Path::new(prefix)) {
+            let relative = prefix.rsplit('/').next().unwrap_or_default().to_string();
+            return Ok(vec![Object { source: prefix.to_string(), relative }]);
+        }
+        let folder = format!("{}/", prefix.trim_end_matches('/'));
+        let urls: Vec<String> = match folder.strip_prefix("s3://") {
+            Some(path) => {
+                let bucket = path.split('/').next().unwrap_or_default();
+                // Lines give the modification date and time and the size before each key
+                let listing = run(Command::new("aws").args(["s3", "ls", "--recursive", &folder]), None, true)?;
+                String::from_utf8_lossy(&listing).lines()
+                    .filter_map(|line| after_words(line, 3))
+                    .map(|key| format!("s3://{}/{}", bucket, key))
+                    .collect()
+            },
+            None => {
+                let listing = run(Command::new("gcloud").args(["storage", "ls", &format!("{}**", folder)]), None, true)?;
+                String::from_utf8_lossy(&listing).lines().map(str::to_string).collect()
+            }
+        };
+        Ok(urls.into_iter()
+            .filter(|url| url.starts_with(&folder) && sources::is_image(Path::new(url)))
+            .map(|url| Object { relative: url[folder.len()..].to_string(), source: url })
+            .collect())
+    }
+
+    /// Outcome of processing an image
+    pub enum Processed {
+        /// Processed image was written to the given URL or path
+        Saved(String),
+        /// Crop was reported without saving
+        Reported,
+        /// Image was skipped as its output exists
+        Existing,
+        /// Image was skipped as no edges were detected or its crop failed a guard, for the given
+        /// reason
+        Flagged(String)
+    }
+
+    /// Process an image, writing it under the output prefix or to the output folder, as the
+    /// filesystem path does for a single image
+    pub fn process(
+        object: &Object,
+        output: Option<&str>,
+        existing: &HashSet<String>,
+        args: &Cpar,
+        params: &CropParams
+    ) -> cpar::Result<Processed> {
+        let buffer = match super::is_url(Path::new(&object.source)) {
+            true => download(&object.source)?,
+            false => fs::read(&object.source)?
+        };
+        let detected = match pipe::detect(buffer, args, params) {
+            Ok(detected) => detected,
+            Err(cpar::Error::NoEdges) if args.on_blank() == Blank::Skip => {
+                return Ok(Processed::Flagged("no edges detected".to_string()));
+            },
+            Err(err) => return Err(err)
+        };
+        let rect = match pipe::guarded(args, params, &detected) {
+            Ok(rect) => rect,
+            Err(flag) => return Ok(Processed::Flagged(flag))
+        };
+        let Detected { img, orientation, rotation, skew, input_format, dpi, .. } = detected;
+        let params = &*dpi::scaled_params(args, params, dpi);
+        let format = pipe::output_format(args, input_format);
+        let name = match args.preserve_structure {
+            true => &object.relative,
+            false => object.relative.rsplit('/').next().unwrap_or_default()
+        };
+        let name = Path::new(name).with_extension(format.extension()).to_string_lossy().replace('\\', "/");
+        let dest = match output {
+            Some(prefix) => format!("{}/{}", prefix.trim_end_matches('/'), name),
+            None => args.output.join(&name).to_string_lossy().into_owned()
+        };
+        let (width, height) = cpar::output_size(img.width(), img.height(), &rect, params);
+        if args.inspect {
+            println!("{}: crop {}\n{}", object.source, rect, cpar::heatmap(&img, &rect, args.inspect_width));
+        }
+        if args.dry_run {
+            println!(
+                "{}: {}x{}{} cropped to {}, {} {}x{}, saved to {}",
+                object.source, img.width(), img.height(), crate::deskewed(skew), rect, crate::restored(params), width, height, dest
+            );
+            return Ok(Processed::Reported);
+        }
+        let processed = cpar::apply(&img, &rect, params);
+        let options = SaveOptions {
+            format: Some(format),
+            xmp: args.xmp.then(|| xmp::packet(img.width(), img.height(), &rect, orientation, rotation, skew, params.restore)),
+            dpi: dpi::output_dpi(args, dpi, (img.width(), img.height()), &rect, (width, height), params),
+            ..args.save_options()
+        };
+
+        if output.is_none() {
+            let dest = Path::new(&dest);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            return Ok(match save::save_claimed(dest, args.existing(), |dest| save::save(&processed, dest, &options))? {
+                Some(dest) => Processed::Saved(dest.to_string_lossy().into_owned()),
+                None => Processed::Existing
+            });
+        }
+        if existing.contains(&dest) {
+            return match args.existing() {
+                Existing::Skip => Ok(Processed::Existing),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists, use --overwrite or --skip-existing", dest)
+                ).into())
+            };
+        }
+        let mut buffer = Cursor::new(Vec::new());
+        save::write(&processed, &mut buffer, format, &options)?;
+        save::verify(Cursor::new(buffer.get_ref()), &processed, format, &options)?;
+        upload(&dest, buffer.get_ref(), format.mime_type())?;
+        if let Some(packet) = options.xmp.as_ref().filter(|_| !xmp::embeds(format)) {
+            upload(&format!("{}.xmp", dest), packet.as_bytes(), "application/rdf+xml")?;
+        }
+        Ok(Processed::Saved(dest))
+    }
+
+    /// Read an object to memory through the stdout of the tool
+    fn download(url: &str) -> io::Result<Vec<u8>> {
+        match url.starts_with("s3://") {
+            true => run(Command::new("aws").args(["s3", "cp", "--only-show-errors", url, "-"]), None, false),
+            false => run(Command::new("gcloud").args(["storage", "cat", url]), None, false)
+        }
+    }
+
+    /// Write an object from memory through the stdin of the tool
+    fn upload(url: &str, data: &[u8], content_type: &str) -> io::Result<()> {
+        let upload = match url.starts_with("s3://") {
+            true => run(Command::new("aws").args(["s3", "cp", "--only-show-errors", "--content-type", content_type, "-", url]), Some(data), false),
+            false => run(Command::new("gcloud").args(["storage", "cp", "--content-type", content_type, "-", url]), Some(data), false)
+        };
+        upload.map(drop)
+    }
+
+    /// Run a tool, writing input to its stdin, and return what it wrote to stdout. When listing, a
+    /// prefix matching no objects is not treated as failing.
+    fn run(command: &mut Command, input: Option<&[u8]>, listing: bool) -> io::Result<Vec<u8>> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = command
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("failed to run {}: {}", program, err)))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input)?;
+        }
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let unmatched = listing && output.stdout.is_empty() && (stderr.trim().is_empty() || stderr.contains("matched no objects"));
+        if output.status.success() || unmatched {
+            return Ok(output.stdout);
+        }
+        Err(hook::command_error(&program, &output))
+    }
+
+    /// Rest of a line after skipping a number of whitespace separated words, keeping whitespace
+    /// within it
+    fn after_words(line: &str, words: usize) -> Option<&str> {
+        let mut rest = line.trim_start();
+        for _ in 0..words {
+            rest = rest[rest.find(char::is_whitespace)?..].trim_start();
+        }
+        Some(rest).filter(|rest| !rest.is_empty())
+    }
+}
@@ -0,0 +1,133 @@
+use std::str::FromStr;
+use image::{ColorType, DynamicImage, GrayImage, RgbImage, RgbaImage};
+
+/// Color type images are converted to before saving
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Colorspace {
+    /// 8-bit grayscale
+    Gray,
+    /// 16-bit grayscale
+    Gray16,
+    /// 8-bit RGB
+    Rgb,
+    /// 8-bit RGB with alpha
+    Rgba
+}
+
+impl FromStr for Colorspace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gray" => Ok(Colorspace::Gray),
+            "gray16" => Ok(Colorspace::Gray16),
+            "rgb" => Ok(Colorspace::Rgb),
+            "rgba" => Ok(Colorspace::Rgba),
+            _ => Err(format!("unknown colorspace '{}', expected gray, gray16, rgb or rgba", s))
+        }
+    }
+}
+
+/// Dithering applied when reducing images of more than 8 bits per channel to 8 bits
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Round each level to the nearest 8-bit level
+    None,
+    /// Offset levels by a repeating 4x4 Bayer pattern before rounding
+    Ordered,
+    /// Diffuse the rounding error of each level onto its neighbours
+    FloydSteinberg
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            _ => Err(format!("unknown dither '{}', expected none, ordered or floyd-steinberg", s))
+        }
+    }
+}
+
+/// Thresholds of ordered dithering, in sixteenths of a level
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Convert an image to a colorspace, dithering when reducing its bit depth to 8 bits. Returns
+/// `None` if the image is already in that colorspace.
+pub fn convert(img: &DynamicImage, colorspace: Colorspace, dither: Dither) -> Option<DynamicImage> {
+    let color = img.color();
+    let target = match colorspace {
+        Colorspace::Gray => ColorType::L8,
+        Colorspace::Gray16 => ColorType::L16,
+        Colorspace::Rgb => ColorType::Rgb8,
+        Colorspace::Rgba => ColorType::Rgba8
+    };
+    if color == target {
+        return None;
+    }
+    let (width, height) = (img.width(), img.height());
+    let dither = match color.bytes_per_pixel() / color.channel_count() {
+        1 => Dither::None,
+        _ => dither
+    };
+    Some(match (colorspace, dither) {
+        (Colorspace::Gray16, _) => DynamicImage::ImageLuma16(img.to_luma16()),
+        (Colorspace::Gray, Dither::None) => DynamicImage::ImageLuma8(img.to_luma8()),
+        (Colorspace::Rgb, Dither::None) => DynamicImage::ImageRgb8(img.to_rgb8()),
+        (Colorspace::Rgba, Dither::None) => DynamicImage::ImageRgba8(img.to_rgba8()),
+        (Colorspace::Gray, dither) => {
+            let levels = quantize(img.to_luma32f().into_raw(), width as usize, 1, dither);
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, levels)?)
+        },
+        (Colorspace::Rgb, dither) => {
+            let levels = quantize(img.to_rgb32f().into_raw(), width as usize, 3, dither);
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, levels)?)
+        },
+        (Colorspace::Rgba, dither) => {
+            let levels = quantize(img.to_rgba32f().into_raw(), width as usize, 4, dither);
+            DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, levels)?)
+        }
+    })
+}
+
+/// Quantize levels from 0 to 1 to 8 bits with dithering, leaving the alpha of 4 channel images
+/// undithered
+fn quantize(mut levels: Vec<f32>, width: usize, channels: usize, dither: Dither) -> Vec<u8> {
+    let stride = width * channels;
+    let dithered = channels.min(3);
+    for level in &mut levels {
+        *level *= 255.0;
+    }
+    let mut quantized = Vec::with_capacity(levels.len());
+    for i in 0..levels.len() {
+        let (x, y, channel) = (i % stride / channels, i / stride, i % channels);
+        let level = levels[i];
+        let rounded = match dither {
+            Dither::Ordered if channel < dithered => {
+                (level + (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5).round()
+            },
+            _ => level.round()
+        }.clamp(0.0, 255.0);
+        if dither == Dither::FloydSteinberg && channel < dithered {
+            let error = level - rounded;
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width {
+                    let neighbour = i + dy * stride + nx as usize * channels - x * channels;
+                    if let Some(neighbour) = levels.get_mut(neighbour) {
+                        *neighbour += error * weight;
+                    }
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+        quantized.push(rounded as u8);
+    }
+    quantized
+}
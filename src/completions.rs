@@ -0,0 +1,21 @@
+use std::io::{self, Write};
+use std::process::ExitCode;
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+use crate::Cli;
+
+#[derive(Args)]
+/// Print a completion script for a shell, to be sourced from its startup file
+pub struct Completions {
+    /// Shell to complete options for (bash, zsh, fish, powershell, elvish)
+    shell: Shell
+}
+
+/// Print the completion script to stdout
+pub fn run(completions: Completions) -> ExitCode {
+    let mut script = Vec::new();
+    clap_complete::generate(completions.shell, &mut Cli::command(), "cpar", &mut script);
+    // Output cut short by a closed pipe is not an error
+    let _ = io::stdout().write_all(&script);
+    ExitCode::SUCCESS
+}
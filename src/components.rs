@@ -0,0 +1,111 @@
+use image::DynamicImage;
+use crate::detect::{row_levels, thresholds};
+use crate::{DetectMode, EdgeDetection, Sides};
+
+/// Run of consecutive artwork pixels along a row, labelled with the region it belongs to
+struct Run {
+    start: u32,
+    end: u32,
+    label: usize
+}
+
+/// Size and bounding box of a connected region of artwork
+#[derive(Clone, Copy)]
+struct Region {
+    area: u64,
+    edges: Sides<u32>
+}
+
+impl Region {
+    fn merge(&mut self, other: &Region) {
+        self.area += other.area;
+        self.edges = Sides {
+            left: self.edges.left.min(other.edges.left),
+            right: self.edges.right.max(other.edges.right),
+            top: self.edges.top.min(other.edges.top),
+            bottom: self.edges.bottom.max(other.edges.bottom)
+        };
+    }
+}
+
+/// Detect the position of each edge of the artwork within an image as the bounding box of its
+/// largest region of 8-connected artwork pixels, or of every region of at least `min_area` pixels,
+/// so that specks and shadows apart from the subject are ignored.
+///
+/// Pixels are artwork with luma below the threshold of the left side. Returns `None` if no region
+/// is large enough.
+pub fn component_edges(img: &DynamicImage, detection: &EdgeDetection, min_area: Option<u32>) -> Option<Sides<u32>> {
+    let threshold = thresholds(img, detection).left;
+    let mode = DetectMode::Luma;
+
+    // Label runs of each row, joining them to the regions of the runs they touch in the row above,
+    // so that only two rows of runs are held rather than a label per pixel
+    let mut parents: Vec<usize> = Vec::new();
+    let mut regions: Vec<Region> = Vec::new();
+    let (mut above, mut runs) = (Vec::<Run>::new(), Vec::<Run>::new());
+    let mut levels = Vec::with_capacity(img.width() as usize);
+    for y in 0..img.height() {
+        row_levels(img, y, mode, &mut levels);
+        runs.clear();
+        let mut x = 0;
+        while x < levels.len() {
+            if !mode.crosses(levels[x], threshold) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < levels.len() && mode.crosses(levels[x], threshold) {
+                x += 1;
+            }
+            let (start, end) = (start as u32, x as u32);
+            let label = parents.len();
+            parents.push(label);
+            regions.push(Region {
+                area: (end - start) as u64,
+                edges: Sides { left: start, right: end - 1, top: y, bottom: y }
+            });
+            // Runs touch diagonally as well as directly above
+            for run in above.iter().filter(|run| run.start <= end && run.end >= start) {
+                union(&mut parents, label, run.label);
+            }
+            runs.push(Run { start, end, label });
+        }
+        std::mem::swap(&mut above, &mut runs);
+    }
+
+    // Gather the runs of each region into its root
+    let mut merged: Vec<Option<Region>> = vec![None; regions.len()];
+    for (label, region) in regions.iter().enumerate() {
+        let root = find(&mut parents, label);
+        match &mut merged[root] {
+            Some(merged) => merged.merge(region),
+            empty => *empty = Some(*region)
+        }
+    }
+    let regions = merged.into_iter().flatten();
+    match min_area {
+        Some(min_area) => regions.filter(|region| region.area >= min_area as u64).reduce(|mut union, region| {
+            union.merge(&region);
+            union
+        }),
+        None => regions.max_by_key(|region| region.area)
+    }
+    .map(|region| region.edges)
+}
+
+/// Root label of the region a label belongs to, halving the path to it on the way
+fn find(parents: &mut [usize], mut label: usize) -> usize {
+    while parents[label] != label {
+        parents[label] = parents[parents[label]];
+        label = parents[label];
+    }
+    label
+}
+
+/// Join the regions of two labels
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    if a != b {
+        parents[a.max(b)] = a.min(b);
+    }
+}
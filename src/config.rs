@@ -0,0 +1,114 @@
+use std::{fs, io};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use clap::{Arg, Command, FromArgMatches};
+use clap::error::ErrorKind;
+use clap::parser::{ArgMatches, ValueSource};
+use toml::{Table, Value};
+use crate::Cpar;
+
+/// Take arguments matched by the built command of the flat form or a subcommand taking the same
+/// options, filling in options from the selected preset of the config file. The output folder is
+/// taken from the end of the sources if `output` is set.
+///
+/// Presets are tables under `[presets]` mapping long option names to values. Options given on the
+/// command line take priority over preset values, including options which conflict with them, for
+/// example `--xt` on the command line overriding `threshold` from the preset.
+pub fn parse(argv: &[OsString], command: &Command, matches: &ArgMatches, output: bool) -> io::Result<Cpar> {
+    let finish = |matches: &ArgMatches| {
+        let mut args = Cpar::from_arg_matches(matches).unwrap_or_else(|err| err.format(&mut command.clone()).exit());
+        args.settings = settings(command, matches);
+        match output {
+            true => take_output(args, command),
+            false => args
+        }
+    };
+    let Some(preset) = matches.get_one::<String>("preset") else {
+        return Ok(finish(matches));
+    };
+
+    // Load preset from config file
+    let path = matches.get_one::<PathBuf>("config").expect("config has a default value");
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    let config = fs::read_to_string(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?
+        .parse::<Table>()
+        .map_err(|err| invalid(err.to_string()))?;
+    let options = config.get("presets")
+        .and_then(|presets| presets.get(preset))
+        .and_then(Value::as_table)
+        .ok_or_else(|| invalid(format!("no preset named '{}'", preset)))?;
+
+    // Insert preset options ahead of command line arguments, skipping those overridden by the user
+    let mut preset_args = Vec::new();
+    for (name, value) in options {
+        let arg = command.get_arguments()
+            .find(|arg| arg.get_long() == Some(name) || arg.get_all_aliases().is_some_and(|aliases| aliases.contains(&name.as_str())))
+            .ok_or_else(|| invalid(format!("unknown option '{}' in preset '{}'", name, preset)))?;
+        if !overridden(command, matches, arg) {
+            push_option(&mut preset_args, arg, value).map_err(invalid)?;
+        }
+    }
+    let argv = argv[..1].iter().cloned().chain(preset_args).chain(argv[1..].iter().cloned());
+    Ok(finish(&command.clone().get_matches_from(argv)))
+}
+
+/// Options which control how a run is carried out rather than the images it saves. The parameter
+/// manifest is recorded by the row for each image instead, so that editing one row processes only
+/// that image again
+const RUN_OPTIONS: [&str; 27] = [
+    "source", "files_from", "config", "preset", "watch", "overwrite", "skip_existing", "rename", "resume", "state_file",
+    "incremental", "manifest", "param_manifest", "streamed_decode", "verify", "verify_checksum", "dry_run", "report",
+    "stats_json", "failed_list", "error_sidecars", "copy_failed", "metrics", "quiet", "verbose", "log_format", "jobs"
+];
+
+/// Options affecting the images saved, with the values given or defaulted, for --incremental to
+/// process sources again when they change
+fn settings(command: &Command, matches: &ArgMatches) -> String {
+    let mut settings = Vec::new();
+    for arg in command.get_arguments().filter(|arg| !RUN_OPTIONS.contains(&arg.get_id().as_str())) {
+        if let Some(values) = matches.get_raw(arg.get_id().as_str()) {
+            let values: Vec<_> = values.map(|value| value.to_string_lossy()).collect();
+            settings.push(format!("--{}={}", arg.get_id(), values.join(",")));
+        }
+    }
+    settings.join(" ")
+}
+
+/// Take the output folder from the end of the sources, unless processing in place
+fn take_output(mut args: Cpar, command: &Command) -> Cpar {
+    if !args.in_place {
+        if args.source.len() < 2 {
+            command.clone()
+                .error(ErrorKind::MissingRequiredArgument, "an output folder must follow the sources, or use --in-place")
+                .exit();
+        }
+        args.output = args.source.pop().expect("sources are not empty");
+    }
+    args
+}
+
+/// Check whether an option, or any option conflicting with it, was given on the command line
+fn overridden(command: &Command, matches: &ArgMatches, arg: &Arg) -> bool {
+    let given = |arg: &Arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+    given(arg) || command.get_arguments().any(|other| given(other) && (
+        command.get_arg_conflicts_with(arg).contains(&other) || command.get_arg_conflicts_with(other).contains(&arg)
+    ))
+}
+
+/// Convert a preset value into command line arguments for an option
+fn push_option(args: &mut Vec<OsString>, arg: &Arg, value: &Value) -> Result<(), String> {
+    let long = arg.get_long().expect("preset options are looked up by long name");
+    match value {
+        Value::Boolean(true) => args.push(format!("--{}", long).into()),
+        Value::Boolean(false) => {},
+        Value::String(value) => args.push(format!("--{}={}", long, value).into()),
+        Value::Integer(value) => args.push(format!("--{}={}", long, value).into()),
+        Value::Float(value) => args.push(format!("--{}={}", long, value).into()),
+        Value::Array(values) => for value in values {
+            push_option(args, arg, value)?;
+        },
+        _ => return Err(format!("unsupported value for option '{}'", long))
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use image::{DynamicImage, ImageBuffer, Pixel};
+use crate::CropRect;
+
+/// Crop an image to a rectangle within its existing buffer, moving each kept row to the front and
+/// releasing the remainder
+pub fn crop_in_place(img: DynamicImage, rect: &CropRect) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(buf) => DynamicImage::ImageLuma8(crop_buffer(buf, rect)),
+        DynamicImage::ImageLumaA8(buf) => DynamicImage::ImageLumaA8(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgb8(buf) => DynamicImage::ImageRgb8(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgba8(buf) => DynamicImage::ImageRgba8(crop_buffer(buf, rect)),
+        DynamicImage::ImageLuma16(buf) => DynamicImage::ImageLuma16(crop_buffer(buf, rect)),
+        DynamicImage::ImageLumaA16(buf) => DynamicImage::ImageLumaA16(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgb16(buf) => DynamicImage::ImageRgb16(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba16(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgb32F(buf) => DynamicImage::ImageRgb32F(crop_buffer(buf, rect)),
+        DynamicImage::ImageRgba32F(buf) => DynamicImage::ImageRgba32F(crop_buffer(buf, rect)),
+        img => img.crop_imm(rect.x, rect.y, rect.width, rect.height)
+    }
+}
+
+fn crop_buffer<P: Pixel>(buf: ImageBuffer<P, Vec<P::Subpixel>>, rect: &CropRect) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let channels = P::CHANNEL_COUNT as usize;
+    let stride = buf.width() as usize * channels;
+    let row = rect.width as usize * channels;
+    let mut raw = buf.into_raw();
+
+    // Kept rows only ever move towards the front, so may be copied in order
+    for y in 0..rect.height as usize {
+        let start = (rect.y as usize + y) * stride + rect.x as usize * channels;
+        raw.copy_within(start..start + row, y * row);
+    }
+    raw.truncate(row * rect.height as usize);
+    raw.shrink_to_fit();
+    ImageBuffer::from_raw(rect.width, rect.height, raw).expect("cropped buffer matches its dimensions")
+}
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use cpar::CropRect;
+use image::{imageops, DynamicImage, GenericImageView};
+
+/// Handling of images whose crop looks the same as that of an image processed earlier in the batch,
+/// which are recorded as duplicates in the report either way
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dedupe {
+    /// Save the image, flagged for review
+    Flag,
+    /// Skip the image without saving it, flagged for review
+    Skip
+}
+
+impl FromStr for Dedupe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flag" => Ok(Dedupe::Flag),
+            "skip" => Ok(Dedupe::Skip),
+            _ => Err(format!("unknown dedupe action '{}', expected flag or skip", s))
+        }
+    }
+}
+
+/// Largest ratio between the aspect ratios of two crops for them to count as duplicates, as the
+/// hashes of crops of plain artwork match whatever their shape
+const ASPECT_TOLERANCE: f32 = 1.1;
+
+/// Perceptual hashes and aspect ratios of the crops of the images processed so far, along with
+/// their sources
+#[derive(Default)]
+pub struct Seen(Mutex<Vec<(u64, f32, PathBuf)>>);
+
+impl Seen {
+    /// Source of an image processed earlier whose crop has nearly the same aspect ratio and a hash
+    /// differing from this one's in at most the given number of bits, or otherwise `None`,
+    /// recording this one for the images after it
+    pub fn original(&self, img: &DynamicImage, rect: &CropRect, source: &Path, distance: u32) -> Option<PathBuf> {
+        let (hash, aspect) = (hash(img, rect), rect.width as f32 / rect.height as f32);
+        let mut seen = self.0.lock().unwrap();
+        let duplicate = seen.iter().find(|(seen, seen_aspect, _)| {
+            (seen ^ hash).count_ones() <= distance && (aspect / seen_aspect).max(seen_aspect / aspect) <= ASPECT_TOLERANCE
+        });
+        match duplicate {
+            Some((_, _, original)) => Some(original.clone()),
+            None => {
+                seen.push((hash, aspect, source.to_path_buf()));
+                None
+            }
+        }
+    }
+}
+
+/// Difference hash of the crop of an image: whether each of 8 columns of a 9x8 thumbnail is
+/// darker than the column to its right, along each of its rows. The hashes of rescans of the same
+/// artwork differ in few bits, however they were compressed or scaled.
+fn hash(img: &DynamicImage, rect: &CropRect) -> u64 {
+    let thumbnail = imageops::thumbnail(&*img.view(rect.x, rect.y, rect.width, rect.height), 9, 8);
+    let luma = |x: u32, y: u32| {
+        let [r, g, b, _] = thumbnail.get_pixel(x, y).0;
+        299 * r as u32 + 587 * g as u32 + 114 * b as u32
+    };
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = hash << 1 | (luma(x, y) < luma(x + 1, y)) as u64;
+        }
+    }
+    hash
+}
@@ -0,0 +1,99 @@
+use image::{DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage};
+use image::imageops::interpolate_bilinear;
+use crate::detect::boundary;
+use crate::EdgeDetection;
+
+/// Largest rotation in degrees, either way, considered when estimating skew
+const MAX_SKEW: f32 = 5.0;
+
+/// Step in degrees between rotations considered when estimating skew
+const SKEW_STEP: f32 = 0.05;
+
+/// Estimate the clockwise rotation in degrees of the artwork within an image, and rotate the image
+/// to correct it, filling uncovered corners with the background color.
+///
+/// Returns `None` if no skew was detected.
+pub fn deskew(img: &DynamicImage, detection: &EdgeDetection) -> Option<(DynamicImage, f32)> {
+    let angle = estimate_skew(img, detection)?;
+    Some((rotate(img, -angle, detection.mode.background()), angle))
+}
+
+/// Estimate the clockwise rotation in degrees of the artwork within an image, using a Hough
+/// transform of the artwork's boundary restricted to nearly axis-aligned lines.
+///
+/// Returns `None` if the artwork is not rotated, or its boundary could not be found.
+pub fn estimate_skew(img: &DynamicImage, detection: &EdgeDetection) -> Option<f32> {
+    let boundary = boundary(img, detection);
+    let offset = (img.width() + img.height()) as f32;
+    let steps = (MAX_SKEW / SKEW_STEP).round() as i32;
+
+    // Take the angle at which the most boundary points fall on a single line for each side,
+    // considering angles outward from zero so that ties favor the smallest rotation
+    let mut best = (0, 0);
+    for step in (0..=steps).flat_map(|step| [step, -step]) {
+        let tan = (step as f32 * SKEW_STEP).to_radians().tan();
+        let votes = [&boundary.left, &boundary.right]
+            .map(|points| peak(points, tan, offset))
+            .into_iter()
+            .chain([&boundary.top, &boundary.bottom].map(|points| peak(points, -tan, offset)))
+            .sum::<usize>();
+        if votes > best.1 {
+            best = (step, votes);
+        }
+    }
+    Some(best.0 as f32 * SKEW_STEP).filter(|&angle| best.1 > 0 && angle != 0.0)
+}
+
+/// Largest number of boundary points projecting onto the same line at the given slope
+fn peak(points: &[(u32, u32)], tan: f32, offset: f32) -> usize {
+    let last = 2 * offset as usize;
+    let mut histogram = vec![0; last + 1];
+    for &(along, depth) in points {
+        let projected = depth as f32 + along as f32 * tan + offset;
+        histogram[(projected.round() as usize).min(last)] += 1;
+    }
+    histogram.into_iter().max().unwrap_or(0)
+}
+
+/// Rotate an image clockwise by degrees about its center, keeping its dimensions and bit depth
+pub fn rotate(img: &DynamicImage, degrees: f32, fill: Rgba<u8>) -> DynamicImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let fill = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, fill));
+
+    // Rotate in the image's own pixel type, with the fill color converted to match
+    macro_rules! rotated {
+        ($variant:ident, $buf:expr, $convert:ident) => {
+            DynamicImage::$variant(rotate_buffer($buf, sin, cos, *fill.$convert().get_pixel(0, 0)))
+        };
+    }
+    match img {
+        DynamicImage::ImageLuma8(buf) => rotated!(ImageLuma8, buf, to_luma8),
+        DynamicImage::ImageLumaA8(buf) => rotated!(ImageLumaA8, buf, to_luma_alpha8),
+        DynamicImage::ImageRgb8(buf) => rotated!(ImageRgb8, buf, to_rgb8),
+        DynamicImage::ImageRgba8(buf) => rotated!(ImageRgba8, buf, to_rgba8),
+        DynamicImage::ImageLuma16(buf) => rotated!(ImageLuma16, buf, to_luma16),
+        DynamicImage::ImageLumaA16(buf) => rotated!(ImageLumaA16, buf, to_luma_alpha16),
+        DynamicImage::ImageRgb16(buf) => rotated!(ImageRgb16, buf, to_rgb16),
+        DynamicImage::ImageRgba16(buf) => rotated!(ImageRgba16, buf, to_rgba16),
+        DynamicImage::ImageRgb32F(buf) => rotated!(ImageRgb32F, buf, to_rgb32f),
+        _ => rotated!(ImageRgba32F, &img.to_rgba32f(), to_rgba32f)
+    }
+}
+
+/// Rotate an image buffer by inverse mapping each output pixel, interpolating the source bilinearly
+fn rotate_buffer<P: Pixel>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    sin: f32,
+    cos: f32,
+    fill: P
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let center_x = (img.width() as f32 - 1.0) / 2.0;
+    let center_y = (img.height() as f32 - 1.0) / 2.0;
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let dx = x as f32 - center_x;
+        let dy = y as f32 - center_y;
+        let source_x = center_x + dx * cos + dy * sin;
+        let source_y = center_y - dx * sin + dy * cos;
+        interpolate_bilinear(img, source_x, source_y).unwrap_or(fill)
+    })
+}
@@ -0,0 +1,37 @@
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma};
+use crate::detect::row_levels;
+use crate::DetectMode;
+
+/// Levels of an image under a detection mode, median filtered along rows and then columns over
+/// windows of size pixels, so that specks up to about half that size across are removed
+pub(crate) fn despeckle(img: &DynamicImage, mode: DetectMode, size: u32) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+    let (width, height) = img.dimensions();
+    let mut levels = Vec::with_capacity(width as usize * height as usize);
+    let mut row = Vec::with_capacity(width as usize);
+    let mut window = Vec::with_capacity(size as usize);
+    for y in 0..height {
+        row_levels(img, y, mode, &mut row);
+        levels.extend((0..width).map(|x| median(&row, x, size, &mut window)));
+    }
+
+    // Filter columns in place, a column at a time
+    let mut column = Vec::with_capacity(height as usize);
+    for x in 0..width as usize {
+        column.clear();
+        column.extend(levels[x..].iter().step_by(width as usize));
+        for y in 0..height {
+            levels[y as usize * width as usize + x] = median(&column, y, size, &mut window);
+        }
+    }
+    ImageBuffer::from_raw(width, height, levels).expect("levels fill the image")
+}
+
+/// Median of the window of size levels centred on position, shrinking at either end
+fn median(levels: &[u16], position: u32, size: u32, window: &mut Vec<u16>) -> u16 {
+    let start = position.saturating_sub((size - 1) / 2) as usize;
+    let end = (position as usize + size as usize / 2 + 1).min(levels.len());
+    window.clear();
+    window.extend_from_slice(&levels[start..end]);
+    let middle = window.len() / 2;
+    *window.select_nth_unstable(middle).1
+}
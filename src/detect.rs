@@ -0,0 +1,663 @@
+use std::borrow::Cow;
+use std::fmt;
+use image::{imageops, DynamicImage, GenericImage, GenericImageView, ImageBuffer, Pixel, Rgba};
+use image::imageops::FilterType;
+use crate::despeckle::despeckle;
+use crate::frame::{strip_frame, strips};
+use crate::refine::{refine, tightened};
+use crate::trim;
+use crate::{
+    auto_threshold, component_edges, exact_edges, gradient_edges, range_threshold, trim_aspect, variance_edges, Aspect, DetectMode,
+    EdgeDetection, EdgeStat, Polarity, Sides
+};
+
+/// Number of scaled median absolute deviations from the median beyond which depths are rejected
+/// as outliers by the median statistic
+const MAD_CUTOFF: f64 = 3.0;
+
+/// Ratio of the standard deviation of normally distributed values to their median absolute
+/// deviation
+const MAD_SCALE: f64 = 1.4826;
+
+/// Fraction of the shallowest and of the deepest depths discarded by the trimmed mean statistic
+const TRIM: f64 = 0.1;
+
+/// Rectangle of an image to keep after cropping
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl CropRect {
+    /// Smallest rectangle containing both rectangles
+    pub fn union(&self, other: &CropRect) -> CropRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        CropRect { x, y, width: right - x, height: bottom - y }
+    }
+
+    /// Largest rectangle contained by both rectangles, or `None` if they do not overlap
+    pub fn intersection(&self, other: &CropRect) -> Option<CropRect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (right > x && bottom > y).then(|| CropRect { x, y, width: right - x, height: bottom - y })
+    }
+}
+
+impl fmt::Display for CropRect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Detect the edges of the artwork within an image, and the rectangle to crop to beyond them.
+///
+/// Returns `None` if any side of the image contains no pixels crossing the threshold.
+pub fn detect(img: &DynamicImage, detection: &EdgeDetection) -> Option<CropRect> {
+    detect_edges(img, detection).map(|edges| {
+        let rect = crop_rect(&edges, &detection.extra, img.width(), img.height());
+        let rect = snap_aspect(&rect, &detection.snap_aspect, img.width(), img.height());
+        match detection.trim_aspect {
+            Some(aspect) => trim_aspect(img, &rect, aspect, detection.gravity),
+            None => rect
+        }
+    })
+}
+
+/// Crop rectangle removing extra margin beyond detected edges, or keeping it if negative, clamped
+/// within the image and at least one pixel in each dimension
+pub fn crop_rect(edges: &Sides<u32>, extra: &Sides<i32>, width: u32, height: u32) -> CropRect {
+    let (x, width) = span(edges.left, edges.right, extra.left, extra.right, width);
+    let (y, height) = span(edges.top, edges.bottom, extra.top, extra.bottom, height);
+    CropRect { x, y, width, height }
+}
+
+/// Adjust a crop rectangle to the nearest of the given aspect ratios, in either orientation, by
+/// trimming or extending whichever dimension changes its area least. The adjusted dimension stays
+/// centered where it fits within the image, and aspect ratios reached only by extending beyond the
+/// image are passed over. Returns the rectangle as is if no aspect ratios are given.
+pub fn snap_aspect(rect: &CropRect, aspects: &[Aspect], width: u32, height: u32) -> CropRect {
+    let ratios = aspects.iter().flat_map(|aspect| [aspect.ratio(), 1.0 / aspect.ratio()]);
+    let candidates = ratios.flat_map(|ratio| [
+        (rect.width, ((rect.width as f32 / ratio).round() as u32).max(1)),
+        (((rect.height as f32 * ratio).round() as u32).max(1), rect.height)
+    ]);
+    let area = |(w, h): (u32, u32)| w as u64 * h as u64;
+    let original = area((rect.width, rect.height));
+    let nearest = candidates
+        .filter(|&(w, h)| w <= width && h <= height)
+        .min_by_key(|&size| area(size).abs_diff(original));
+    let Some((snapped_width, snapped_height)) = nearest else {
+        return *rect;
+    };
+    // Keep the center of the crop, shifting it where needed to stay within the image
+    let center = |start: u32, length: u32, snapped: u32, size: u32| {
+        let start = start as i64 + (length as i64 - snapped as i64) / 2;
+        start.clamp(0, (size - snapped) as i64) as u32
+    };
+    CropRect {
+        x: center(rect.x, rect.width, snapped_width, width),
+        y: center(rect.y, rect.height, snapped_height, height),
+        width: snapped_width,
+        height: snapped_height
+    }
+}
+
+/// Start and length of the range kept between two edges along one dimension, collapsing to a
+/// single pixel if the extra margins cross
+fn span(start: u32, end: u32, extra_start: i32, extra_end: i32, size: u32) -> (u32, u32) {
+    let size = size as i64;
+    let start = (start as i64 + extra_start as i64).min(size - 1).max(0);
+    let end = (end as i64 - extra_end as i64).min(size).max(0);
+    (start as u32, (end - start).max(1) as u32)
+}
+
+/// Detect the position of each edge of the artwork within an image, before applying extra margin.
+///
+/// Returns `None` if any side of the image contains no pixels crossing the threshold.
+pub fn detect_edges(img: &DynamicImage, detection: &EdgeDetection) -> Option<Sides<u32>> {
+    if let Some((inverted, inner)) = inverted(img, detection) {
+        return detect_edges(&inverted, &inner);
+    }
+    if let Some(thickness) = detection.strip_frame.filter(|_| strips(detection.mode)) {
+        let outer = detect_edges(img, &EdgeDetection { strip_frame: None, ..detection.clone() })?;
+        return Some(strip_frame(img, outer, detection, thickness));
+    }
+    if let Some(tightened) = detection.refine.then(|| tightened(img, detection)).flatten() {
+        let coarse = detect_edges(img, &EdgeDetection { refine: false, ..detection.clone() })?;
+        return Some(refine(img, coarse, &tightened));
+    }
+    if let Some((masked, inner)) = masked(img, detection) {
+        return detect_edges(&masked, &inner);
+    }
+    if let Some((region, inner)) = detection_region(img.width(), img.height(), detection) {
+        let region = region?;
+        let edges = detect_edges(&img.crop_imm(region.x, region.y, region.width, region.height), &inner)?;
+        return Some(Sides {
+            left: edges.left + region.x,
+            right: edges.right + region.x,
+            top: edges.top + region.y,
+            bottom: edges.bottom + region.y
+        });
+    }
+    if let DetectMode::Exact = detection.mode {
+        return exact_edges(img);
+    }
+    let img = &*blurred(img, detection);
+    if let DetectMode::Gradient { energy } = detection.mode {
+        return gradient_edges(img, energy);
+    }
+    if let DetectMode::Variance { deviation } = detection.mode {
+        return variance_edges(img, deviation);
+    }
+    if let DetectMode::Bbox { min_area } = detection.mode {
+        return component_edges(img, detection, min_area);
+    }
+    boundary_edges(scan_boundary(img, detection), detection)
+}
+
+/// Place the edges of the artwork from the boundary of an image, as found by [`boundary`].
+///
+/// Returns `None` if any side of the boundary has no points.
+pub(crate) fn boundary_edges(boundary: Sides<Vec<(u32, u32)>>, detection: &EdgeDetection) -> Option<Sides<u32>> {
+    let depths = |points: Vec<(u32, u32)>| points.into_iter().map(|(_, depth)| depth).collect();
+
+    // Determine depth into image from sides to declare image edge, from the statistic of the depths
+    // of each row or column. Left and top edges are reversed, as depth increases with position.
+    let (stat, percentile) = (detection.stat, &detection.percentile);
+    Some(Sides {
+        left: edge(depths(boundary.left), stat, percentile.left, true)?,
+        right: edge(depths(boundary.right), stat, percentile.right, false)?,
+        top: edge(depths(boundary.top), stat, percentile.top, true)?,
+        bottom: edge(depths(boundary.bottom), stat, percentile.bottom, false)?
+    })
+}
+
+/// Find the first pixel crossing the threshold from each side of an image, along each row or
+/// column. Points are given as position along the side, and depth into the image from it.
+///
+/// The image is blurred and its levels median filtered first if requested.
+pub fn boundary(img: &DynamicImage, detection: &EdgeDetection) -> Sides<Vec<(u32, u32)>> {
+    if let Some((inverted, inner)) = inverted(img, detection) {
+        return boundary(&inverted, &inner);
+    }
+    if let Some((masked, inner)) = masked(img, detection) {
+        return boundary(&masked, &inner);
+    }
+    if let Some((region, inner)) = detection_region(img.width(), img.height(), detection) {
+        let Some(region) = region else {
+            return Sides { left: Vec::new(), right: Vec::new(), top: Vec::new(), bottom: Vec::new() };
+        };
+        let boundary = boundary(&img.crop_imm(region.x, region.y, region.width, region.height), &inner);
+        let rows = |points: Vec<(u32, u32)>| points.into_iter().map(|(y, x)| (y + region.y, x + region.x)).collect();
+        let columns = |points: Vec<(u32, u32)>| points.into_iter().map(|(x, y)| (x + region.x, y + region.y)).collect();
+        return Sides {
+            left: rows(boundary.left),
+            right: rows(boundary.right),
+            top: columns(boundary.top),
+            bottom: columns(boundary.bottom)
+        };
+    }
+    scan_boundary(&blurred(img, detection), detection)
+}
+
+/// Percentage of the rows and columns crossing threshold in an image whose crossing lies within 1%
+/// of the image of the edge placed on its side. Crossings of a clean border agree closely, while
+/// noise and torn or skewed borders spread them out.
+///
+/// Gradient, variance, exact and bbox detection place edges without finding crossings, so are
+/// always fully confident.
+pub fn edge_confidence(img: &DynamicImage, detection: &EdgeDetection, edges: &Sides<u32>) -> f32 {
+    if let DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact | DetectMode::Bbox { .. } = detection.mode {
+        return 100.0;
+    }
+    let boundary = boundary(img, detection);
+    let (width, height) = img.dimensions();
+    let sides = [
+        (&boundary.left, edges.left, width),
+        (&boundary.right, edges.right, width),
+        (&boundary.top, edges.top, height),
+        (&boundary.bottom, edges.bottom, height)
+    ];
+    let (mut near, mut total) = (0, 0);
+    for (points, edge, size) in sides {
+        let tolerance = (size / 100).max(1);
+        near += points.iter().filter(|&&(_, depth)| depth.abs_diff(edge) <= tolerance).count();
+        total += points.len();
+    }
+    match total {
+        0 => 100.0,
+        _ => 100.0 * near as f32 / total as f32
+    }
+}
+
+/// Fraction of the pixels of the border left outside a crop on each side of an image which are
+/// artwork by the threshold of that side, such as a signature near the edge left out by percentile
+/// or negative extra margin. The top and bottom of the border span the width of the image, and
+/// its sides are between them.
+///
+/// Masked pixels, and those outside the detection region or within the ignored margin, are not
+/// counted, nor are the column and row just past the crop, where detected right and bottom edges
+/// lie. Modes without a threshold of their own compare luma against the threshold.
+pub fn ink_density(img: &DynamicImage, detection: &EdgeDetection, rect: &CropRect) -> Sides<f32> {
+    if let Some((inverted, inner)) = inverted(img, detection) {
+        return ink_density(&inverted, &inner, rect);
+    }
+    if let Some((masked, inner)) = masked(img, detection) {
+        return ink_density(&masked, &inner, rect);
+    }
+    let (width, height) = img.dimensions();
+    let counted = match detection_region(img.width(), img.height(), detection) {
+        Some((Some(region), _)) => region,
+        Some((None, _)) => return Sides::all(0.0),
+        None => CropRect { x: 0, y: 0, width, height }
+    };
+    let (mode, threshold) = (detection.mode, thresholds(img, detection));
+    let end = counted.x + counted.width;
+    let left = rect.x.clamp(counted.x, end);
+    let right = (rect.x + rect.width + 1).clamp(left, end);
+    let (left, right) = ((left - counted.x) as usize, (right - counted.x) as usize);
+    let mut levels = Vec::with_capacity(width as usize);
+
+    // Count ink and all pixels of each side
+    let mut counts = Sides::all((0, 0));
+    let count = |(ink, total): &mut (usize, usize), levels: &[u16], threshold: u16| {
+        *ink += levels.iter().filter(|&&level| mode.crosses(level, threshold)).count();
+        *total += levels.len();
+    };
+    for y in counted.y..counted.y + counted.height {
+        row_levels(img, y, mode, &mut levels);
+        let levels = &levels[counted.x as usize..end as usize];
+        match y {
+            y if y < rect.y => count(&mut counts.top, levels, threshold.top),
+            y if y > rect.y + rect.height => count(&mut counts.bottom, levels, threshold.bottom),
+            _ => {
+                count(&mut counts.left, &levels[..left], threshold.left);
+                count(&mut counts.right, &levels[right..], threshold.right);
+            }
+        }
+    }
+    let density = |(ink, total): (usize, usize)| match total {
+        0 => 0.0,
+        _ => ink as f32 / total as f32
+    };
+    Sides {
+        left: density(counts.left),
+        right: density(counts.right),
+        top: density(counts.top),
+        bottom: density(counts.bottom)
+    }
+}
+
+/// Image with its colors inverted if its background is dark, so that artwork is detected by the
+/// same comparison as against a light background, along with the detection to run on it
+fn inverted<'a>(img: &'a DynamicImage, detection: &EdgeDetection) -> Option<(Cow<'a, DynamicImage>, EdgeDetection)> {
+    let thresholded = matches!(detection.mode, DetectMode::Luma | DetectMode::Channel(_) | DetectMode::Bbox { .. });
+    if detection.polarity == Polarity::Light || !thresholded {
+        return None;
+    }
+    let inner = EdgeDetection { polarity: Polarity::Light, ..detection.clone() };
+    if detection.polarity == Polarity::Auto && !is_dark(img, detection.mode) {
+        return Some((Cow::Borrowed(img), inner));
+    }
+    let mut inverted = img.clone();
+    inverted.invert();
+    Some((Cow::Owned(inverted), inner))
+}
+
+/// Check whether the corners of an image, each a sixteenth of its width and height, are darker on
+/// average than the middle level
+fn is_dark(img: &DynamicImage, mode: DetectMode) -> bool {
+    let (width, height) = img.dimensions();
+    let (size_x, size_y) = ((width / 16).max(1), (height / 16).max(1));
+    let corners = [(0, 0), (width - size_x, 0), (0, height - size_y), (width - size_x, height - size_y)];
+    let (mut sum, mut count) = (0u64, 0u64);
+    for (x, y) in corners {
+        for (_, _, pixel) in img.view(x, y, size_x, size_y).pixels() {
+            let rgba = pixel.0.map(|channel| channel as u16 * 257);
+            sum += mode.level(Rgba(rgba)) as u64;
+            count += 1;
+        }
+    }
+    sum < count * 32768
+}
+
+/// Copy of an image with the pixels under the nonzero pixels of the detection mask replaced by the
+/// background of the detection mode, along with the detection to run on the copy
+fn masked(img: &DynamicImage, detection: &EdgeDetection) -> Option<(DynamicImage, EdgeDetection)> {
+    let mask = detection.mask.as_deref()?;
+    let (width, height) = img.dimensions();
+    let stretched;
+    let mask = match mask.dimensions() == (width, height) {
+        true => mask,
+        false => {
+            stretched = imageops::resize(mask, width, height, FilterType::Nearest);
+            &stretched
+        }
+    };
+    let mut masked = img.clone();
+    match detection.mode {
+        DetectMode::Exact => trim::fill_corner(&mut masked, mask),
+        mode => {
+            let background = mode.background();
+            for (x, y, _) in mask.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[0] != 0) {
+                masked.put_pixel(x, y, background);
+            }
+        }
+    }
+    Some((masked, EdgeDetection { mask: None, ..detection.clone() }))
+}
+
+/// Part of an image of the given dimensions to detect edges within, if the detection restricts it
+/// to a region or ignores a margin, along with the detection to run on that part alone. The part is
+/// `None` if nothing of the image is left to detect within.
+pub(crate) fn detection_region(
+    width: u32,
+    height: u32,
+    detection: &EdgeDetection
+) -> Option<(Option<CropRect>, EdgeDetection)> {
+    if detection.region.is_none() && detection.ignore_margin == 0 {
+        return None;
+    }
+    let region = detection.region.unwrap_or(CropRect { x: 0, y: 0, width, height });
+    let (x, y) = (region.x.min(width), region.y.min(height));
+    let margin = detection.ignore_margin;
+    let region = CropRect {
+        x: x.saturating_add(margin),
+        y: y.saturating_add(margin),
+        width: region.width.min(width - x).saturating_sub(margin.saturating_mul(2)),
+        height: region.height.min(height - y).saturating_sub(margin.saturating_mul(2))
+    };
+    let inner = EdgeDetection { region: None, ignore_margin: 0, ..detection.clone() };
+    Some((Some(region).filter(|region| region.width > 0 && region.height > 0), inner))
+}
+
+/// Copy of an image blurred for detection if requested, leaving the image itself sharp
+fn blurred<'a>(img: &'a DynamicImage, detection: &EdgeDetection) -> Cow<'a, DynamicImage> {
+    match detection.blur {
+        Some(sigma) => Cow::Owned(img.blur(sigma)),
+        None => Cow::Borrowed(img)
+    }
+}
+
+/// Find the boundary of an image already blurred for detection
+fn scan_boundary(img: &DynamicImage, detection: &EdgeDetection) -> Sides<Vec<(u32, u32)>> {
+    let mode = detection.mode;
+    let threshold = &thresholds(img, detection);
+    if let Some(size) = detection.despeckle {
+        let levels = despeckle(img, mode, size);
+        let content = |level, threshold| mode.crosses(level, threshold);
+        return scan(&levels, |pixel| pixel.0[0], content, threshold, detection.sample_stride);
+    }
+
+    // Scan raw buffers in the image's own pixel type and with a constant mode where possible, so
+    // that neither is dispatched on per pixel
+    macro_rules! scan {
+        ($buf:expr, $widen:expr) => {
+            match mode {
+                DetectMode::Luma | DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact
+                | DetectMode::Bbox { .. } => scan!($buf, $widen, DetectMode::Luma),
+                DetectMode::Alpha => scan!($buf, $widen, DetectMode::Alpha),
+                DetectMode::Background { .. } | DetectMode::Channel(_) => scan!($buf, $widen, mode)
+            }
+        };
+        ($buf:expr, $widen:expr, $mode:expr) => {
+            scan(
+                $buf,
+                |pixel| $mode.level($widen(pixel.to_rgba())),
+                |level, threshold| $mode.crosses(level, threshold),
+                threshold,
+                detection.sample_stride
+            )
+        };
+    }
+    match img {
+        DynamicImage::ImageLuma8(buf) => scan!(buf, widen),
+        DynamicImage::ImageLumaA8(buf) => scan!(buf, widen),
+        DynamicImage::ImageRgb8(buf) => scan!(buf, widen),
+        DynamicImage::ImageRgba8(buf) => scan!(buf, widen),
+        DynamicImage::ImageLuma16(buf) => scan!(buf, std::convert::identity),
+        DynamicImage::ImageLumaA16(buf) => scan!(buf, std::convert::identity),
+        DynamicImage::ImageRgb16(buf) => scan!(buf, std::convert::identity),
+        DynamicImage::ImageRgba16(buf) => scan!(buf, std::convert::identity),
+        DynamicImage::ImageRgb32F(buf) => scan!(buf, float),
+        DynamicImage::ImageRgba32F(buf) => scan!(buf, float),
+        _ => scan!(&img.to_rgba8(), widen)
+    }
+}
+
+/// Thresholds of each side of an image, computed from its range of levels or its border if
+/// requested
+pub(crate) fn thresholds(img: &DynamicImage, detection: &EdgeDetection) -> Sides<u16> {
+    let computed = match (detection.range_threshold, detection.auto_threshold) {
+        (Some(percent), _) => range_threshold(img, detection.mode, percent),
+        (None, true) => auto_threshold(img, detection.mode),
+        (None, false) => None
+    };
+    computed.map(Sides::all).unwrap_or(detection.threshold)
+}
+
+/// Find the first pixel crossing the threshold from each side of an image buffer, with pixels
+/// converted to levels by level, and levels compared against thresholds by content. Rows and
+/// columns are sampled every stride, as by [`sampled`].
+fn scan<P: Pixel>(
+    buf: &ImageBuffer<P, Vec<P::Subpixel>>,
+    level: impl Fn(&P) -> u16,
+    content: impl Fn(u16, u16) -> bool,
+    sides: &Sides<u16>,
+    stride: u32
+) -> Sides<Vec<(u32, u32)>> {
+    let (width, height) = buf.dimensions();
+
+    // Check left and right edges of image along each row
+    let left = sampled(height, stride, |ys| ys.into_iter()
+        .filter_map(|y| Some((y, row(buf, y).position(|pixel| content(level(pixel), sides.left))? as u32)))
+        .collect());
+    let right = sampled(height, stride, |ys| ys.into_iter()
+        .filter_map(|y| Some((y, row(buf, y).rposition(|pixel| content(level(pixel), sides.right))? as u32)))
+        .collect());
+    let mut spans = vec![(None, None); height as usize];
+    for &(y, x) in &left {
+        spans[y as usize].0 = Some(x);
+    }
+    for &(y, x) in &right {
+        spans[y as usize].1 = Some(x);
+    }
+
+    // Check top and bottom edges of image a row at a time, visiting only columns not yet crossed.
+    // With the same threshold as the left and right edges, nothing outside a row's span crosses,
+    // unless rows were sampled, leaving the spans of rows between samples unmeasured.
+    let columns = |mut pending: Vec<u32>, ys: &mut dyn Iterator<Item = u32>, threshold| {
+        let within_spans = stride <= 1 && threshold == sides.left && threshold == sides.right;
+        let mut points = Vec::new();
+        for y in ys {
+            if pending.is_empty() {
+                break;
+            }
+            let (first, last) = match within_spans {
+                true => match spans[y as usize] {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => continue
+                },
+                false => (0, width - 1)
+            };
+            let start = pending.partition_point(|&x| x < first);
+            let end = pending.partition_point(|&x| x <= last);
+            let mut kept = start;
+            for i in start..end {
+                let x = pending[i];
+                match content(level(buf.get_pixel(x, y)), threshold) {
+                    true => points.push((x, y)),
+                    false => {
+                        pending[kept] = x;
+                        kept += 1;
+                    }
+                }
+            }
+            pending.drain(kept..end);
+        }
+        points.sort_unstable();
+        points
+    };
+    let top = sampled(width, stride, |xs| columns(xs, &mut (0..height), sides.top));
+    let bottom = sampled(width, stride, |xs| columns(xs, &mut (0..height).rev(), sides.bottom));
+
+    Sides { left, right, top, bottom }
+}
+
+/// Points crossing threshold along a side of the given length, as position along the side and
+/// depth into the image, found by measure among the ascending positions it is given.
+///
+/// Positions are measured every stride and at the end of the side. Positions between two samples
+/// are measured too where the samples disagree by more than stride, or only one of them crosses,
+/// as where artwork begins along the side, and are otherwise interpolated between the samples.
+fn sampled(len: u32, stride: u32, mut measure: impl FnMut(Vec<u32>) -> Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    if stride <= 1 || len == 0 {
+        return measure((0..len).collect());
+    }
+    let mut samples: Vec<u32> = (0..len).step_by(stride as usize).collect();
+    if samples.last() != Some(&(len - 1)) {
+        samples.push(len - 1);
+    }
+    let mut depths = vec![None; len as usize];
+    for (at, depth) in measure(samples.clone()) {
+        depths[at as usize] = Some(depth);
+    }
+    let mut measured = Vec::new();
+    for pair in samples.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        match (depths[a as usize], depths[b as usize]) {
+            (Some(first), Some(last)) if first.abs_diff(last) <= stride => {
+                for at in a + 1..b {
+                    let t = (at - a) as f64 / (b - a) as f64;
+                    depths[at as usize] = Some((first as f64 + (last as f64 - first as f64) * t).round() as u32);
+                }
+            },
+            (None, None) => {},
+            _ => measured.extend(a + 1..b)
+        }
+    }
+    for (at, depth) in measure(measured) {
+        depths[at as usize] = Some(depth);
+    }
+    (0..len).zip(depths).filter_map(|(at, depth)| Some((at, depth?))).collect()
+}
+
+/// Fill levels with those of one row of an image under a detection mode, dispatching on the
+/// image's pixel type once per row rather than once per pixel
+pub(crate) fn row_levels(img: &DynamicImage, y: u32, mode: DetectMode, levels: &mut Vec<u16>) {
+    levels.clear();
+    macro_rules! extend {
+        ($buf:expr, $widen:expr) => {
+            levels.extend(row($buf, y).map(|pixel| mode.level($widen(pixel.to_rgba()))))
+        };
+    }
+    match img {
+        DynamicImage::ImageLuma8(buf) => extend!(buf, widen),
+        DynamicImage::ImageLumaA8(buf) => extend!(buf, widen),
+        DynamicImage::ImageRgb8(buf) => extend!(buf, widen),
+        DynamicImage::ImageRgba8(buf) => extend!(buf, widen),
+        DynamicImage::ImageLuma16(buf) => extend!(buf, std::convert::identity),
+        DynamicImage::ImageLumaA16(buf) => extend!(buf, std::convert::identity),
+        DynamicImage::ImageRgb16(buf) => extend!(buf, std::convert::identity),
+        DynamicImage::ImageRgba16(buf) => extend!(buf, std::convert::identity),
+        DynamicImage::ImageRgb32F(buf) => extend!(buf, float),
+        DynamicImage::ImageRgba32F(buf) => extend!(buf, float),
+        _ => levels.extend((0..img.width()).map(|x| mode.level(pixel(img, x, y))))
+    }
+}
+
+/// Pixels of one row of an image buffer, read directly from its raw samples
+fn row<P: Pixel>(
+    buf: &ImageBuffer<P, Vec<P::Subpixel>>,
+    y: u32
+) -> impl DoubleEndedIterator<Item = &P> + ExactSizeIterator {
+    let channels = P::CHANNEL_COUNT as usize;
+    let stride = buf.width() as usize * channels;
+    buf.as_raw()[y as usize * stride..][..stride].chunks_exact(channels).map(P::from_slice)
+}
+
+/// Read a pixel on the 16-bit scale, without quantizing high bit depth images to 8 bits.
+///
+/// Floating point channels are clamped to the displayable range.
+fn pixel(img: &DynamicImage, x: u32, y: u32) -> Rgba<u16> {
+    match img {
+        DynamicImage::ImageLuma16(buf) => buf.get_pixel(x, y).to_rgba(),
+        DynamicImage::ImageLumaA16(buf) => buf.get_pixel(x, y).to_rgba(),
+        DynamicImage::ImageRgb16(buf) => buf.get_pixel(x, y).to_rgba(),
+        DynamicImage::ImageRgba16(buf) => *buf.get_pixel(x, y),
+        DynamicImage::ImageRgb32F(buf) => float(buf.get_pixel(x, y).to_rgba()),
+        DynamicImage::ImageRgba32F(buf) => float(*buf.get_pixel(x, y)),
+        _ => widen(img.get_pixel(x, y))
+    }
+}
+
+/// Widen an 8-bit pixel to the 16-bit scale
+fn widen(pixel: Rgba<u8>) -> Rgba<u16> {
+    Rgba(pixel.0.map(|c| c as u16 * 257))
+}
+
+/// Convert a floating point pixel to the 16-bit scale, clamping to the displayable range
+fn float(pixel: Rgba<f32>) -> Rgba<u16> {
+    Rgba(pixel.0.map(|c| (c.clamp(0.0, 1.0) * 65535.0).round() as u16))
+}
+
+/// Select the position of an edge from the depths at which rows or columns cross the threshold,
+/// measured inward from the side, with positions increasing inward if reverse. The edge is placed
+/// at the depth given by the statistic, rounded to the nearest pixel and keeping the shallower
+/// pixel when halfway between two.
+fn edge(positions: Vec<u32>, stat: EdgeStat, percentile: u8, reverse: bool) -> Option<u32> {
+    if positions.is_empty() {
+        return None;
+    }
+    // Order depths from the shallowest to the deepest
+    let mut depths: Vec<f64> = positions.into_iter()
+        .map(|position| match reverse {
+            true => position as f64,
+            false => -(position as f64)
+        })
+        .collect();
+    depths.sort_unstable_by(f64::total_cmp);
+    let depth = match stat {
+        EdgeStat::Percentile => percentile_of(&depths, percentile as f64),
+        EdgeStat::Median => {
+            // Reject depths far from the median, measured in median absolute deviations scaled
+            // to match standard deviations of normally distributed depths
+            let median = percentile_of(&depths, 50.0);
+            let mut deviations: Vec<f64> = depths.iter().map(|depth| (depth - median).abs()).collect();
+            deviations.sort_unstable_by(f64::total_cmp);
+            let cutoff = MAD_CUTOFF * MAD_SCALE * percentile_of(&deviations, 50.0);
+            let inliers: Vec<f64> = depths.iter().copied().filter(|depth| (depth - median).abs() <= cutoff).collect();
+            match inliers.is_empty() {
+                true => median,
+                false => percentile_of(&inliers, 50.0)
+            }
+        },
+        EdgeStat::TrimmedMean => {
+            let trim = (depths.len() as f64 * TRIM) as usize;
+            let kept = &depths[trim..depths.len() - trim];
+            kept.iter().sum::<f64>() / kept.len() as f64
+        }
+    };
+    let depth = (depth - 0.5).ceil();
+    Some(match reverse {
+        true => depth,
+        false => -depth
+    } as u32)
+}
+
+/// Percentile (0-100) of sorted values, interpolated linearly between the values at the closest
+/// ranks
+fn percentile_of(sorted: &[f64], percentile: f64) -> f64 {
+    let rank = percentile / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (rank - lower as f64) * (sorted[upper] - sorted[lower])
+}
@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use cpar::{CropParams, CropRect};
+use image::ImageFormat;
+use crate::save::{self, OutputFormat};
+use crate::Cpar;
+
+/// Centimetres in an inch
+const CM_PER_INCH: f32 = 2.54;
+
+/// TIFF tags recording resolution, and its unit
+const X_RESOLUTION: u16 = 282;
+const Y_RESOLUTION: u16 = 283;
+const RESOLUTION_UNIT: u16 = 296;
+
+/// Print size of output images, in inches
+#[derive(Clone, Copy)]
+pub struct PhysicalSize {
+    pub width: f32,
+    pub height: f32
+}
+
+impl FromStr for PhysicalSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Lengths are in inches unless another unit is given
+        let units = [("in", 1.0), ("cm", 1.0 / CM_PER_INCH), ("mm", 0.1 / CM_PER_INCH)];
+        let (size, inches) = units.iter()
+            .find_map(|&(unit, inches)| Some((s.strip_suffix(unit)?, inches)))
+            .unwrap_or((s, 1.0));
+        let length = |length: &str| {
+            length.parse::<f32>().ok().filter(|length| *length > 0.0 && length.is_finite()).map(|length| length * inches)
+        };
+        size.split_once('x')
+            .and_then(|(width, height)| Some(PhysicalSize { width: length(width)?, height: length(height)? }))
+            .ok_or_else(|| format!("invalid physical size '{}', expected WxH in in, cm or mm such as 6x4in", s))
+    }
+}
+
+/// Read the horizontal resolution in dots per inch recorded by an image file, from the pHYs chunk
+/// of a PNG, the JFIF or EXIF header of a JPEG, or the first directory of a TIFF
+pub fn read(path: &Path) -> Option<f32> {
+    read_from(&mut BufReader::new(File::open(path).ok()?))
+}
+
+/// Read the resolution recorded by encoded image data, as [`read`] does
+pub fn read_from<R: Read + Seek>(reader: &mut R) -> Option<f32> {
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature).ok()?;
+    match signature {
+        [0x89, b'P', b'N', b'G', ..] => png_dpi(reader),
+        [0xFF, 0xD8, ..] => jpeg_dpi(reader),
+        [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => tiff_dpi(reader),
+        _ => None
+    }
+    .filter(|dpi| *dpi > 0.0 && dpi.is_finite())
+}
+
+/// Find the pHYs chunk among those before the image data, after the signature
+fn png_dpi<R: Read + Seek>(reader: &mut R) -> Option<f32> {
+    loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header).ok()?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        match &header[4..] {
+            b"pHYs" => {
+                let mut physical = [0; 9];
+                reader.read_exact(&mut physical).ok()?;
+                // Only pixels per metre are a physical unit
+                let x = u32::from_be_bytes([physical[0], physical[1], physical[2], physical[3]]);
+                return (physical[8] == 1).then(|| x as f32 * CM_PER_INCH / 100.0);
+            },
+            b"IDAT" => return None,
+            _ => reader.seek(SeekFrom::Current(length as i64 + 4)).ok()?
+        };
+    }
+}
+
+/// Find the density of a JFIF header, otherwise the resolution of an EXIF header, among the
+/// segments before the image data
+fn jpeg_dpi<R: Read + Seek>(reader: &mut R) -> Option<f32> {
+    reader.seek(SeekFrom::Start(2)).ok()?;
+    let mut exif = None;
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).ok()?;
+        let [0xFF, marker, high, low] = header else {
+            return exif;
+        };
+        let length = u16::from_be_bytes([high, low]).saturating_sub(2) as usize;
+        match marker {
+            // Start of a frame or scan
+            0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return exif,
+            0xE0 | 0xE1 => {
+                let mut segment = vec![0; length];
+                reader.read_exact(&mut segment).ok()?;
+                if let Some(jfif) = segment.strip_prefix(b"JFIF\0").filter(|jfif| jfif.len() >= 5) {
+                    let x = u16::from_be_bytes([jfif[3], jfif[4]]) as f32;
+                    match jfif[2] {
+                        1 => return Some(x),
+                        2 => return Some(x * CM_PER_INCH),
+                        _ => {}
+                    }
+                } else if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                    exif = exif.or_else(|| tiff_dpi(&mut Cursor::new(tiff)));
+                }
+            },
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64)).ok()?;
+            }
+        }
+    }
+}
+
+/// Find the resolution and its unit in the first directory of a TIFF, or of an EXIF header, read
+/// from its start
+fn tiff_dpi<R: Read + Seek>(reader: &mut R) -> Option<f32> {
+    let mut read = |at: u64, length: usize| -> Option<Vec<u8>> {
+        let mut bytes = vec![0; length];
+        reader.seek(SeekFrom::Start(at)).ok()?;
+        reader.read_exact(&mut bytes).ok()?;
+        Some(bytes)
+    };
+    let header = read(0, 8)?;
+    let order = Order::of(&header)?;
+    let directory = order.u32(&header[4..]) as u64;
+    let count = order.u16(&read(directory, 2)?) as usize;
+    let entries = read(directory + 2, count * 12)?;
+    let (mut resolution, mut unit) = (None, 2);
+    for entry in entries.chunks_exact(12) {
+        match order.u16(entry) {
+            X_RESOLUTION => {
+                let rational = read(order.u32(&entry[8..]) as u64, 8)?;
+                resolution = Some(order.u32(&rational) as f32 / order.u32(&rational[4..]) as f32);
+            },
+            RESOLUTION_UNIT => unit = order.u16(&entry[8..]),
+            _ => {}
+        }
+    }
+    match unit {
+        2 => resolution,
+        3 => resolution.map(|resolution| resolution * CM_PER_INCH),
+        _ => None
+    }
+}
+
+/// Byte order of a TIFF
+#[derive(Clone, Copy)]
+enum Order {
+    Little,
+    Big
+}
+
+impl Order {
+    fn of(header: &[u8]) -> Option<Self> {
+        match header.get(..2)? {
+            b"II" => Some(Order::Little),
+            b"MM" => Some(Order::Big),
+            _ => None
+        }
+    }
+
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            Order::Little => u16::from_le_bytes(bytes),
+            Order::Big => u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            Order::Little => u32::from_le_bytes(bytes),
+            Order::Big => u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn put_u16(self, bytes: &mut [u8], value: u16) {
+        bytes[..2].copy_from_slice(&match self {
+            Order::Little => value.to_le_bytes(),
+            Order::Big => value.to_be_bytes()
+        });
+    }
+
+    fn put_u32(self, bytes: &mut [u8], value: u32) {
+        bytes[..4].copy_from_slice(&match self {
+            Order::Little => value.to_le_bytes(),
+            Order::Big => value.to_be_bytes()
+        });
+    }
+}
+
+/// Parameters scaling an image to the requested print size at the target DPI, or from the DPI of
+/// its source to the target DPI so that its print size is kept
+pub fn scaled_params<'a>(args: &Cpar, params: &'a CropParams, source: Option<f32>) -> Cow<'a, CropParams> {
+    match (args.target_dpi, args.physical_size, source) {
+        (Some(dpi), Some(size), _) => Cow::Owned(CropParams {
+            target_width: Some(((size.width * dpi as f32).round() as u32).max(1)),
+            target_height: Some(((size.height * dpi as f32).round() as u32).max(1)),
+            ..params.clone()
+        }),
+        (Some(dpi), None, Some(source)) => Cow::Owned(CropParams { downscale: source / dpi as f32, ..params.clone() }),
+        _ => Cow::Borrowed(params)
+    }
+}
+
+/// Resolution to record in an output image of the given dimensions: the target DPI, or otherwise
+/// that printing it at the requested physical size, or the DPI of its source scaled as the image
+/// was
+pub fn output_dpi(
+    args: &Cpar,
+    source: Option<f32>,
+    (img_width, img_height): (u32, u32),
+    rect: &CropRect,
+    (width, height): (u32, u32),
+    params: &CropParams
+) -> Option<f32> {
+    match (args.target_dpi, args.physical_size) {
+        (Some(dpi), _) => Some(dpi as f32),
+        (None, Some(size)) => Some((width as f32 / size.width).max(height as f32 / size.height)),
+        (None, None) => source.map(|dpi| dpi / cpar::output_scale(img_width, img_height, rect, params))
+    }
+}
+
+/// Check whether a resolution can be recorded in images of a format
+pub fn embeds(format: OutputFormat) -> bool {
+    matches!(format, OutputFormat::Image(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Tiff))
+}
+
+/// Record a resolution in an image encoded in a format it [`embeds`] in, leaving other formats as
+/// they are
+pub fn embed(data: Vec<u8>, format: OutputFormat, dpi: f32) -> Vec<u8> {
+    match format {
+        OutputFormat::Image(ImageFormat::Jpeg) => embed_jpeg(data, dpi),
+        OutputFormat::Image(ImageFormat::Png) => embed_png(data, dpi),
+        OutputFormat::Image(ImageFormat::Tiff) => embed_tiff(data, dpi),
+        _ => data
+    }
+}
+
+/// Set the density of the JFIF header following the start of image marker, inserting one if the
+/// encoder left it out
+fn embed_jpeg(mut data: Vec<u8>, dpi: f32) -> Vec<u8> {
+    let density = (dpi.round() as u16).max(1).to_be_bytes();
+    if data.get(2..4) == Some(&[0xFF, 0xE0]) && data.get(6..11) == Some(b"JFIF\0") {
+        data[13] = 1;
+        data[14..16].copy_from_slice(&density);
+        data[16..18].copy_from_slice(&density);
+        return data;
+    }
+    let mut segment = vec![0xFF, 0xE0, 0, 16];
+    segment.extend(b"JFIF\0");
+    segment.extend([1, 2, 1]);
+    segment.extend(density);
+    segment.extend(density);
+    segment.extend([0, 0]);
+    save::splice(data, 2, &segment)
+}
+
+/// Insert a pHYs chunk in pixels per metre after the IHDR chunk
+fn embed_png(data: Vec<u8>, dpi: f32) -> Vec<u8> {
+    let density = ((dpi * 100.0 / CM_PER_INCH).round() as u32).to_be_bytes();
+    let mut physical = density.to_vec();
+    physical.extend(density);
+    physical.push(1);
+    save::splice(data, save::PNG_HEADER_END, &save::png_chunk(b"pHYs", &physical))
+}
+
+/// Overwrite the resolution and its unit in the first directory of a TIFF, which the encoder
+/// writes as a placeholder
+fn embed_tiff(mut data: Vec<u8>, dpi: f32) -> Vec<u8> {
+    let Some(order) = Order::of(&data) else {
+        return data;
+    };
+    let directory = order.u32(&data[4..]) as usize;
+    let count = order.u16(&data[directory..]) as usize;
+    for n in 0..count {
+        let entry = directory + 2 + n * 12;
+        match order.u16(&data[entry..]) {
+            X_RESOLUTION | Y_RESOLUTION => {
+                // Resolution is kept to hundredths of a dot per inch
+                let value = order.u32(&data[entry + 8..]) as usize;
+                order.put_u32(&mut data[value..], (dpi * 100.0).round() as u32);
+                order.put_u32(&mut data[value + 4..], 100);
+            },
+            RESOLUTION_UNIT => order.put_u16(&mut data[entry + 8..], 2),
+            _ => {}
+        }
+    }
+    data
+}
@@ -0,0 +1,14 @@
+use thiserror::Error;
+
+/// Errors which may occur while processing an image
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Image(#[from] image::ImageError),
+    #[error("failed to detect sides of image")]
+    NoEdges
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
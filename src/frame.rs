@@ -0,0 +1,77 @@
+use image::DynamicImage;
+use crate::detect::{detect_edges, row_levels, thresholds};
+use crate::{CropRect, DetectMode, EdgeDetection, Sides};
+
+/// Fraction of the pixels along a line at an edge which must cross the threshold for the line to
+/// be part of a frame
+const FRAME_COVERAGE: f32 = 0.9;
+
+/// Check whether frames can be stripped under a detection mode, which must compare against a
+/// threshold
+pub(crate) fn strips(mode: DetectMode) -> bool {
+    !matches!(mode, DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact)
+}
+
+/// Move edges inward past a frame drawn around the artwork, such as the thin black line inside the
+/// margin of a scan, then detect the artwork again within it so that the margin inside the frame
+/// is cropped too.
+///
+/// A frame is found where, on every side, a run of at most `thickness` lines from the edge has
+/// nearly every pixel crossing the threshold and the line after it does not. Edges are kept where
+/// there is no such frame, or nothing within it.
+pub(crate) fn strip_frame(img: &DynamicImage, edges: Sides<u32>, detection: &EdgeDetection, thickness: u32) -> Sides<u32> {
+    let (span_x, span_y) = (edges.right.saturating_sub(edges.left) + 1, edges.bottom.saturating_sub(edges.top) + 1);
+    // Lines counted from each edge inward: the frame, and the one after it
+    let depth_x = (thickness + 1).min(span_x);
+    let depth_y = (thickness + 1).min(span_y);
+    let (mode, threshold) = (detection.mode, thresholds(img, detection));
+    let mut levels = Vec::with_capacity(img.width() as usize);
+
+    // Count pixels crossing threshold along each of the outermost lines of every side
+    let mut counts = Sides {
+        left: vec![0; depth_x as usize],
+        right: vec![0; depth_x as usize],
+        top: vec![0; depth_y as usize],
+        bottom: vec![0; depth_y as usize]
+    };
+    for y in edges.top..=edges.bottom {
+        row_levels(img, y, mode, &mut levels);
+        for n in 0..depth_x {
+            counts.left[n as usize] += mode.crosses(levels[(edges.left + n) as usize], threshold.left) as u32;
+            counts.right[n as usize] += mode.crosses(levels[(edges.right - n) as usize], threshold.right) as u32;
+        }
+        let row = &levels[edges.left as usize..=edges.right as usize];
+        let crossing = |threshold: u16| row.iter().filter(|&&level| mode.crosses(level, threshold)).count() as u32;
+        if y - edges.top < depth_y {
+            counts.top[(y - edges.top) as usize] = crossing(threshold.top);
+        }
+        if edges.bottom - y < depth_y {
+            counts.bottom[(edges.bottom - y) as usize] = crossing(threshold.bottom);
+        }
+    }
+
+    // Frames span at most the thickness, so that solid artwork running past it is kept
+    let frame = |counts: &[u32], length: u32| {
+        let lines = counts.iter().take_while(|&&count| count as f32 >= FRAME_COVERAGE * length as f32).count() as u32;
+        (lines > 0 && lines <= thickness && lines < counts.len() as u32).then_some(lines)
+    };
+    let (Some(left), Some(right), Some(top), Some(bottom)) = (
+        frame(&counts.left, span_y),
+        frame(&counts.right, span_y),
+        frame(&counts.top, span_x),
+        frame(&counts.bottom, span_x)
+    ) else {
+        return edges;
+    };
+    if left + right >= span_x || top + bottom >= span_y {
+        return edges;
+    }
+    let inside = CropRect {
+        x: edges.left + left,
+        y: edges.top + top,
+        width: span_x - left - right,
+        height: span_y - top - bottom
+    };
+    let inner = EdgeDetection { strip_frame: None, region: Some(inside), ignore_margin: 0, ..detection.clone() };
+    detect_edges(img, &inner).unwrap_or(edges)
+}
@@ -0,0 +1,214 @@
+use std::f32::consts::PI;
+use std::sync::{mpsc, OnceLock};
+use image::{DynamicImage, Rgba32FImage};
+use image::imageops::FilterType;
+use wgpu::util::DeviceExt;
+use crate::convert;
+
+/// Device and pipeline for resampling images on the GPU
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    limits: wgpu::Limits
+}
+
+impl Gpu {
+    /// Connect to a hardware adapter and compile the resampling pipeline
+    async fn connect() -> Option<Gpu> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+
+        // Software rasterizers are slower than resampling on the CPU directly
+        if adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            return None;
+        }
+        let limits = adapter.limits();
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("cpar"),
+                required_limits: limits.clone(),
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+        let module = device.create_shader_module(wgpu::include_wgsl!("resample.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("resample"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None
+        });
+        Some(Gpu { device, queue, pipeline, limits })
+    }
+}
+
+static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+
+/// Connect to the GPU on first use, returning `None` if no hardware adapter is available
+fn gpu() -> Option<&'static Gpu> {
+    GPU.get_or_init(|| pollster::block_on(Gpu::connect())).as_ref()
+}
+
+/// Check whether a hardware GPU adapter is available
+pub fn available() -> bool {
+    gpu().is_some()
+}
+
+/// Blur an image with the same Gaussian kernel as `DynamicImage::blur`.
+///
+/// Returns `None` if no GPU is available or the image exceeds its buffer limits.
+pub fn blur(img: &DynamicImage, sigma: f32) -> Option<DynamicImage> {
+    let sigma = if sigma <= 0.0 { 1.0 } else { sigma };
+    let kernel = |x: f32| ((2.0 * PI).sqrt() * sigma).recip() * (-x.powi(2) / (2.0 * sigma.powi(2))).exp();
+    resample(img, img.width(), img.height(), &kernel, 2.0 * sigma)
+}
+
+/// Resize an image with the same filter kernels as `DynamicImage::resize_exact`.
+///
+/// Returns `None` if no GPU is available or the image exceeds its buffer limits.
+pub fn resize(img: &DynamicImage, width: u32, height: u32, filter: FilterType) -> Option<DynamicImage> {
+    let sinc = |x: f32| if x == 0.0 { 1.0 } else { (x * PI).sin() / (x * PI) };
+    let cubic = |x: f32| {
+        // Catmull-Rom spline, with B = 0 and C = 0.5
+        let a = x.abs();
+        match a {
+            a if a < 1.0 => (9.0 * a.powi(3) - 15.0 * a.powi(2) + 6.0) / 6.0,
+            a if a < 2.0 => (-3.0 * a.powi(3) + 15.0 * a.powi(2) - 24.0 * a + 12.0) / 6.0,
+            _ => 0.0
+        }
+    };
+    let (kernel, support): (&dyn Fn(f32) -> f32, f32) = match filter {
+        FilterType::Nearest => (&|_| 1.0, 0.0),
+        FilterType::Triangle => (&|x: f32| (1.0 - x.abs()).max(0.0), 1.0),
+        FilterType::CatmullRom => (&cubic, 2.0),
+        FilterType::Gaussian => (&|x: f32| (2.0 * PI).sqrt().recip() * 2.0 * (-2.0 * x.powi(2)).exp(), 3.0),
+        FilterType::Lanczos3 => (&|x: f32| if x.abs() < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 }, 3.0)
+    };
+    resample(img, width, height, kernel, support)
+}
+
+/// Input pixels contributing to each output pixel along one axis, as the first input pixel and a
+/// fixed number of normalized weights, padded with zeros
+struct Taps {
+    starts: Vec<u32>,
+    weights: Vec<f32>,
+    count: u32
+}
+
+impl Taps {
+    /// Compute taps as `image` does when sampling, widening the kernel when downscaling
+    fn new(size: u32, new_size: u32, kernel: &dyn Fn(f32) -> f32, support: f32) -> Self {
+        let ratio = size as f32 / new_size as f32;
+        let scale = ratio.max(1.0);
+        let runs: Vec<(u32, Vec<f32>)> = (0..new_size)
+            .map(|out| {
+                let center = (out as f32 + 0.5) * ratio;
+                let left = ((center - support * scale).floor() as i64).clamp(0, size as i64 - 1);
+                let right = ((center + support * scale).ceil() as i64).clamp(left + 1, size as i64);
+                let weights: Vec<f32> = (left..right).map(|i| kernel((i as f32 - (center - 0.5)) / scale)).collect();
+                let sum: f32 = weights.iter().sum();
+                (left as u32, weights.into_iter().map(|weight| weight / sum).collect())
+            })
+            .collect();
+        let count = runs.iter().map(|(_, weights)| weights.len()).max().unwrap_or(1) as u32;
+        let mut taps = Taps { starts: Vec::new(), weights: Vec::new(), count };
+        for (start, weights) in runs {
+            taps.starts.push(start);
+            taps.weights.extend(weights.iter().copied().chain(std::iter::repeat(0.0)).take(count as usize));
+        }
+        taps
+    }
+}
+
+/// Resample an image vertically then horizontally on the GPU, keeping its color type
+fn resample(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    kernel: &dyn Fn(f32) -> f32,
+    support: f32
+) -> Option<DynamicImage> {
+    let gpu = gpu()?;
+    let pixels = img.to_rgba32f();
+    let vertical = Taps::new(img.height(), height, kernel, support);
+    let horizontal = Taps::new(img.width(), width, kernel, support);
+
+    // Keep every buffer within the device's limits, leaving larger images to the CPU
+    let size = |width: u32, height: u32| width as u64 * height as u64 * 16;
+    let largest = size(img.width(), img.height()).max(size(img.width(), height)).max(size(width, height));
+    if largest > gpu.limits.max_storage_buffer_binding_size || largest > gpu.limits.max_buffer_size {
+        return None;
+    }
+
+    let storage = |contents: &[u8]| gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents,
+        usage: wgpu::BufferUsages::STORAGE
+    });
+    let output = |size: u64, usage| gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage,
+        mapped_at_creation: false
+    });
+    let src = storage(bytemuck::cast_slice(pixels.as_raw()));
+    let tmp = output(size(img.width(), height), wgpu::BufferUsages::STORAGE);
+    let dst = output(size(width, height), wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+    let staging = output(size(width, height), wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+
+    // Sample each axis in turn, vertically into the intermediate buffer then horizontally out of it
+    let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    let passes = [
+        (&src, &tmp, &vertical, [img.width(), img.height(), img.width(), height]),
+        (&tmp, &dst, &horizontal, [img.width(), height, width, height])
+    ];
+    for (n, (input, output, taps, [src_width, src_height, dst_width, dst_height])) in passes.into_iter().enumerate() {
+        let params = [src_width, src_height, dst_width, dst_height, taps.count, (n == 0) as u32, 0, 0];
+        let params = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::UNIFORM
+        });
+        let starts = storage(bytemuck::cast_slice(&taps.starts));
+        let weights = storage(bytemuck::cast_slice(&taps.weights));
+        let bindings = [&params, input, output, &starts, &weights];
+        let entries: Vec<_> = bindings.iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding()
+            })
+            .collect();
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &gpu.pipeline.get_bind_group_layout(0),
+            entries: &entries
+        });
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        pass.set_pipeline(&gpu.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(dst_width.div_ceil(8), dst_height.div_ceil(8), 1);
+    }
+    encoder.copy_buffer_to_buffer(&dst, 0, &staging, 0, size(width, height));
+    gpu.queue.submit([encoder.finish()]);
+
+    // Read back the result
+    let (sender, receiver) = mpsc::channel();
+    staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    gpu.device.poll(wgpu::PollType::wait_indefinitely()).ok()?;
+    receiver.recv().ok()?.ok()?;
+    let samples = bytemuck::cast_slice(&staging.slice(..).get_mapped_range().ok()?).to_vec();
+    let resampled = DynamicImage::ImageRgba32F(Rgba32FImage::from_raw(width, height, samples)?);
+    Some(convert(resampled, img.color()))
+}
@@ -0,0 +1,57 @@
+use image::{DynamicImage, GenericImageView};
+use crate::detect::row_levels;
+use crate::{DetectMode, Sides};
+
+/// Detect the position of each edge of the artwork within an image as the outermost rows and
+/// columns whose mean Sobel gradient magnitude, in 8-bit levels, exceeds energy.
+///
+/// Smooth gradients and fine paper texture have low energy, so are treated as background.
+/// Returns `None` if no row or column exceeds energy.
+pub fn gradient_edges(img: &DynamicImage, energy: f32) -> Option<Sides<u32>> {
+    let (width, height) = img.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    // Keep luma of only the three rows under the kernel, in 8-bit levels
+    let mut levels = Vec::with_capacity(width as usize);
+    let mut luma = |y: u32| -> Vec<f32> {
+        row_levels(img, y, DetectMode::Luma, &mut levels);
+        levels.iter().map(|&level| level as f32 / 257.0).collect()
+    };
+    let mut window = [luma(0), luma(1), luma(2)];
+
+    // Accumulate gradient magnitude of interior pixels along each row and column
+    let mut rows = vec![0.0; height as usize];
+    let mut columns = vec![0.0; width as usize];
+    for y in 1..height - 1 {
+        if y > 1 {
+            window.rotate_left(1);
+            window[2] = luma(y + 1);
+        }
+        let [above, row, below] = &window;
+        for x in 1..(width - 1) as usize {
+            let gx = above[x + 1] + 2.0 * row[x + 1] + below[x + 1]
+                - above[x - 1] - 2.0 * row[x - 1] - below[x - 1];
+            let gy = below[x - 1] + 2.0 * below[x] + below[x + 1]
+                - above[x - 1] - 2.0 * above[x] - above[x + 1];
+            // Sobel kernels weigh a step between levels four times
+            let magnitude = (gx * gx + gy * gy).sqrt() / 4.0;
+            rows[y as usize] += magnitude;
+            columns[x] += magnitude;
+        }
+    }
+
+    // Steps respond on both sides of the boundary, so edges are taken one pixel inward
+    let above = |sums: &[f32], length: u32| {
+        let first = sums.iter().position(|&sum| sum / length as f32 > energy)? as u32;
+        let last = sums.iter().rposition(|&sum| sum / length as f32 > energy)? as u32;
+        Some(match last > first + 1 {
+            true => (first + 1, last - 1),
+            false => (first, last)
+        })
+    };
+    let (top, bottom) = above(&rows, width - 2)?;
+    let (left, right) = above(&columns, height - 2)?;
+    Some(Sides { left, right, top, bottom })
+}
@@ -0,0 +1,120 @@
+use image::{imageops, DynamicImage, GenericImageView};
+use crate::{Aspect, CropRect, Gravity};
+
+/// Longest side of the thumbnail of a crop that entropy and saliency are measured on
+const MEASURE_SIZE: u32 = 256;
+
+/// Levels of the luma histograms entropy is measured from
+const BINS: usize = 64;
+
+/// Trim a crop rectangle to an aspect ratio by shortening whichever dimension is too long for it,
+/// keeping the part of the artwork chosen by gravity. Returns the rectangle as is if it already
+/// has the aspect ratio.
+pub fn trim_aspect(img: &DynamicImage, rect: &CropRect, aspect: Aspect, gravity: Gravity) -> CropRect {
+    let ratio = aspect.ratio();
+    let horizontal = rect.width as f32 / rect.height as f32 > ratio;
+    let (length, kept) = match horizontal {
+        true => (rect.width, ((rect.height as f32 * ratio).round() as u32).clamp(1, rect.width)),
+        false => (rect.height, ((rect.width as f32 / ratio).round() as u32).clamp(1, rect.height))
+    };
+    if kept == length {
+        return *rect;
+    }
+    let offset = match gravity {
+        Gravity::Top if !horizontal => 0,
+        Gravity::Center | Gravity::Top => (length - kept) / 2,
+        Gravity::Entropy | Gravity::Saliency => busiest(img, rect, horizontal, kept, gravity)
+    };
+    match horizontal {
+        true => CropRect { x: rect.x + offset, width: kept, ..*rect },
+        false => CropRect { y: rect.y + offset, height: kept, ..*rect }
+    }
+}
+
+/// Offset along the trimmed dimension of a crop of the part of the given length with the most
+/// entropy or saliency, measured on a thumbnail of the crop. Ties keep the part nearest the middle.
+fn busiest(img: &DynamicImage, rect: &CropRect, horizontal: bool, kept: u32, gravity: Gravity) -> u32 {
+    let scale = (MEASURE_SIZE as f32 / rect.width.max(rect.height) as f32).min(1.0);
+    let size = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    let thumbnail = imageops::thumbnail(&*img.view(rect.x, rect.y, rect.width, rect.height), size(rect.width), size(rect.height));
+    let (width, height) = thumbnail.dimensions();
+    let luma = |x: u32, y: u32| {
+        let [r, g, b, _] = thumbnail.get_pixel(x, y).0;
+        (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000
+    };
+
+    // Measure each line across the trimmed dimension, then each window of lines the length kept
+    let (lines, across, length) = match horizontal {
+        true => (width, height, rect.width),
+        false => (height, width, rect.height)
+    };
+    let pixel = |line: u32, at: u32| match horizontal {
+        true => (line, at),
+        false => (at, line)
+    };
+    let window = ((kept as f32 * lines as f32 / length as f32).round() as u32).clamp(1, lines);
+    let scores: Vec<f64> = match gravity {
+        Gravity::Entropy => {
+            let histograms: Vec<[u32; BINS]> = (0..lines).map(|line| {
+                let mut histogram = [0; BINS];
+                for at in 0..across {
+                    let (x, y) = pixel(line, at);
+                    histogram[luma(x, y) as usize * BINS / 256] += 1;
+                }
+                histogram
+            }).collect();
+            let mut histogram = [0; BINS];
+            (0..lines).filter_map(|line| {
+                for (count, added) in histogram.iter_mut().zip(&histograms[line as usize]) {
+                    *count += added;
+                }
+                if line >= window {
+                    for (count, removed) in histogram.iter_mut().zip(&histograms[(line - window) as usize]) {
+                        *count -= removed;
+                    }
+                }
+                (line + 1 >= window).then(|| entropy(&histogram))
+            }).collect()
+        },
+        _ => {
+            // Saliency of a pixel is its gradient of luma and the saturation of its color
+            let salience = |x: u32, y: u32| {
+                let dx = luma((x + 1).min(width - 1), y).abs_diff(luma(x.saturating_sub(1), y));
+                let dy = luma(x, (y + 1).min(height - 1)).abs_diff(luma(x, y.saturating_sub(1)));
+                let [r, g, b, _] = thumbnail.get_pixel(x, y).0;
+                (dx + dy + r.max(g).max(b) as u32 - r.min(g).min(b) as u32) as f64
+            };
+            let mut sums = vec![0.0; lines as usize + 1];
+            for line in 0..lines {
+                let sum: f64 = (0..across).map(|at| {
+                    let (x, y) = pixel(line, at);
+                    salience(x, y)
+                }).sum();
+                sums[line as usize + 1] = sums[line as usize] + sum;
+            }
+            (0..=lines - window).map(|start| sums[(start + window) as usize] - sums[start as usize]).collect()
+        }
+    };
+
+    let middle = (lines - window) as f64 / 2.0;
+    let best = scores.iter()
+        .enumerate()
+        .fold(None, |best: Option<(usize, f64)>, (start, &score)| match best {
+            Some((at, top)) if top > score || (top == score && (at as f64 - middle).abs() <= (start as f64 - middle).abs()) => best,
+            _ => Some((start, score))
+        })
+        .map_or(0, |(start, _)| start);
+    ((best as f32 * length as f32 / lines as f32).round() as u32).min(length - kept)
+}
+
+/// Shannon entropy in bits of a histogram of levels
+fn entropy(histogram: &[u32]) -> f64 {
+    let total: u32 = histogram.iter().sum();
+    histogram.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
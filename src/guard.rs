@@ -0,0 +1,116 @@
+use std::str::FromStr;
+use cpar::{CropRect, EdgeDetection};
+use image::DynamicImage;
+use crate::Cpar;
+
+/// Handling of images whose detected crop fails a guard, which are flagged for review either way
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Skip the image without saving it
+    Skip,
+    /// Save the image without cropping it
+    Uncropped
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Fallback::Skip),
+            "uncropped" => Ok(Fallback::Uncropped),
+            _ => Err(format!("unknown fallback '{}', expected skip or uncropped", s))
+        }
+    }
+}
+
+/// Handling of blank images, such as separator pages, in which no edges are detected
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Blank {
+    /// Skip the image without saving it, flagged for review
+    Skip,
+    /// Save the image without cropping it, flagged for review
+    Copy,
+    /// Fail the image
+    Error
+}
+
+impl FromStr for Blank {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Blank::Skip),
+            "copy" => Ok(Blank::Copy),
+            "error" => Ok(Blank::Error),
+            _ => Err(format!("unknown blank image handling '{}', expected skip, copy or error", s))
+        }
+    }
+}
+
+/// Parse a ratio greater than 0 and at most 1
+pub fn parse_ratio(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|ratio| *ratio > 0.0 && *ratio <= 1.0)
+        .ok_or_else(|| format!("invalid ratio '{}', expected greater than 0 and at most 1", s))
+}
+
+/// Check a crop of an image of the given dimensions against the guards against undersized and
+/// overcropped crops and the content guard, whose flag is given by content, describing why it
+/// fails the first guard it fails along with the handling of that guard
+pub fn flagged(
+    args: &Cpar,
+    rect: &CropRect,
+    (width, height): (u32, u32),
+    content: impl FnOnce() -> Option<String>
+) -> Option<(String, Fallback)> {
+    undersized(args, rect, width, height).map(|flag| (flag, args.on_undersized))
+        .or_else(|| overcropped(args, rect, width, height).map(|flag| (flag, args.on_overcropped)))
+        .or_else(|| content().map(|flag| (flag, Fallback::Skip)))
+}
+
+/// Check a crop of an image against the minimum dimensions, describing why it is too small
+pub fn undersized(args: &Cpar, rect: &CropRect, width: u32, height: u32) -> Option<String> {
+    if let Some(min) = args.min_width.filter(|&min| rect.width < min) {
+        return Some(format!("crop {} is narrower than {}px", rect, min));
+    }
+    if let Some(min) = args.min_height.filter(|&min| rect.height < min) {
+        return Some(format!("crop {} is shorter than {}px", rect, min));
+    }
+    let kept = rect.width as f64 * rect.height as f64 / (width as f64 * height as f64);
+    if let Some(min) = args.min_crop_ratio.filter(|&min| kept < min as f64) {
+        return Some(format!("crop {} keeps {:.1}% of the image, below {:.1}%", rect, 100.0 * kept, 100.0 * min));
+    }
+    None
+}
+
+/// Check a crop of an image against the largest fraction of either axis to remove, describing why
+/// it removes too much
+pub fn overcropped(args: &Cpar, rect: &CropRect, width: u32, height: u32) -> Option<String> {
+    let max = args.max_crop?;
+    let removed = |kept: u32, size: u32| 1.0 - kept as f64 / size as f64;
+    let (horizontal, vertical) = (removed(rect.width, width), removed(rect.height, height));
+    match horizontal.max(vertical) > max as f64 {
+        true => Some(format!(
+            "crop {} removes {:.1}% horizontally and {:.1}% vertically, above {:.1}%",
+            rect, 100.0 * horizontal, 100.0 * vertical, 100.0 * max
+        )),
+        false => None
+    }
+}
+
+/// Check the border a crop removes from each side of an image against the largest fraction of ink
+/// to discard, describing why it would cut away content
+pub fn content(args: &Cpar, img: &DynamicImage, rect: &CropRect, detection: &EdgeDetection) -> Option<String> {
+    let max = args.content_guard?;
+    let density = cpar::ink_density(img, detection, rect);
+    let sides = [("left", density.left), ("right", density.right), ("top", density.top), ("bottom", density.bottom)];
+    let (side, density) = sides.into_iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+    match density > max {
+        true => Some(format!(
+            "{} border removed by crop {} is {:.2}% ink, above {:.2}%", side, rect, 100.0 * density, 100.0 * max
+        )),
+        false => None
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+use image::DynamicImage;
+
+/// Extensions of HEIF images, which are decoded with libheif rather than the image crate
+const EXTENSIONS: [&str; 3] = ["heic", "heif", "hif"];
+
+/// Check whether a path has the extension of a HEIF image
+pub fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|heif| ext.eq_ignore_ascii_case(heif)))
+}
+
+/// Decode the primary image of a HEIF file, rotating, mirroring and cropping it as displayed if
+/// requested. Images of more than 8 bits per channel are decoded at 16 bits.
+#[cfg(feature = "heif")]
+pub fn decode(path: &Path, auto_orient: bool) -> cpar::Result<DynamicImage> {
+    use std::{fs, io};
+    use image::ImageBuffer;
+    use libheif_rs::{Channel, ColorSpace, DecodingOptions, HeifContext, LibHeif, RgbChroma};
+
+    let invalid = |err: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidData, format!("HEIF: {}", err));
+    let data = fs::read(path)?;
+    let context = HeifContext::read_from_bytes(&data).map_err(|err| invalid(&err))?;
+    let handle = context.primary_image_handle().map_err(|err| invalid(&err))?;
+    let alpha = handle.has_alpha_channel();
+    let high = handle.luma_bits_per_pixel() > 8;
+    let chroma = match (high, alpha) {
+        (false, false) => RgbChroma::Rgb,
+        (false, true) => RgbChroma::Rgba,
+        (true, false) => RgbChroma::HdrRgbLe,
+        (true, true) => RgbChroma::HdrRgbaLe
+    };
+    let mut options = DecodingOptions::new();
+    if let Some(options) = &mut options {
+        options.set_ignore_transformations(!auto_orient);
+    }
+    let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), options).map_err(|err| invalid(&err))?;
+
+    // Copy rows of the interleaved plane without their padding
+    let plane = decoded.planes().interleaved.ok_or_else(|| invalid(&"no interleaved image plane"))?;
+    let (width, height) = (plane.width, plane.height);
+    let channels = if alpha { 4 } else { 3 };
+    let row_bytes = width as usize * channels * if high { 2 } else { 1 };
+    let rows = || plane.data.chunks(plane.stride).take(height as usize).map(|row| &row[..row_bytes]);
+    let img = if high {
+        // Stretch samples to the 16-bit scale
+        let max = (1u32 << decoded.bits_per_pixel(Channel::Interleaved).unwrap_or(16)) - 1;
+        let samples: Vec<u16> = rows()
+            .flat_map(|row| row.chunks_exact(2))
+            .map(|sample| (u16::from_le_bytes([sample[0], sample[1]]) as u32 * 65535 / max) as u16)
+            .collect();
+        match alpha {
+            true => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba16),
+            false => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb16)
+        }
+    } else {
+        let samples: Vec<u8> = rows().flatten().copied().collect();
+        match alpha {
+            true => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba8),
+            false => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb8)
+        }
+    };
+    Ok(img.ok_or_else(|| invalid(&"image data does not match its dimensions"))?)
+}
+
+/// Fail to decode a HEIF file, as support for them was not built
+#[cfg(not(feature = "heif"))]
+pub fn decode(_: &Path, _: bool) -> cpar::Result<DynamicImage> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "decoding HEIF images requires building with the heif feature").into())
+}
@@ -0,0 +1,112 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+
+/// External command run for each image, in whose words {source} and {dest} are replaced by the
+/// paths of the image
+#[derive(Clone, Debug)]
+pub struct Hook {
+    words: Vec<String>
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = split(s).ok_or_else(|| format!("invalid command '{}', quotes are not closed", s))?;
+        match words.is_empty() {
+            true => Err("command must not be empty".to_string()),
+            false => Ok(Hook { words })
+        }
+    }
+}
+
+impl Hook {
+    /// Run the command for an image without a shell, failing if it cannot be started or exits
+    /// unsuccessfully
+    pub fn run(&self, source: &Path, dest: &Path) -> io::Result<()> {
+        let program = &self.words[0];
+        let output = Command::new(expand(program, source, dest))
+            .args(self.words[1..].iter().map(|word| expand(word, source, dest)))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| io::Error::new(err.kind(), format!("failed to run {}: {}", program, err)))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        Err(command_error(program, &output))
+    }
+}
+
+/// Error of an external command which exited unsuccessfully, reporting the last line it wrote to
+/// stderr, which usually gives the reason
+pub fn command_error(program: &str, output: &Output) -> io::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr.lines().rev().find(|line| !line.trim().is_empty())
+        .map(|line| format!(": {}", line.trim()))
+        .unwrap_or_default();
+    io::Error::other(format!("{} failed with {}{}", program, output.status, reason))
+}
+
+/// Split a command into words at whitespace as a shell would, with single quotes grouping words
+/// literally and double quotes grouping them with backslash escaping quotes and backslashes.
+/// Returns `None` if a quote is not closed.
+fn split(command: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => quoted.push(c)
+                    }
+                }
+            },
+            '"' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\') => quoted.push(c),
+                            c => quoted.extend(['\\', c])
+                        },
+                        c => quoted.push(c)
+                    }
+                }
+            },
+            c => word.get_or_insert_with(String::new).push(c)
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Replace {source} and {dest} in a word with paths, which need not be valid UTF-8
+fn expand(word: &str, source: &Path, dest: &Path) -> OsString {
+    let mut expanded = OsString::new();
+    let mut rest = word;
+    while let Some(start) = rest.find('{') {
+        expanded.push(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{source}") {
+            expanded.push(source);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{dest}") {
+            expanded.push(dest);
+            rest = after;
+        } else {
+            expanded.push("{");
+            rest = &rest[1..];
+        }
+    }
+    expanded.push(rest);
+    expanded
+}
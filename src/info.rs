@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::Args;
+use cpar::DetectMode;
+use image::ImageReader;
+use crate::{decode_animation, dpi, heif, open, pages, pdf, raw, svg};
+
+#[derive(Args)]
+/// Print the format, dimensions, color type and resolution of images, along with statistics of
+/// their luminance and the threshold --auto-threshold would choose, to tune cropping options
+pub struct Info {
+    /// Images to describe
+    #[clap(num_args = 1.., required = true)]
+    images: Vec<PathBuf>,
+    /// Decode images without applying their EXIF orientation
+    #[clap(long)]
+    no_auto_orient: bool
+}
+
+/// Describe each image on stdout, exiting with 1 if any could not be decoded
+pub fn run(info: Info) -> ExitCode {
+    let mut failed = false;
+    for (n, path) in info.images.iter().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        if let Err(err) = describe(path, !info.no_auto_orient) {
+            eprintln!("Error: {}: {}", path.display(), err);
+            failed = true;
+        }
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS
+    }
+}
+
+/// Print the description of an image, with levels on the 8-bit scale whatever its bit depth
+fn describe(path: &Path, auto_orient: bool) -> cpar::Result<()> {
+    let format = match (heif::is_heif(path), svg::is_svg(path)) {
+        (true, _) => "HEIF".to_string(),
+        (_, true) => format!("SVG, rasterized at {} DPI", svg::DEFAULT_DPI),
+        _ if raw::is_raw(path) => "camera RAW".to_string(),
+        _ if pdf::is_pdf(path) => format!("PDF, rendered at {} DPI", pdf::DEFAULT_DPI),
+        _ => ImageReader::open(path)?.with_guessed_format()?.format()
+            .map_or("unknown".to_string(), |format| format!("{:?}", format))
+    };
+    let (img, _) = open(path, auto_orient, svg::DEFAULT_DPI)?;
+    println!("{}", path.display());
+    println!("  Format:     {}", format);
+    println!("  Dimensions: {}x{}", img.width(), img.height());
+    println!("  Color:      {:?}", img.color());
+    if let Some(dpi) = dpi::read(path) {
+        println!("  Resolution: {} DPI", dpi);
+    }
+    if let Some(pages) = pages::decode_pages(path, pdf::DEFAULT_DPI)? {
+        println!("  Pages:      {}", pages.len());
+    } else if let Some(frames) = decode_animation(path)? {
+        println!("  Frames:     {}", frames.len());
+    }
+
+    let luma = img.to_luma16();
+    let (mut min, mut max, mut sum) = (u16::MAX, 0, 0u64);
+    for &level in luma.as_raw() {
+        min = min.min(level);
+        max = max.max(level);
+        sum += level as u64;
+    }
+    let level = |level: f64| level / 257.0;
+    let mean = sum as f64 / luma.as_raw().len().max(1) as f64;
+    println!("  Luminance:  min {:.0}, max {:.0}, mean {:.1}", level(min as f64), level(max as f64), level(mean));
+    match cpar::auto_threshold(&img, DetectMode::Luma) {
+        Some(threshold) => println!("  Threshold:  {:.0} (Otsu, of the border)", level(threshold as f64)),
+        None => println!("  Threshold:  none, the border is uniform")
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use image::{ColorType, DynamicImage};
+use crate::{convert, CropParams};
+
+/// Fraction of pixels clipped at each end of the luma histogram by auto levels, so that a few
+/// specks of dust or glare do not hold back the stretch
+const CLIP: f64 = 0.005;
+
+/// Stretch the luma histogram of an image to the full range if requested, then apply the gamma
+/// correction. Every color channel is stretched by the same amount, so hues are kept, and alpha
+/// is left unchanged.
+pub(crate) fn levels(img: DynamicImage, params: &CropParams) -> DynamicImage {
+    let (low, high) = match params.auto_levels {
+        true => luma_range(&img),
+        false => (0.0, 1.0)
+    };
+    if (low, high) == (0.0, 1.0) && params.gamma == 1.0 {
+        return img;
+    }
+
+    let mut adjusted = img.to_rgba32f();
+    let exponent = 1.0 / params.gamma;
+    // Floating point images may hold levels outside the displayable range
+    let clamp = !matches!(img.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    for pixel in adjusted.pixels_mut() {
+        for level in pixel.0.iter_mut().take(3) {
+            *level = (*level - low) / (high - low);
+            if clamp {
+                *level = level.clamp(0.0, 1.0);
+            }
+            if exponent != 1.0 {
+                *level = level.max(0.0).powf(exponent);
+            }
+        }
+    }
+    convert(adjusted.into(), img.color())
+}
+
+/// Find the darkest and lightest luma of an image, on a scale of 0 to 1, after clipping the
+/// outermost pixels of the histogram. Returns the full range for images of a single level.
+fn luma_range(img: &DynamicImage) -> (f32, f32) {
+    let luma = img.to_luma16();
+    let mut histogram = vec![0u64; u16::MAX as usize + 1];
+    for pixel in luma.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    // Step inward from each end until the clipped fraction of pixels is passed
+    let clipped = (luma.len() as f64 * CLIP) as u64;
+    let low = clip(histogram.iter(), clipped);
+    let high = u16::MAX as usize - clip(histogram.iter().rev(), clipped);
+    match high > low {
+        true => (low as f32 / u16::MAX as f32, high as f32 / u16::MAX as f32),
+        false => (0.0, 1.0)
+    }
+}
+
+/// Count the levels stepped over from one end of a histogram before passing clipped pixels
+fn clip<'a>(mut counts: impl Iterator<Item = &'a u64>, clipped: u64) -> usize {
+    let mut count = 0;
+    counts.position(|level| {
+        count += level;
+        count > clipped
+    }).unwrap_or(0)
+}
@@ -0,0 +1,253 @@
+//! Crop Preserving Aspect Ratio - Crops artwork and restores it to the original aspect ratio
+mod animation;
+mod components;
+mod crop;
+mod deskew;
+mod despeckle;
+mod detect;
+mod error;
+mod frame;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod gradient;
+mod gravity;
+mod levels;
+mod linear;
+mod orient;
+mod pad;
+mod params;
+mod pipeline;
+mod preview;
+mod refine;
+mod sharpen;
+mod split;
+mod streaming;
+mod threshold;
+mod trim;
+mod variance;
+
+use image::{ColorType, DynamicImage, GenericImageView};
+
+/// Stand-in for the GPU backend when built without it, leaving all resampling to the CPU
+#[cfg(not(feature = "gpu"))]
+mod gpu {
+    use image::DynamicImage;
+    use image::imageops::FilterType;
+
+    pub fn available() -> bool {
+        false
+    }
+
+    pub fn blur(_: &DynamicImage, _: f32) -> Option<DynamicImage> {
+        None
+    }
+
+    pub fn resize(_: &DynamicImage, _: u32, _: u32, _: FilterType) -> Option<DynamicImage> {
+        None
+    }
+}
+
+pub use animation::{crop_frames, detect_frames, process_frames, FrameCrop};
+pub use components::component_edges;
+pub use deskew::{deskew, estimate_skew, rotate};
+pub use detect::{boundary, crop_rect, detect, detect_edges, edge_confidence, ink_density, snap_aspect, CropRect};
+pub use error::{Error, Result};
+pub use gpu::available as gpu_available;
+pub use gradient::gradient_edges;
+pub use gravity::trim_aspect;
+pub use orient::{orient, text_rotation};
+pub use pad::{pad, pad_at};
+pub use params::{
+    parse_aspect, parse_color, parse_filter, parse_level, parse_region, Aspect, Channel, CropParams, DetectMode, EdgeDetection,
+    EdgeStat, Gravity, Polarity, RestoreMode, Rotation, Sharpen, Sides, DEFAULT_DEVIATION, DEFAULT_ENERGY
+};
+pub use pipeline::{Artwork, Pipeline, Stage};
+pub use preview::{blended, draw_boundary, draw_crop, heatmap, side_by_side};
+pub use split::split_panels;
+pub use streaming::StreamedDetection;
+pub use threshold::{auto_threshold, otsu, range_threshold};
+pub use trim::exact_edges;
+pub use variance::variance_edges;
+
+/// Crop an image to its detected edges and downscale it, restoring the original aspect ratio.
+///
+/// Fails with [`Error::NoEdges`] if the edges of the image could not be detected.
+pub fn process(img: &DynamicImage, params: &CropParams) -> Result<DynamicImage> {
+    let oriented = params.rotate.map(|_| orient(img.clone(), params).0);
+    let img = oriented.as_ref().unwrap_or(img);
+    let deskewed = params.deskew.then(|| deskew(img, &params.detection)).flatten();
+    let img = deskewed.as_ref().map_or(img, |(img, _)| img);
+    let rect = detect(img, &params.detection).ok_or(Error::NoEdges)?;
+    Ok(apply(img, &rect, params))
+}
+
+/// Crop an image to the given rectangle and downscale it, restoring the original aspect ratio
+pub fn apply(img: &DynamicImage, rect: &CropRect, params: &CropParams) -> DynamicImage {
+    let size = output_size(img.width(), img.height(), rect, params);
+    restore(img.crop_imm(rect.x, rect.y, rect.width, rect.height), size, params)
+}
+
+/// Downscale an image already cropped to the given rectangle of an image of the given dimensions,
+/// restoring the original aspect ratio, as [`apply`] would the uncropped image
+pub fn apply_cropped(cropped: DynamicImage, width: u32, height: u32, rect: &CropRect, params: &CropParams) -> DynamicImage {
+    restore(cropped, output_size(width, height, rect, params), params)
+}
+
+/// Crop an image to the given rectangle and downscale it, restoring the original aspect ratio.
+///
+/// Crops within the image's own buffer rather than copying the rectangle, so that very large
+/// images are not held in memory twice.
+pub fn apply_owned(img: DynamicImage, rect: &CropRect, params: &CropParams) -> DynamicImage {
+    let size = output_size(img.width(), img.height(), rect, params);
+    restore(crop::crop_in_place(img, rect), size, params)
+}
+
+/// Blur a cropped image, resize or pad it to the output dimensions, then sharpen it and adjust
+/// its levels
+fn restore(cropped: DynamicImage, (width, height): (u32, u32), params: &CropParams) -> DynamicImage {
+    let blurred = if let Some(sigma) = params.blur {
+        blur(&cropped, sigma, params)
+    } else {
+        cropped
+    };
+    let finished = |img: DynamicImage| {
+        let sharpened = match &params.sharpen {
+            Some(amount) => sharpen::sharpen(&img, amount, params),
+            None => img
+        };
+        levels::levels(sharpened, params)
+    };
+    match params.restore {
+        RestoreMode::Resize if blurred.dimensions() == (width, height) => finished(blurred),
+        RestoreMode::Resize => finished(resize(&blurred, width, height, params)),
+        RestoreMode::Crop => finished(blurred),
+        RestoreMode::Pad => {
+            let color = params.pad_color.unwrap_or(params.detection.mode.background());
+            // Scale the artwork as the padded dimensions were scaled, spanning the side not padded
+            let (artwork_width, artwork_height) = (blurred.width() as f32, blurred.height() as f32);
+            let sized = params.target_width.is_some() || params.target_height.is_some() || params.max_dimension.is_some();
+            let scale = match sized {
+                true => ((artwork_width / width as f32).max(artwork_height / height as f32), true),
+                false => (params.downscale, false)
+            };
+            let scaled = match scale {
+                (1.0, _) => blurred,
+                (factor, exact) => {
+                    let scaled_width = (scaled(artwork_width, factor, exact) as u32).clamp(1, width);
+                    let scaled_height = (scaled(artwork_height, factor, exact) as u32).clamp(1, height);
+                    resize(&blurred, scaled_width, scaled_height, params)
+                }
+            };
+            // Sharpen before padding, so that no halo is drawn along the padding, and adjust levels
+            // of the artwork alone
+            pad(&finished(scaled), width, height, color)
+        }
+    }
+}
+
+/// Blur an image, on the GPU if requested and available, in linear light if requested
+fn blur(img: &DynamicImage, sigma: f32, params: &CropParams) -> DynamicImage {
+    resampled(img, params, |img| params.gpu.then(|| gpu::blur(img, sigma)).flatten().unwrap_or_else(|| img.blur(sigma)))
+}
+
+/// Resize an image with the resampling filter, on the GPU if requested and available, in linear
+/// light if requested
+pub(crate) fn resize(img: &DynamicImage, width: u32, height: u32, params: &CropParams) -> DynamicImage {
+    resampled(img, params, |img| {
+        params.gpu.then(|| gpu::resize(img, width, height, params.filter))
+            .flatten()
+            .unwrap_or_else(|| img.resize_exact(width, height, params.filter))
+    })
+}
+
+/// Resample an image as given, converting it to linear light and back around it if requested
+fn resampled(img: &DynamicImage, params: &CropParams, resample: impl Fn(&DynamicImage) -> DynamicImage) -> DynamicImage {
+    match params.linear_resize {
+        true => linear::from_linear(resample(&linear::to_linear(img)), img.color()),
+        false => resample(img)
+    }
+}
+
+/// Convert an image to a color type, keeping it as is where there is no conversion to that type
+pub fn convert(img: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => img.to_luma8().into(),
+        ColorType::La8 => img.to_luma_alpha8().into(),
+        ColorType::Rgb8 => img.to_rgb8().into(),
+        ColorType::Rgba8 => img.to_rgba8().into(),
+        ColorType::L16 => img.to_luma16().into(),
+        ColorType::La16 => img.to_luma_alpha16().into(),
+        ColorType::Rgb16 => img.to_rgb16().into(),
+        ColorType::Rgba16 => img.to_rgba16().into(),
+        ColorType::Rgb32F => img.to_rgb32f().into(),
+        _ => img
+    }
+}
+
+/// Determine the dimensions of an image after cropping to the given rectangle, such that it is
+/// downscaled, restoring the original or target aspect ratio
+pub fn output_size(width: u32, height: u32, rect: &CropRect, params: &CropParams) -> (u32, u32) {
+    let Some((new_x, new_y)) = restored_size(width, height, rect, params) else {
+        return (rect.width, rect.height);
+    };
+    let (factor, exact) = scale(new_x, new_y, params);
+    (
+        (scaled(new_x, factor, exact) as u32).max(1),
+        (scaled(new_y, factor, exact) as u32).max(1)
+    )
+}
+
+/// Determine the factor the artwork within the given rectangle of an image is downscaled by, as
+/// for [`output_size`]
+pub fn output_scale(width: u32, height: u32, rect: &CropRect, params: &CropParams) -> f32 {
+    match restored_size(width, height, rect, params) {
+        Some((new_x, new_y)) => scale(new_x, new_y, params).0,
+        None => 1.0
+    }
+}
+
+/// Dimensions restoring the original or target aspect ratio after cropping, before downscaling.
+/// Returns `None` if the aspect ratio is not restored.
+fn restored_size(width: u32, height: u32, rect: &CropRect, params: &CropParams) -> Option<(f32, f32)> {
+    let (f_width, f_height) = match params.aspect {
+        Some(aspect) => (aspect.width, aspect.height),
+        None => (width as f32, height as f32)
+    };
+    let x_rel_size = rect.width as f32 / f_width;
+    let y_rel_size = rect.height as f32 / f_height;
+    match params.restore {
+        RestoreMode::Resize if x_rel_size < y_rel_size => Some((rect.width as f32, x_rel_size * f_height)),
+        RestoreMode::Resize => Some((y_rel_size * f_width, rect.height as f32)),
+        // Pad the shorter side, rather than shrinking the longer
+        RestoreMode::Pad if x_rel_size < y_rel_size => {
+            Some(((y_rel_size * f_width).max(rect.width as f32), rect.height as f32))
+        },
+        RestoreMode::Pad => Some((rect.width as f32, (x_rel_size * f_height).max(rect.height as f32))),
+        RestoreMode::Crop => None
+    }
+}
+
+/// Factor to divide the dimensions of an image by to fit within the target width and height where
+/// given, otherwise to downscale it, and then further to fit within the maximum dimension. Also
+/// returns whether the factor was found from a size in pixels, which should be met exactly.
+fn scale(width: f32, height: f32, params: &CropParams) -> (f32, bool) {
+    let fit = |size: f32, target: Option<u32>| target.map(|target| size / target as f32);
+    let fitted = [fit(width, params.target_width), fit(height, params.target_height)].into_iter().flatten();
+    let (factor, exact) = match fitted.reduce(f32::max) {
+        Some(factor) => (factor, true),
+        None => (params.downscale, false)
+    };
+    match params.max_dimension.map(|max| width.max(height) / max as f32) {
+        Some(limit) if limit > factor => (limit, true),
+        _ => (factor, exact)
+    }
+}
+
+/// Dimension scaled by a factor, rounded to the nearest pixel where the factor was found from a
+/// size in pixels so that floating point error cannot leave it a pixel short
+fn scaled(size: f32, factor: f32, exact: bool) -> f32 {
+    match exact {
+        true => (size / factor).round(),
+        false => (size / factor).floor()
+    }
+}
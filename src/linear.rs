@@ -0,0 +1,74 @@
+use std::sync::OnceLock;
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel};
+use crate::convert;
+
+/// Level in linear light of a level encoded with the sRGB transfer function, both from 0 to 1 for
+/// standard dynamic range
+fn decode(level: f32) -> f32 {
+    match level <= 0.04045 {
+        true => level / 12.92,
+        false => ((level + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Level encoded with the sRGB transfer function of a level in linear light, both from 0 to 1
+fn encode(level: f32) -> f32 {
+    match level <= 0.0031308 {
+        true => level * 12.92,
+        false => 1.055 * level.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Table mapping each 16-bit level through a transfer function
+fn table(transfer: fn(f32) -> f32) -> Vec<u16> {
+    (0..=u16::MAX).map(|level| (transfer(level as f32 / u16::MAX as f32) * u16::MAX as f32).round() as u16).collect()
+}
+
+/// Map the color channels of an image through a table of 16-bit levels, or a transfer function
+/// for images of floating point levels, leaving alpha as it is
+fn mapped(img: DynamicImage, table: &[u16], transfer: fn(f32) -> f32) -> DynamicImage {
+    fn map<P: Pixel>(
+        mut buf: ImageBuffer<P, Vec<P::Subpixel>>,
+        level: impl Fn(P::Subpixel) -> P::Subpixel
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let alpha = P::CHANNEL_COUNT == 2 || P::CHANNEL_COUNT == 4;
+        for pixel in buf.pixels_mut() {
+            match alpha {
+                true => pixel.apply_with_alpha(&level, |alpha| alpha),
+                false => pixel.apply(&level)
+            }
+        }
+        buf
+    }
+    let lookup = |level: u16| table[level as usize];
+    let transfer = |level: f32| transfer(level.max(0.0));
+    match img {
+        DynamicImage::ImageRgb32F(buf) => DynamicImage::ImageRgb32F(map(buf, transfer)),
+        DynamicImage::ImageRgba32F(buf) => DynamicImage::ImageRgba32F(map(buf, transfer)),
+        DynamicImage::ImageLuma16(buf) => DynamicImage::ImageLuma16(map(buf, lookup)),
+        DynamicImage::ImageLumaA16(buf) => DynamicImage::ImageLumaA16(map(buf, lookup)),
+        DynamicImage::ImageRgb16(buf) => DynamicImage::ImageRgb16(map(buf, lookup)),
+        DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba16(map(buf, lookup)),
+        img => img
+    }
+}
+
+/// Image converted to linear light, at 16 bits per channel, or in floating point if its levels
+/// already are, so that resampling averages light rather than encoded levels
+pub(crate) fn to_linear(img: &DynamicImage) -> DynamicImage {
+    static TABLE: OnceLock<Vec<u16>> = OnceLock::new();
+    let wide = match img.color() {
+        ColorType::L8 | ColorType::L16 => img.to_luma16().into(),
+        ColorType::La8 | ColorType::La16 => img.to_luma_alpha16().into(),
+        ColorType::Rgb8 | ColorType::Rgb16 => img.to_rgb16().into(),
+        ColorType::Rgb32F | ColorType::Rgba32F => img.clone(),
+        _ => img.to_rgba16().into()
+    };
+    mapped(wide, TABLE.get_or_init(|| table(decode)), decode)
+}
+
+/// Image in linear light converted back to the sRGB transfer function and to a color type
+pub(crate) fn from_linear(img: DynamicImage, color: ColorType) -> DynamicImage {
+    static TABLE: OnceLock<Vec<u16>> = OnceLock::new();
+    convert(mapped(img, TABLE.get_or_init(|| table(encode)), encode), color)
+}
@@ -0,0 +1,89 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use cpar::{CropParams, CropRect};
+use image::DynamicImage;
+use log::warn;
+use rayon::prelude::*;
+use crate::sources::Source;
+use crate::{decode_animation, open, pages, report, Cpar};
+
+/// How a single crop shared by every image of a batch is determined
+#[derive(Clone, PartialEq, Eq)]
+pub enum LockCrop {
+    /// Union of the crops detected in each image, keeping all of their artwork
+    Union,
+    /// Intersection of the crops detected in each image, removing all of their borders
+    Intersection,
+    /// Crop detected in the first image
+    First,
+    /// Crop read from a JSON file, in the form of the crop of a report record
+    File(PathBuf)
+}
+
+impl FromStr for LockCrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(LockCrop::Union),
+            "intersection" => Ok(LockCrop::Intersection),
+            "first" => Ok(LockCrop::First),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LockCrop::File(path.into())),
+                _ => Err(format!("unknown lock crop '{}', expected union, intersection, first or file:PATH", s))
+            }
+        }
+    }
+}
+
+/// Determine the crop shared by all source images.
+///
+/// Sources which fail to decode, or in which no edges are detected, are left out, failing with
+/// [`cpar::Error::NoEdges`] only if no edges are detected in any source.
+pub fn lock(lock: &LockCrop, sources: &[Source], args: &Cpar, params: &CropParams) -> cpar::Result<CropRect> {
+    let detect = |source: &Source| match detect(&source.path, args, params) {
+        Ok(rects) => rects,
+        Err(err) => {
+            warn!(image:% = source.path.display(), error:% = err; "Left {} out of locked crop: {}", source.path.display(), err);
+            Vec::new()
+        }
+    };
+    match lock {
+        LockCrop::Union => sources.par_iter()
+            .flat_map_iter(detect)
+            .reduce_with(|a, b| a.union(&b))
+            .ok_or(cpar::Error::NoEdges),
+        LockCrop::Intersection => sources.par_iter()
+            .flat_map_iter(detect)
+            .map(Some)
+            .reduce_with(|a, b| a?.intersection(&b?))
+            .ok_or(cpar::Error::NoEdges)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "detected crops do not overlap").into()),
+        LockCrop::First => sources.iter()
+            .find_map(|source| detect(source).into_iter().next())
+            .ok_or(cpar::Error::NoEdges),
+        LockCrop::File(path) => Ok(report::read_crop(path)?)
+    }
+}
+
+/// Detect the crops of an image file, one for each page of a multipage image, or the union of the
+/// crops of the frames of an animation
+pub fn detect(path: &Path, args: &Cpar, params: &CropParams) -> cpar::Result<Vec<CropRect>> {
+    if let Some(frames) = decode_animation(path)? {
+        return Ok(vec![cpar::detect_frames(&frames, params)?.1]);
+    }
+    let detect = |img: DynamicImage| {
+        let (img, _) = cpar::orient(img, params);
+        let deskewed = params.deskew.then(|| cpar::deskew(&img, &params.detection)).flatten();
+        cpar::detect(deskewed.as_ref().map_or(&img, |(img, _)| img), &params.detection)
+    };
+    let rects = match pages::decode_pages(path, args.pdf_dpi)? {
+        Some(pages) => pages.into_iter().filter_map(detect).collect(),
+        None => {
+            let (img, _) = open(path, !args.no_auto_orient, args.svg_dpi)?;
+            detect(img).into_iter().collect()
+        }
+    };
+    Ok(rects)
+}
@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use indicatif::ProgressBar;
+use log::{kv, Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value};
+
+/// Format of log lines written to stderr
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Plain messages
+    Text,
+    /// One JSON object per line, with level, message and structured fields
+    Json
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format '{}', expected text or json", s))
+        }
+    }
+}
+
+/// Logger writing to stderr, suspending any progress bar while doing so
+struct Logger {
+    format: OnceLock<LogFormat>,
+    progress: Mutex<Option<ProgressBar>>
+}
+
+static LOGGER: Logger = Logger { format: OnceLock::new(), progress: Mutex::new(None) };
+
+/// Install the logger, showing warnings and errors only when quiet, and more detail with each
+/// level of verbosity
+pub fn init(quiet: bool, verbose: u8, format: LogFormat) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace
+    };
+    LOGGER.format.set(format).ok();
+    log::set_logger(&LOGGER).expect("logger already installed");
+    log::set_max_level(level);
+}
+
+/// Suspend a progress bar while writing log lines, so that they are not drawn over
+pub fn set_progress(progress: &ProgressBar) {
+    *LOGGER.progress.lock().unwrap() = Some(progress.clone());
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies log internal diagnostics, such as GPU adapter probing, which are not ours
+        // to report
+        metadata.level() <= log::max_level() && metadata.target().split("::").next() == Some("cpar")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = match self.format.get().copied().unwrap_or(LogFormat::Text) {
+            LogFormat::Text if record.level() >= Level::Debug => {
+                format!("{}: {}", record.level().as_str().to_lowercase(), record.args())
+            },
+            LogFormat::Text => record.args().to_string(),
+            LogFormat::Json => {
+                let mut fields = Map::new();
+                fields.insert("level".into(), record.level().as_str().to_lowercase().into());
+                fields.insert("message".into(), record.args().to_string().into());
+                let _ = record.key_values().visit(&mut Fields(&mut fields));
+                Value::Object(fields).to_string()
+            }
+        };
+        let write = || {
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        };
+        match &*self.progress.lock().unwrap() {
+            Some(progress) => progress.suspend(write),
+            None => write()
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Collector of structured log fields into a JSON object
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> kv::VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = match (value.to_u64(), value.to_f64(), value.to_bool()) {
+            (Some(number), _, _) => number.into(),
+            (_, Some(number), _) => number.into(),
+            (_, _, Some(boolean)) => boolean.into(),
+            _ => value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Summarise boundary depths along one side of an image, as the most common depths with their
+/// number of rows or columns
+pub fn histogram(depths: impl Iterator<Item = u32>) -> String {
+    let mut counts = std::collections::BTreeMap::new();
+    for depth in depths {
+        *counts.entry(depth).or_insert(0usize) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.iter()
+        .take(8)
+        .map(|(depth, count)| format!("{}:{}", depth, count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,30 +1,354 @@
-use std::fs;
-use std::path::PathBuf;
-use clap::Parser;
-use image::{GenericImageView, ImageReader, Pixel};
+mod archive;
+mod bench;
+mod boxes;
+mod cloud;
+mod colorspace;
+mod completions;
+mod config;
+mod dedupe;
+mod dpi;
+mod guard;
+mod heif;
+mod hook;
+mod info;
+mod lock;
+mod logging;
+mod manifest;
+mod metrics;
+mod overrides;
+mod pages;
+mod pdf;
+mod pipe;
+mod raw;
+mod report;
+mod restore;
+mod resume;
+mod route;
+mod save;
+mod serve;
+mod sources;
+mod stats;
+mod strips;
+mod svg;
+mod watch;
+mod xmp;
+
+use std::{env, fs, io};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use colorspace::{Colorspace, Dither};
+use dedupe::{Dedupe, Seen};
+use cpar::{
+    Aspect, CropParams, CropRect, DetectMode, EdgeDetection, EdgeStat, FrameCrop, Gravity, Polarity, RestoreMode, Rotation, Sharpen,
+    Sides, StreamedDetection
+};
+use image::{
+    AnimationDecoder, DynamicImage, Frame, GenericImageView, GrayImage, ImageDecoder, ImageFormat, ImageReader, ImageResult, Rgba
+};
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::codecs::png::CompressionType;
 use image::imageops::FilterType;
+use image::metadata::Orientation;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use hook::Hook;
+use pages::PageOutput;
+use pipe::Detected;
+use guard::{Blank, Fallback};
+use lock::LockCrop;
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use logging::LogFormat;
+use manifest::Manifest;
+use metrics::{Metrics, Sink};
+use overrides::Overrides;
+use report::{Record, Status};
+use resume::State;
+use route::{Measure, Route};
+use stats::Stats;
+use strips::Strips;
+use save::{Existing, OutputFormat, SaveOptions, Verify};
+use sources::Source;
 
 #[derive(Parser)]
 /// Crop Preserving Aspect Ratio - Crops artwork and restores it to the original aspect ratio
-#[command(arg_required_else_help = true)]
-struct CPAR {
-    /// Source file(s) to process
+#[command(
+    name = "cpar",
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    override_usage = "cpar [OPTIONS] <SOURCE>... <OUTPUT>\n       cpar [OPTIONS] --in-place <SOURCE>...\n       \
+        cpar <COMMAND> ...",
+    after_help = "Sources named as a command are processed with cpar crop, or by giving their path as ./NAME"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+    #[command(flatten)]
+    args: Cpar
+}
+
+/// Subcommands given in place of the first source, of which crop is the flat form
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Crop images, as the flat form does
+    #[command(override_usage = "cpar crop [OPTIONS] <SOURCE>... <OUTPUT>\n       cpar crop [OPTIONS] --in-place <SOURCE>...")]
+    Crop(Cpar),
+    /// Detect the crop of each source image, printing them as JSON without writing any images
+    #[command(
+        override_usage = "cpar detect [OPTIONS] <SOURCE>...",
+        mut_arg("source", |arg| arg.help("Source file(s), directories or glob patterns to detect the crops of"))
+    )]
+    Detect(Cpar),
+    /// Crop source images to the crops listed in a JSON file written by cpar detect or --report
+    #[command(
+        override_usage = "cpar apply [OPTIONS] <BOXES> <OUTPUT>\n       cpar apply [OPTIONS] --in-place <BOXES>",
+        mut_arg("source", |arg| arg.value_name("BOXES").help(
+            "JSON file listing sources and their crops, followed by the output folder unless using --in-place"
+        ))
+    )]
+    Apply(Cpar),
+    Info(info::Info),
+    Bench(bench::Bench),
+    Restore(restore::Restore),
+    Completions(completions::Completions),
+    Serve(serve::Serve)
+}
+
+/// Options of the flat form and of the crop, detect and apply subcommands
+#[derive(Parser)]
+struct Cpar {
+    /// Source file(s), directories or glob patterns to process, or - to read an image from stdin,
+    /// followed by the output folder to place processed images within, or - to write a single image
+    /// to stdout. The output folder is left out with --in-place
     #[clap(num_args = 1.., required = true)]
     source: Vec<PathBuf>,
-    /// Output folder to place processed images within
+    /// Output folder, taken from the end of the sources
+    #[clap(skip)]
     output: PathBuf,
+    /// Crops of sources read by `cpar apply`, used instead of detecting their edges
+    #[clap(skip)]
+    boxes: HashMap<PathBuf, CropRect>,
+    /// Options affecting the images saved, recorded with each source by --incremental
+    #[clap(skip)]
+    settings: String,
+    /// Mask image given by --mask, converted to grayscale
+    #[clap(skip)]
+    mask_image: Option<Arc<GrayImage>>,
+    /// Rows of the parameter manifest given by --param-manifest
+    #[clap(skip)]
+    overrides: Option<Overrides>,
+    /// Output format to transcode all images to (png, jpeg, webp, avif, jxl, tiff, bmp, ...)
+    #[clap(short, long, value_parser = save::parse_format)]
+    format: Option<OutputFormat>,
+    /// Crop animated GIF/WebP frames to the union of their crops, or each to its own (union, per-frame).
+    /// Animations are always saved as GIF
+    #[clap(long, default_value = "union")]
+    frame_crop: FrameCrop,
+    /// Save pages of multipage TIFFs and PDF documents to separate files ({stem}_p{n}), or to a
+    /// single multipage TIFF regardless of --format (split, multipage)
+    #[clap(long, default_value = "split")]
+    pages: PageOutput,
+    /// Encoding quality (1-100) of JPEG and AVIF output, and of lossy WebP output (requires building
+    /// with the webp feature), which is otherwise lossless. JPEG XL output is always lossless
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Encoding speed (1-10) of AVIF output, trading file size for time [default: 4]
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=10))]
+    speed: Option<u8>,
+    /// Compression level of PNG output (fast, default, best)
+    #[clap(long, value_parser = save::parse_png_compression)]
+    png_compression: Option<CompressionType>,
+    /// Convert output to 8 or 16-bit grayscale, or 8-bit RGB with or without alpha (gray, gray16,
+    /// rgb, rgba), such as to save black and white pages as true grayscale
+    #[clap(long)]
+    colorspace: Option<Colorspace>,
+    /// Dithering when --colorspace reduces images of more than 8 bits per channel to 8 bits (none,
+    /// ordered, floyd-steinberg)
+    #[clap(long, default_value = "none", requires = "colorspace")]
+    dither: Dither,
+    /// Make output images, the report and the summary byte-identical between runs, such as to diff
+    /// them against golden files: encoder settings left to defaults are fixed, AVIF is encoded on a
+    /// single thread, and timing in --stats-json is zeroed. Conflicts with --gpu, whose results
+    /// vary by adapter, and --rename, whose numbering depends on which image finishes first
+    #[clap(long, conflicts_with_all = ["gpu", "rename"])]
+    deterministic: bool,
+    /// Decode each saved image again, failing it unless it decodes at the dimensions it was saved
+    /// at, to catch encoders failing silently during long unattended runs. Multipage TIFF and
+    /// animated output, and formats which cannot be decoded such as JPEG XL, are not checked
+    #[clap(long)]
+    verify: bool,
+    /// Also fail saved images unless a checksum of their decoded pixels matches that of the pixels
+    /// encoded, for images saved in lossless formats
+    #[clap(long, requires = "verify")]
+    verify_checksum: bool,
+    /// Also save a thumbnail of each processed image within OUTPUT/thumbs, scaled down to fit within
+    /// PX by PX
+    #[clap(
+        long, value_name = "PX", conflicts_with_all = ["in_place", "preview"],
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    thumbnail: Option<u32>,
+    /// Also save each processed image scaled down to fit within each of these sizes in pixels, such
+    /// as 2048,1024,512, beside it with the size suffix appended to its name
+    #[clap(
+        long, value_name = "PX", value_delimiter = ',', conflicts_with_all = ["in_place", "preview"],
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    sizes: Vec<u32>,
+    /// Suffix appended to the names of images saved by --sizes, in which {size} is replaced by the
+    /// size
+    #[clap(long, value_name = "SUFFIX", default_value = "_{size}", value_parser = parse_size_suffix)]
+    size_suffix: String,
+    /// Record the original dimensions and crop of each image in XMP metadata, embedded in JPEG, PNG
+    /// and WebP output and otherwise written beside the saved image as FILE.xmp, so that the crop can
+    /// be reproduced or undone given the original, or padded back to its dimensions by cpar restore
+    #[clap(long, conflicts_with = "preview")]
+    xmp: bool,
+    /// Command to run before processing each image, such as to fetch or convert it, in which
+    /// {source} and {dest} are replaced by the paths of the image. Words are split as by a shell,
+    /// without running one. Images whose command fails are not processed
+    #[clap(long, value_name = "COMMAND")]
+    pre_cmd: Option<Hook>,
+    /// Command to run on each saved image, such as 'oxipng {dest}' to recompress it or to upload it,
+    /// in which {source} and {dest} are replaced by the paths of the source and saved image. Images
+    /// whose command fails are reported as failed
+    #[clap(long, value_name = "COMMAND")]
+    post_cmd: Option<Hook>,
+    /// Read sources from files listing them one per line, such as the output of find, or from
+    /// stdin if given as -, avoiding limits on the length of command lines
+    #[clap(long, conflicts_with = "watch")]
+    files_from: bool,
+    /// Config file to load presets from
+    #[clap(long, default_value = "cpar.toml", requires = "preset")]
+    config: PathBuf,
+    /// Preset from config file providing default option values
+    #[clap(long)]
+    preset: Option<String>,
+
+    /// Watch source directories, processing new images as they appear
+    #[clap(short, long)]
+    watch: bool,
+    /// Mirror the structure of source directories within the output folder
+    #[clap(short = 'P', long)]
+    preserve_structure: bool,
+    /// Replace output files which already exist
+    #[clap(long, group = "existing")]
+    overwrite: bool,
+    /// Skip images whose output file already exists
+    #[clap(long, group = "existing")]
+    skip_existing: bool,
+    /// Append a numeric suffix to output files which already exist
+    #[clap(long, group = "existing")]
+    rename: bool,
+    /// Replace each source image with its processed image instead of saving to an output folder.
+    /// Multipage TIFFs are saved back as multipage TIFFs
+    #[clap(long, conflicts_with_all = ["format", "existing", "preserve_structure", "preview", "watch", "split"])]
+    in_place: bool,
+    /// Keep each source replaced by --in-place beside it with this suffix appended, such as .orig
+    #[clap(long, value_name = "SUFFIX", requires = "in_place")]
+    backup_suffix: Option<String>,
+    /// Sort outputs into folders within the output folder by their crop, such as
+    /// cropped>50%:heavily_cropped/, comparing the percentage of the image cropped away, the width or
+    /// height of the output in pixels, or the percentage of the rows and columns crossing threshold
+    /// near the edges placed. Repeatable; the first rule matched applies
+    #[clap(long, value_name = "RULE", conflicts_with = "in_place")]
+    route: Vec<Route>,
+    /// Skip images completed by an earlier run with --resume, such as one that was interrupted,
+    /// recording images as they are completed
+    #[clap(long, conflicts_with = "watch")]
+    resume: bool,
+    /// File recording completed images for --resume by path, along with their size, modification
+    /// time and checksum so that renamed images are still recognized [default: OUTPUT/.cpar-state.jsonl]
+    #[clap(long, value_name = "PATH", requires = "resume")]
+    state_file: Option<PathBuf>,
+    /// Skip images whose source and options are unchanged since they were last processed, as
+    /// recorded in a manifest of checksums, replacing the outputs of those which changed
+    #[clap(long, conflicts_with = "watch")]
+    incremental: bool,
+    /// Manifest recording processed images for --incremental [default: OUTPUT/.cpar-manifest.jsonl]
+    #[clap(long, value_name = "PATH", requires = "incremental")]
+    manifest: Option<PathBuf>,
+
+    /// Pixel property used to detect edges (luma, alpha), a color channel or the brightest or darkest
+    /// of them (channel:r, channel:g, channel:b, channel:max, channel:min), gradient energy of rows
+    /// and columns (gradient), their variation regardless of background brightness (variance), an
+    /// exact match of the corner color (exact), or the bounding box of the largest connected region
+    /// of artwork (bbox)
+    #[clap(short = 'D', long, default_value = "luma")]
+    detect: DetectMode,
+    /// Detect edges by distance from a background color (#RRGGBB) instead of by threshold
+    #[clap(long, conflicts_with = "detect", value_parser = cpar::parse_color)]
+    background: Option<Rgba<u8>>,
+    /// Maximum color distance from background to identify as whitespace
+    #[clap(long, default_value_t = 16.0, requires = "background")]
+    tolerance: f32,
+    /// Minimum mean gradient magnitude of rows and columns of artwork in gradient mode
+    #[clap(long, default_value_t = cpar::DEFAULT_ENERGY)]
+    energy: f32,
+    /// Minimum standard deviation of luma of rows and columns of artwork in variance mode, in 8-bit
+    /// levels
+    #[clap(long, default_value_t = cpar::DEFAULT_DEVIATION)]
+    deviation: f32,
+    /// Crop to the bounding box of every connected region of at least this many pixels in bbox
+    /// mode, rather than of the largest region alone
+    #[clap(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    min_component: Option<u32>,
+    /// Trim rows and columns exactly the color of the top left corner pixel, like ImageMagick's
+    /// -trim, as for screenshots. Shorthand for --detect exact
+    #[clap(long, conflicts_with_all = ["detect", "background"])]
+    exact_trim: bool,
+    /// Brightness of the background in luma, channel and bbox modes, inverting levels against dark
+    /// backgrounds so that threshold keeps its meaning, or deciding per image from its corners
+    /// (light, dark, auto)
+    #[clap(long, default_value = "light")]
+    polarity: Polarity,
 
-    /// Threshold value to identify as whitespace
-    #[clap(short, long, default_value_t = 250)]
-    threshold: u8,
+    /// Threshold value to identify as whitespace, or minimum opacity of artwork in alpha mode. Given
+    /// as an 8-bit level (250), a percentage (98%) or a fraction (64000/65535) for high bit depth
+    /// images. Use auto to compute per image with Otsu's method, falling back to 250 or the side
+    /// thresholds
+    #[clap(short, long, default_value = "250", value_parser = parse_threshold)]
+    threshold: Threshold,
+    /// Threshold as a percentage (0-100) of the way from the darkest to the brightest level of each
+    /// image, or from least to most opaque in alpha mode, so that one setting suits a batch of
+    /// mixed bit depths and exposures
+    #[clap(long, value_name = "PERCENT", value_parser = parse_percent, conflicts_with_all = [
+        "threshold", "x_threshold", "y_threshold", "left_threshold", "right_threshold", "top_threshold",
+        "bottom_threshold"
+    ])]
+    threshold_pct: Option<f32>,
     /// Threshold value in x-axis
-    #[clap(long, visible_alias = "xt", conflicts_with = "threshold")]
-    x_threshold: Option<u8>,
+    #[clap(long, visible_alias = "xt", conflicts_with = "threshold", value_parser = cpar::parse_level)]
+    x_threshold: Option<u16>,
     /// Threshold value in y-axis
-    #[clap(long, visible_alias = "yt", conflicts_with = "threshold")]
-    y_threshold: Option<u8>,
+    #[clap(long, visible_alias = "yt", conflicts_with = "threshold", value_parser = cpar::parse_level)]
+    y_threshold: Option<u16>,
+    /// Threshold value at left edge
+    #[clap(long, visible_alias = "lt", value_parser = cpar::parse_level)]
+    left_threshold: Option<u16>,
+    /// Threshold value at right edge
+    #[clap(long, visible_alias = "rt", value_parser = cpar::parse_level)]
+    right_threshold: Option<u16>,
+    /// Threshold value at top edge
+    #[clap(long, visible_alias = "tt", value_parser = cpar::parse_level)]
+    top_threshold: Option<u16>,
+    /// Threshold value at bottom edge
+    #[clap(long, visible_alias = "bt", value_parser = cpar::parse_level)]
+    bottom_threshold: Option<u16>,
 
-    /// Percentage of rows/columns having crossed threshold to consider edge found
+    /// Percentile of the depths at which rows/columns cross threshold to place each edge at, from 0
+    /// keeping all artwork to 100 cropping to the deepest, interpolated between rows/columns
     #[clap(short, long, default_value_t = 95, value_parser = clap::value_parser!(u8).range(0..=100))]
     percentile: u8,
     /// Percentile in x-axis
@@ -33,109 +357,1779 @@ struct CPAR {
     /// Percentile in y-axis
     #[clap(long, visible_alias = "yp", conflicts_with = "percentile", value_parser = clap::value_parser!(u8).range(0..=100))]
     y_percentile: Option<u8>,
+    /// Percentile at left edge
+    #[clap(long, visible_alias = "lp", value_parser = clap::value_parser!(u8).range(0..=100))]
+    left_percentile: Option<u8>,
+    /// Percentile at right edge
+    #[clap(long, visible_alias = "rp", value_parser = clap::value_parser!(u8).range(0..=100))]
+    right_percentile: Option<u8>,
+    /// Percentile at top edge
+    #[clap(long, visible_alias = "tp", value_parser = clap::value_parser!(u8).range(0..=100))]
+    top_percentile: Option<u8>,
+    /// Percentile at bottom edge
+    #[clap(long, visible_alias = "bp", value_parser = clap::value_parser!(u8).range(0..=100))]
+    bottom_percentile: Option<u8>,
+    /// Statistic of the depths of rows/columns placing each edge: a percentile, or the median after
+    /// rejecting outliers such as a torn corner, or the mean of the middle 80% (percentile, median,
+    /// trimmed-mean)
+    #[clap(long, default_value = "percentile")]
+    edge_stat: EdgeStat,
+    /// Median filter levels over windows of N pixels during detection only, ignoring dust specks
+    /// up to about half that size in the margins
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..))]
+    despeckle: Option<u32>,
+    /// Blur a copy of the image by sigma to steady edge detection on noisy scans, leaving the saved
+    /// image sharp
+    #[clap(long, value_name = "SIGMA")]
+    detect_blur: Option<f32>,
+    /// Detect edges a second time within the first crop, on a downscaled copy with thresholds 10%
+    /// tighter, to shave residual border such as soft shadows
+    #[clap(long)]
+    refine: bool,
+    /// Scan every Nth row and column for the boundary, speeding up detection on large scans. Rows
+    /// and columns between them are interpolated where they agree, and scanned where they differ
+    /// such as near the ends of the artwork, so only artwork thinner than N pixels may be missed
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    sample_stride: u32,
+    /// After cropping the margin, strip a dark frame of at most N pixels thick drawn around the
+    /// artwork, such as the thin black line inside the margin of a scan, and crop the margin within
+    /// it too. The frame must cross threshold along nearly all of every side
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    strip_frame: Option<u32>,
+    /// Only detect edges within this region, given as X,Y,W,H in pixels, ignoring anything outside
+    /// it such as scanner edge artifacts or punch holes
+    #[clap(long, value_name = "X,Y,W,H", value_parser = cpar::parse_region)]
+    detect_region: Option<CropRect>,
+    /// Ignore this many pixels at each side of the image, or of the detection region, when
+    /// detecting edges
+    #[clap(long, value_name = "PX", default_value_t = 0)]
+    ignore_margin: u32,
+    /// Ignore the pixels under the nonzero pixels of this grayscale image when detecting edges, such
+    /// as stamps, watermarks or binding shadows in the margins. Stretched to the size of each image.
+    #[clap(long, value_name = "PATH")]
+    mask: Option<PathBuf>,
 
-    /// Extra margin to crop beyond found edge in both axes
-    #[clap(short, long, default_value_t = 0)]
-    extra: u32,
+    /// Extra margin to crop beyond found edge in both axes, or negative to keep more border
+    #[clap(short, long, default_value_t = 0, allow_negative_numbers = true)]
+    extra: i32,
     /// Extra crop in x-axis
-    #[clap(long, visible_alias = "ex", conflicts_with = "extra")]
-    x_extra: Option<u32>,
+    #[clap(long, visible_alias = "ex", conflicts_with = "extra", allow_negative_numbers = true)]
+    x_extra: Option<i32>,
     /// Extra crop in y-axis
-    #[clap(long, visible_alias = "ey", conflicts_with = "extra")]
-    y_extra: Option<u32>,
+    #[clap(long, visible_alias = "ey", conflicts_with = "extra", allow_negative_numbers = true)]
+    y_extra: Option<i32>,
+    /// Extra crop at left edge
+    #[clap(long, visible_aliases = ["el", "extra-left"], allow_negative_numbers = true)]
+    left_extra: Option<i32>,
+    /// Extra crop at right edge
+    #[clap(long, visible_aliases = ["er", "extra-right"], allow_negative_numbers = true)]
+    right_extra: Option<i32>,
+    /// Extra crop at top edge
+    #[clap(long, visible_aliases = ["et", "extra-top"], allow_negative_numbers = true)]
+    top_extra: Option<i32>,
+    /// Extra crop at bottom edge
+    #[clap(long, visible_aliases = ["eb", "extra-bottom"], allow_negative_numbers = true)]
+    bottom_extra: Option<i32>,
+    /// CSV file tuning images individually, with a header naming a path column and any of threshold,
+    /// percentile and extra columns, whose cells replace those options on every side for the image
+    /// in their row. Empty cells keep the options given
+    #[clap(long, value_name = "CSV")]
+    param_manifest: Option<PathBuf>,
+    /// Adjust each crop by the least area to the nearest of these aspect ratios, in portrait or
+    /// landscape, given as W:H or as a4, letter, legal or square, such as a4,letter for documents
+    #[clap(long, value_name = "ASPECT", value_delimiter = ',', value_parser = cpar::parse_aspect)]
+    snap_aspect: Vec<Aspect>,
 
-    /// Blur image by sigma
+    /// Ignore EXIF orientation, processing images as stored rather than as displayed
+    #[clap(long)]
+    no_auto_orient: bool,
+    /// Resolution in dots per inch to rasterize SVG images at, over white, before cropping them
+    /// (requires building with the svg feature). Rasterized images are saved as PNG unless --format
+    /// is given
+    #[clap(long, value_name = "DPI", default_value_t = svg::DEFAULT_DPI, value_parser = parse_dpi)]
+    svg_dpi: f32,
+    /// Resolution in dots per inch to render the pages of PDF documents at, over white, before
+    /// cropping them (requires building with the pdf feature). Rendered pages are saved as PNG
+    /// unless --format is given
+    #[clap(long, value_name = "DPI", default_value_t = pdf::DEFAULT_DPI, value_parser = parse_dpi)]
+    pdf_dpi: f32,
+    /// Detect the edges of TIFF images stored in strips a strip at a time as they are decoded, then
+    /// decode only the strips within the crop, rather than holding the whole image in memory. Other
+    /// images, and those processed with options needing the whole image, are decoded whole
+    #[clap(long, conflicts_with_all = ["split", "preview", "inspect", "debug_output", "diff_output"])]
+    streamed_decode: bool,
+    /// Rotate images clockwise by 90, 180 or 270 degrees before detecting their crop, or with auto
+    /// turn sideways scans upright by the direction of their lines of text, after EXIF orientation
+    #[clap(long, value_name = "DEGREES")]
+    rotate: Option<Rotation>,
+    /// Estimate and correct rotation of scanned artwork by up to 5 degrees before cropping
+    #[clap(long)]
+    deskew: bool,
+    /// Split images into panels separated by interior whitespace gutters, such as facing pages
+    /// scanned together, saving each panel cropped as {name}_1, {name}_2, ...
+    #[clap(long)]
+    split: bool,
+    /// Narrowest gutter in pixels between panels when splitting
+    #[clap(long, default_value_t = 10, requires = "split")]
+    min_gutter: u32,
+    /// Crop every image of the batch to one shared rectangle, such as frames or pages of a series:
+    /// the union or intersection of their detected crops, the crop of the first image, or a crop
+    /// read from a JSON file with x, y, width and height (union, intersection, first, file:PATH)
+    #[clap(long, value_name = "LOCK", conflicts_with_all = ["watch", "split"])]
+    lock_crop: Option<LockCrop>,
+    /// Save the crop of each image to a sidecar file beside it ({name}.crop.json), describing the
+    /// first page of multipage images
+    #[clap(long, conflicts_with = "split")]
+    write_crops: bool,
+    /// Crop each image to the rectangle saved in its sidecar file by --write-crops, instead of
+    /// detecting its edges
+    #[clap(long, conflicts_with_all = ["split", "lock_crop"])]
+    apply_crops: bool,
+
+    /// Restore the original aspect ratio by resizing the cropped image, or by padding it with the
+    /// background color (resize, pad)
+    #[clap(short, long, default_value = "resize")]
+    mode: RestoreMode,
+    /// Only crop, writing the cropped image without restoring the aspect ratio or downscaling
+    #[clap(long, conflicts_with_all = [
+        "mode", "aspect", "pad_color", "downscale", "target_width", "target_height", "max_dimension", "target_dpi",
+        "filter"
+    ])]
+    crop_only: bool,
+    /// Restore a target aspect ratio (W:H) instead of the original, such as 2:3 for book covers
+    #[clap(short, long)]
+    aspect: Option<Aspect>,
+    /// Trim each crop to the --aspect ratio instead of stretching or padding it, keeping the middle,
+    /// the top, the part with the most detail by entropy, or the part with the most edges and color
+    /// by saliency (center, top, entropy, saliency)
+    #[clap(long, requires = "aspect")]
+    gravity: Option<Gravity>,
+    /// Color to pad with (#RRGGBB or #RRGGBBAA), defaulting to white, transparent in alpha mode, or
+    /// the background color
+    #[clap(long, value_parser = cpar::parse_color)]
+    pad_color: Option<Rgba<u8>>,
+
+    /// Resampling filter used when resizing (nearest, triangle, catmullrom, gaussian, lanczos3)
+    #[clap(long, default_value = "lanczos3", value_parser = cpar::parse_filter)]
+    filter: FilterType,
+    /// Blur and resize in linear light, converting from sRGB and back, so that fine dark details
+    /// such as line art are not darkened when downscaling
+    #[clap(long)]
+    linear_resize: bool,
+    /// Blur the saved image by sigma after cropping, without affecting detection (see
+    /// --detect-blur)
     #[clap(short, long)]
     blur: Option<f32>,
+    /// Sharpen image after resizing with an unsharp mask, adding amount times the difference from
+    /// a blur of radius (sigma, default 1) wherever it exceeds threshold (8-bit levels, default 0)
+    #[clap(long, value_name = "AMOUNT[,RADIUS,THRESHOLD]")]
+    sharpen: Option<Sharpen>,
+    /// Stretch levels of the cropped image so that its darkest and lightest pixels, ignoring the
+    /// outermost 0.5%, span the full range
+    #[clap(long)]
+    auto_levels: bool,
+    /// Gamma correction of the cropped image, brightening midtones above 1 and darkening them below
+    #[clap(long, default_value_t = 1.0, value_parser = parse_gamma)]
+    gamma: f32,
 
     /// Downscale image by factor
-    #[clap(short, long, default_value_t = 1.0)]
-    downscale: f32
+    #[clap(short, long, default_value_t = 1.0, value_parser = parse_downscale)]
+    downscale: f32,
+    /// Scale output to this width in pixels in place of --downscale, fitting within --target-height
+    /// too if given
+    #[clap(long, value_name = "PX", conflicts_with = "downscale", value_parser = clap::value_parser!(u32).range(1..))]
+    target_width: Option<u32>,
+    /// Scale output to this height in pixels in place of --downscale
+    #[clap(long, value_name = "PX", conflicts_with = "downscale", value_parser = clap::value_parser!(u32).range(1..))]
+    target_height: Option<u32>,
+    /// Downscale output further where needed so that its longer side is at most PX, leaving smaller
+    /// images as they are
+    #[clap(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+    /// Resolution in dots per inch to record in output, scaling images from the resolution of their
+    /// source so that their print size is kept, or to fit --physical-size at this resolution
+    #[clap(
+        long, value_name = "DPI", conflicts_with_all = ["downscale", "target_width", "target_height"],
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    target_dpi: Option<u32>,
+    /// Print size to fit output within (WxH, in inches, or with a unit of in, cm or mm such as
+    /// 15x10cm), scaling images to it at --target-dpi, or otherwise recording the resolution at
+    /// which they print at this size
+    #[clap(long, value_name = "WxH", conflicts_with_all = ["downscale", "target_width", "target_height"])]
+    physical_size: Option<dpi::PhysicalSize>,
+    /// Blur and resize on the GPU, falling back to the CPU if no hardware adapter is available (requires
+    /// building with the gpu feature)
+    #[clap(long)]
+    gpu: bool,
+
+    /// Narrowest crop in pixels to accept, guarding against failed detection leaving a sliver
+    #[clap(long, value_name = "PX")]
+    min_width: Option<u32>,
+    /// Shortest crop in pixels to accept
+    #[clap(long, value_name = "PX")]
+    min_height: Option<u32>,
+    /// Smallest fraction (0-1] of the image's area for a crop to keep
+    #[clap(long, value_name = "RATIO", value_parser = guard::parse_ratio)]
+    min_crop_ratio: Option<f32>,
+    /// Skip images whose crop is below the minimum dimensions, or save them uncropped (skip,
+    /// uncropped). Either way they are flagged in the report, and piped images are failed rather than
+    /// skipped
+    #[clap(long, default_value = "skip")]
+    on_undersized: Fallback,
+    /// Largest fraction (0-1] of either axis for a crop to remove, guarding against cropping into
+    /// artwork
+    #[clap(long, value_name = "RATIO", value_parser = guard::parse_ratio)]
+    max_crop: Option<f32>,
+    /// Save images whose crop removes more than the maximum uncropped for review, or skip them
+    /// (skip, uncropped). Either way they are flagged in the report
+    #[clap(long, default_value = "uncropped")]
+    on_overcropped: Fallback,
+    /// Largest fraction (0-1] of the border removed by a crop on any side to be ink, by the detection
+    /// threshold, guarding against cutting away content near the edge such as a signature. Images
+    /// above it are skipped and flagged in the report
+    #[clap(long, value_name = "DENSITY", value_parser = guard::parse_ratio)]
+    content_guard: Option<f32>,
+    /// Flag images whose crop looks the same as that of an image processed earlier in the batch,
+    /// such as scans digitized twice, by a perceptual hash, or skip them (flag, skip). Either way
+    /// the image each duplicates is recorded in the report
+    #[clap(long, value_name = "ACTION")]
+    dedupe: Option<Dedupe>,
+    /// Bits of the 64-bit perceptual hashes of two crops which may differ for --dedupe to count them
+    /// as duplicates
+    #[clap(
+        long, value_name = "BITS", default_value_t = 6, requires = "dedupe",
+        value_parser = clap::value_parser!(u32).range(0..=64)
+    )]
+    dedupe_distance: u32,
+    /// Skip blank images in which no edges are detected, such as separator pages, save them
+    /// uncropped, or fail them (skip, copy, error). Skipped and copied images are flagged in the
+    /// report, and piped images are failed rather than skipped
+    #[clap(long, value_name = "POLICY", default_value = "error")]
+    on_blank: Blank,
+    /// Save images in which no edges are detected, or whose detection fails unexpectedly, uncropped
+    /// and flagged for review instead of failing them, so that every source has an output. Implies
+    /// --on-blank copy
+    #[clap(long, conflicts_with_all = ["split", "on_blank"])]
+    passthrough_unmatched: bool,
+
+    /// Report detected crop rectangles and output dimensions without writing any images
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+    /// Save a preview of each image with the detected crop drawn over it, instead of processing it
+    #[clap(long, conflicts_with = "dry_run")]
+    preview: bool,
+    /// Color of the crop drawn over previews (#RRGGBB)
+    #[clap(long, default_value = "#FF0000", value_parser = cpar::parse_color)]
+    preview_color: Rgba<u8>,
+    /// Print a heatmap of each image's luma in unicode blocks with the detected crop outlined, to
+    /// check crops over a terminal such as on a remote machine
+    #[clap(long)]
+    inspect: bool,
+    /// Width in characters of heatmaps printed by --inspect
+    #[clap(
+        long, value_name = "COLS", default_value_t = 64, requires = "inspect",
+        value_parser = clap::value_parser!(u32).range(8..)
+    )]
+    inspect_width: u32,
+    /// Folder to save diagnostic images within ({name}.debug.png), plotting where each row and column
+    /// crossed the threshold from each side, with lines at the edges selected by percentile
+    #[clap(long, value_name = "DIR")]
+    debug_output: Option<PathBuf>,
+    /// Folder to save comparison images within ({name}.diff.png), showing each image before and
+    /// after processing for review of large batches
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["dry_run", "preview"])]
+    diff_output: Option<PathBuf>,
+    /// Layout of comparison images: the original with its crop drawn beside the result
+    /// (side-by-side), or the result blended over the original (blend)
+    #[clap(long, default_value = "side-by-side", requires = "diff_output")]
+    diff_mode: DiffMode,
+    /// Write a JSON report of the results of processing each image
+    #[clap(long, conflicts_with = "watch")]
+    report: Option<PathBuf>,
+    /// Write a JSON summary of the run, as printed at its end
+    #[clap(long, conflicts_with = "watch")]
+    stats_json: Option<PathBuf>,
+    /// Write the paths of images which failed to a file, one per line, such as to retry them
+    #[clap(long, value_name = "PATH", conflicts_with = "watch")]
+    failed_list: Option<PathBuf>,
+    /// Write the error of each image which fails, and the options it was processed with, to a
+    /// sidecar in the output folder ({stem}.error.txt) for review without the log
+    #[clap(long, conflicts_with = "dry_run")]
+    error_sidecars: bool,
+    /// Copy each image which fails into the output folder as it is, beside its error sidecar
+    #[clap(long, requires = "error_sidecars", conflicts_with = "in_place")]
+    copy_failed: bool,
+    /// Report the outcome, processing time and percentage cropped of each image for monitoring,
+    /// serving them at /metrics for Prometheus to scrape while the run lasts (prometheus:[HOST:]PORT,
+    /// port 9090 by default), or sending them to a StatsD server (statsd:[HOST:]PORT, port 8125)
+    #[clap(long, value_name = "SINK")]
+    metrics: Option<Sink>,
+
+    /// Suppress progress bar and per-image status output, logging only warnings and errors
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log detected edges of each image, or with -vv the depths at which each row or column
+    /// crossed the threshold
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Format of log lines written to stderr (text, json)
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Maximum number of images to process concurrently [default: number of CPUs]
+    #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>
 }
 
-fn main() -> std::io::Result<()> {
-    let args = CPAR::parse();
+/// Threshold value, or automatic threshold detection
+#[derive(Clone, Copy)]
+enum Threshold {
+    Auto,
+    Value(u16)
+}
 
-    // Set axis thresholds
-    let x_threshold = args.x_threshold.unwrap_or(args.threshold);
-    let y_threshold = args.y_threshold.unwrap_or(args.threshold);
-    let x_percentile = 1.0 - args.x_percentile.unwrap_or(args.percentile) as f32 / 100.0;
-    let y_percentile = 1.0 - args.y_percentile.unwrap_or(args.percentile) as f32 / 100.0;
+/// Layout of comparison images saved by --diff-output
+#[derive(Clone, Copy)]
+enum DiffMode {
+    SideBySide,
+    Blend
+}
+
+impl FromStr for DiffMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(DiffMode::SideBySide),
+            "blend" => Ok(DiffMode::Blend),
+            _ => Err(format!("unknown diff mode '{}', expected side-by-side or blend", s))
+        }
+    }
+}
+
+/// Parse a threshold value or `auto`
+fn parse_threshold(s: &str) -> Result<Threshold, String> {
+    match s {
+        "auto" => Ok(Threshold::Auto),
+        _ => cpar::parse_level(s).map(Threshold::Value).map_err(|err| format!("{}, or auto", err))
+    }
+}
+
+/// Parse a downscale factor, which must be positive
+fn parse_downscale(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|factor| *factor > 0.0 && factor.is_finite())
+        .ok_or_else(|| format!("invalid downscale factor '{}', expected a positive number such as 2", s))
+}
+
+/// Parse a percentage from 0 to 100, optionally followed by `%`
+fn parse_percent(s: &str) -> Result<f32, String> {
+    s.strip_suffix('%').unwrap_or(s)
+        .parse::<f32>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .ok_or_else(|| format!("invalid percentage '{}', expected 0 to 100", s))
+}
+
+/// Parse a suffix for --sizes, which must include the size to tell sizes apart
+fn parse_size_suffix(s: &str) -> Result<String, String> {
+    match s.contains("{size}") {
+        true => Ok(s.to_string()),
+        false => Err(format!("invalid suffix '{}', expected it to include {{size}}", s))
+    }
+}
+
+/// Parse a positive, finite gamma
+fn parse_gamma(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|gamma| *gamma > 0.0 && gamma.is_finite())
+        .ok_or_else(|| format!("invalid gamma '{}', expected a positive number such as 1.2", s))
+}
+
+/// Parse a positive, finite resolution to rasterize SVG images or render PDF pages at
+fn parse_dpi(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|dpi| *dpi > 0.0 && dpi.is_finite())
+        .ok_or_else(|| format!("invalid resolution '{}', expected a positive number of dots per inch such as 300", s))
+}
+
+impl Cpar {
+    /// Encoder settings for saved images
+    fn save_options(&self) -> SaveOptions {
+        SaveOptions {
+            format: self.format,
+            quality: self.quality,
+            speed: self.speed,
+            png_compression: self.png_compression,
+            colorspace: self.colorspace,
+            dither: self.dither,
+            deterministic: self.deterministic,
+            xmp: None,
+            dpi: None,
+            verify: self.verify.then_some(match self.verify_checksum {
+                true => Verify::Checksum,
+                false => Verify::Dimensions
+            })
+        }
+    }
+
+    /// Handling of blank images, which are copied uncropped with --passthrough-unmatched
+    fn on_blank(&self) -> Blank {
+        match self.passthrough_unmatched {
+            true => Blank::Copy,
+            false => self.on_blank
+        }
+    }
+
+    /// Behaviour when the destination of an image already exists
+    fn existing(&self) -> Existing {
+        if self.overwrite || self.incremental && !self.skip_existing && !self.rename {
+            Existing::Overwrite
+        } else if self.skip_existing {
+            Existing::Skip
+        } else if self.rename {
+            Existing::Rename
+        } else {
+            Existing::Fail
+        }
+    }
+}
+
+/// Result of successfully processing a single image
+enum Outcome {
+    /// Processed image was saved to the given path
+    Saved(PathBuf),
+    /// Crop was reported without saving
+    Reported,
+    /// Image was skipped as its destination already exists
+    Skipped,
+    /// Image was skipped as an earlier run completed it
+    Resumed,
+    /// Image was skipped as it and the options are unchanged since an earlier run processed it
+    Unchanged,
+    /// Image was skipped as its crop failed a guard, flagged in its record
+    Flagged
+}
+
+/// Decoded image whose crop is saved
+enum Decoded {
+    /// The whole image
+    Whole(DynamicImage),
+    /// Only the crop of an image of the given dimensions, as decoded with --streamed-decode
+    Crop(DynamicImage, (u32, u32))
+}
+
+/// Folder within the output folder to save thumbnails in
+const THUMBNAIL_DIR: &str = "thumbs";
+
+/// Exit code of a run which could not be carried out, as for invalid arguments. Runs in which
+/// only some images failed exit with 1.
+const FATAL: u8 = 2;
+
+fn main() -> ExitCode {
+    let argv: Vec<_> = env::args_os().collect();
+    let mut cli = Cli::command();
+    cli.build();
+    let matches = cli.clone().get_matches_from(&argv);
+    let command = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.format(&mut cli).exit()).command;
+    // Subcommands taking the options of the flat form parse them with their own command, which
+    // comes first as arguments conflict with subcommands
+    let (argv, cli, matches) = match matches.subcommand() {
+        Some((name, matches)) => (&argv[1..], cli.find_subcommand(name).expect("subcommand is defined"), matches),
+        None => (&argv[..], &cli, &matches)
+    };
+    match command {
+        Some(Subcommand::Detect(_)) => return boxes::detect(argv, cli, matches),
+        Some(Subcommand::Apply(_)) => return boxes::apply(argv, cli, matches),
+        Some(Subcommand::Info(info)) => return info::run(info),
+        Some(Subcommand::Bench(bench)) => return bench::run(bench),
+        Some(Subcommand::Restore(restore)) => return restore::run(restore),
+        Some(Subcommand::Completions(completions)) => return completions::run(completions),
+        Some(Subcommand::Serve(serve)) => return serve::run(serve),
+        Some(Subcommand::Crop(_)) | None => {}
+    }
+    let args = match config::parse(argv, cli, matches, true) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(FATAL);
+        }
+    };
+    logging::init(args.quiet, args.verbose, args.log_format);
+    execute(args).unwrap_or_else(|err| {
+        error!(error:% = err; "Error: {}", err);
+        ExitCode::from(FATAL)
+    })
+}
+
+/// Processing parameters given by the arguments, setting side thresholds, percentiles and extra
+/// margins by falling back to axis and then global values
+fn crop_params(args: &Cpar) -> CropParams {
+    let threshold = match args.threshold {
+        Threshold::Auto => 250 * 257,
+        Threshold::Value(threshold) => threshold
+    };
+    let x_threshold = args.x_threshold.unwrap_or(threshold);
+    let y_threshold = args.y_threshold.unwrap_or(threshold);
+    let x_percentile = args.x_percentile.unwrap_or(args.percentile);
+    let y_percentile = args.y_percentile.unwrap_or(args.percentile);
     let x_extra = args.x_extra.unwrap_or(args.extra);
     let y_extra = args.y_extra.unwrap_or(args.extra);
+    CropParams {
+        detection: EdgeDetection {
+            mode: match (args.exact_trim, args.background) {
+                (true, _) => DetectMode::Exact,
+                (false, Some(color)) => DetectMode::Background { color, tolerance: args.tolerance },
+                (false, None) => match args.detect {
+                    DetectMode::Gradient { .. } => DetectMode::Gradient { energy: args.energy },
+                    DetectMode::Variance { .. } => DetectMode::Variance { deviation: args.deviation },
+                    DetectMode::Bbox { .. } => DetectMode::Bbox { min_area: args.min_component },
+                    mode => mode
+                }
+            },
+            polarity: args.polarity,
+            threshold: Sides {
+                left: args.left_threshold.unwrap_or(x_threshold),
+                right: args.right_threshold.unwrap_or(x_threshold),
+                top: args.top_threshold.unwrap_or(y_threshold),
+                bottom: args.bottom_threshold.unwrap_or(y_threshold)
+            },
+            auto_threshold: matches!(args.threshold, Threshold::Auto),
+            range_threshold: args.threshold_pct,
+            percentile: Sides {
+                left: args.left_percentile.unwrap_or(x_percentile),
+                right: args.right_percentile.unwrap_or(x_percentile),
+                top: args.top_percentile.unwrap_or(y_percentile),
+                bottom: args.bottom_percentile.unwrap_or(y_percentile)
+            },
+            stat: args.edge_stat,
+            despeckle: args.despeckle,
+            blur: args.detect_blur,
+            region: args.detect_region,
+            ignore_margin: args.ignore_margin,
+            mask: args.mask_image.clone(),
+            extra: Sides {
+                left: args.left_extra.unwrap_or(x_extra),
+                right: args.right_extra.unwrap_or(x_extra),
+                top: args.top_extra.unwrap_or(y_extra),
+                bottom: args.bottom_extra.unwrap_or(y_extra)
+            },
+            snap_aspect: args.snap_aspect.clone(),
+            trim_aspect: args.gravity.and(args.aspect),
+            gravity: args.gravity.unwrap_or_default(),
+            refine: args.refine,
+            sample_stride: args.sample_stride,
+            strip_frame: args.strip_frame
+        },
+        rotate: args.rotate,
+        deskew: args.deskew,
+        restore: match args.crop_only {
+            true => RestoreMode::Crop,
+            false => args.mode
+        },
+        aspect: args.aspect,
+        pad_color: args.pad_color,
+        filter: args.filter,
+        linear_resize: args.linear_resize,
+        blur: args.blur,
+        sharpen: args.sharpen,
+        auto_levels: args.auto_levels,
+        gamma: args.gamma,
+        downscale: args.downscale,
+        target_width: args.target_width,
+        target_height: args.target_height,
+        max_dimension: args.max_dimension,
+        gpu: args.gpu
+    }
+}
 
-    // Ensure destination folder exists
-    fs::create_dir_all(&args.output)?;
+/// Set up the pool of workers processing images in parallel
+fn worker_pool(args: &Cpar) -> rayon::ThreadPool {
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = args.jobs {
+        pool = pool.num_threads(jobs as usize);
+    }
+    pool.build().expect("failed to create worker pool")
+}
 
-    // Process images
-    for path in args.source {
-        let img = ImageReader::open(&path)?.decode().expect("failed to decode image");
-        println!("Processing {}", path.file_name().unwrap().to_str().unwrap());
+/// Find the source images given by the arguments, reading them from lists with --files-from
+fn find_sources(args: &Cpar) -> io::Result<Vec<Source>> {
+    match args.files_from {
+        true => {
+            let mut listed = Vec::new();
+            for list in &args.source {
+                listed.extend(sources::read_list(list)?);
+            }
+            sources::expand(&listed)
+        },
+        false => sources::expand(&args.source)
+    }
+}
 
-        let mut x_thresholds = Vec::new();
-        let mut y_thresholds = Vec::new();
+/// Read the mask and parameter manifest named by the arguments, once they are parsed rather than
+/// while parsing them, so that a mask is read once for every image
+fn load_files(args: &mut Cpar) -> io::Result<()> {
+    if let Some(path) = &args.mask {
+        let mask = image::open(path).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("failed to open mask '{}': {}", path.display(), err))
+        })?;
+        args.mask_image = Some(Arc::new(mask.to_luma8()));
+    }
+    args.overrides = args.param_manifest.as_deref().map(overrides::load).transpose()?;
+    Ok(())
+}
+
+/// Process images as requested by the arguments, exiting with 1 if any image failed
+fn execute(mut args: Cpar) -> io::Result<ExitCode> {
+    if args.gpu && !cpar::gpu_available() {
+        warn!("No GPU available, blurring and resizing on the CPU");
+    }
+    load_files(&mut args)?;
+
+    let params = crop_params(&args);
+
+    let pool = worker_pool(&args);
+
+    // Process a single image piped through stdin or stdout
+    let stdin = !args.files_from && args.source.iter().any(|source| pipe::is_stdio(source));
+    if stdin || pipe::is_stdio(&args.output) {
+        return Ok(match pipe::pipe(&args, &params) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                error!(error:% = err; "Failed: {}", err);
+                ExitCode::FAILURE
+            }
+        });
+    }
+
+    // Stream images from or to object storage rather than the filesystem
+    if args.source.iter().chain([&args.output]).any(|path| cloud::is_url(path)) {
+        return Ok(match cloud::run(&args, &params, &pool) {
+            Ok(code) => code,
+            Err(err) => {
+                error!(error:% = err; "Failed: {}", err);
+                ExitCode::from(FATAL)
+            }
+        });
+    }
+
+    // Start serving or sending metrics of each image processed
+    let metrics = args.metrics.as_ref().map(Metrics::start).transpose()?;
+
+    // Watch source directories for new images instead of processing a batch
+    if args.watch {
+        if !args.dry_run {
+            fs::create_dir_all(&args.output)?;
+        }
+        let run = Run::new(&args, &params, None, None, None, metrics, ProgressBar::hidden());
+        watch::watch(&run, &pool)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Find source images and ensure destination folder exists
+    let start = Instant::now();
+    let sources = find_sources(&args)?;
+    if !args.dry_run {
+        fs::create_dir_all(&args.output)?;
+    }
+
+    // Determine the crop shared by all images before processing any of them
+    let lock = match &args.lock_crop {
+        Some(lock) => match pool.install(|| lock::lock(lock, &sources, &args, &params)) {
+            Ok(rect) => {
+                info!(crop:% = rect; "Locked crop to {}", rect);
+                Some(rect)
+            },
+            Err(err) => {
+                error!(error:% = err; "Failed to lock crop: {}", err);
+                return Ok(ExitCode::from(FATAL));
+            }
+        },
+        None => None
+    };
+
+    // Load images completed by earlier runs
+    let state = match args.resume {
+        true => {
+            let path = args.state_file.clone().unwrap_or(args.output.join(resume::STATE_FILE));
+            Some(State::open(&path, !args.dry_run)?)
+        },
+        false => None
+    };
+
+    // Load images processed by earlier runs, with the options they were processed with
+    let manifest = match args.incremental {
+        true => {
+            let path = args.manifest.clone().unwrap_or(args.output.join(manifest::MANIFEST_FILE));
+            Some(Manifest::open(&path, !args.dry_run)?)
+        },
+        false => None
+    };
+
+    // Set up progress bar, which is also hidden automatically when not attached to a terminal
+    let progress = if args.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(sources.len() as u64)
+    };
+    progress.set_style(
+        ProgressStyle::with_template("[{elapsed_precise}] {wide_bar} {pos}/{len} (ETA {eta}) {prefix} {msg}")
+            .expect("invalid progress bar template")
+    );
+    logging::set_progress(&progress);
+    let run = Run::new(&args, &params, lock, state, manifest, metrics, progress);
+
+    // Process images, recording failures so that remaining images are still processed
+    let records: Vec<_> = pool.install(|| sources.par_iter().map(|source| run.process(source)).collect());
+    run.progress.finish_and_clear();
+    if let Some(report) = &args.report {
+        report::write(report, &records)?;
+    }
+    let stats = Stats::new(&records, start.elapsed());
+    info!("{}", stats);
+    if let Some(path) = &args.stats_json {
+        let seconds = if args.deterministic { 0.0 } else { stats.seconds };
+        stats::write(path, &Stats { seconds, ..stats })?;
+    }
+
+    // Summarise failures
+    let failures: Vec<_> = records.iter().filter(|record| record.status == Status::Failed).collect();
+    if let Some(path) = &args.failed_list {
+        report::write_failed(path, &failures)?;
+    }
+    if failures.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    error!("{} of {} images failed:", failures.len(), sources.len());
+    for record in &failures {
+        error!("  {}: {}", record.source.display(), record.error.as_deref().unwrap_or_default());
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+/// Shared state of a processing run
+struct Run<'a> {
+    args: &'a Cpar,
+    params: &'a CropParams,
+    /// Crop shared by all images, in place of detecting each image's own
+    lock: Option<CropRect>,
+    /// Images completed by earlier runs, to skip and to record completed images in
+    state: Option<State>,
+    /// Images processed by earlier runs with --incremental, to skip if unchanged and to record
+    /// processed images in
+    manifest: Option<Manifest>,
+    /// Sink recording the outcome of each image with --metrics
+    metrics: Option<Metrics>,
+    /// Hashes of the crops of the images processed so far, for --dedupe
+    seen: Seen,
+    progress: ProgressBar,
+    failed: AtomicUsize
+}
+
+impl<'a> Run<'a> {
+    fn new(
+        args: &'a Cpar,
+        params: &'a CropParams,
+        lock: Option<CropRect>,
+        state: Option<State>,
+        manifest: Option<Manifest>,
+        metrics: Option<Metrics>,
+        progress: ProgressBar
+    ) -> Self {
+        Self { args, params, lock, state, manifest, metrics, seen: Seen::default(), progress, failed: AtomicUsize::new(0) }
+    }
 
-        // Check right edge of image
-        for y in 0..img.height() {
-            for x in (0..img.width()).rev() {
-                if img.get_pixel(x, y).to_luma().0[0] < x_threshold {
-                    x_thresholds.push(x);
-                    break;
+    /// Checksum of the options an image is processed with, including its crop from `cpar apply`, its
+    /// row of the parameter manifest and the pixels of the mask, which may be edited under its path
+    fn settings(&self, path: &Path) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(self.args.settings.as_bytes());
+        if let Some(rect) = self.args.boxes.get(path) {
+            hasher.update(format!(" --crop={}", rect).as_bytes());
+        }
+        if let Some(entry) = overrides::find(self.args, path) {
+            hasher.update(entry.to_string().as_bytes());
+        }
+        if let Some(mask) = &self.args.mask_image {
+            hasher.update(&mask.width().to_le_bytes());
+            hasher.update(&mask.height().to_le_bytes());
+            hasher.update(mask.as_raw());
+        }
+        hasher.finalize()
+    }
+
+    /// Parameters to process an image with, tuned by its row of the parameter manifest
+    fn file_params(&self, path: &Path) -> Cow<'a, CropParams> {
+        overrides::params(self.args, self.params, path)
+    }
+
+    /// Process a single source image, reporting its outcome
+    fn process(&self, source: &Source) -> Record {
+        let (args, start) = (self.args, Instant::now());
+        let path = &source.path;
+        let filename = &*display_name(path);
+        let mut dest = if args.in_place {
+            path.clone()
+        } else if args.preserve_structure {
+            args.output.join(&source.relative)
+        } else {
+            args.output.join(path.file_name().unwrap_or_default())
+        };
+        // Archives keep their extension, with the images inside them converted
+        if let Some(format) = args.format.filter(|_| !archive::is_archive(path)) {
+            dest.set_extension(format.extension());
+        } else if svg::is_svg(path) || raw::is_raw(path) {
+            // Formats which cannot be saved are saved as PNG
+            dest.set_extension("png");
+        } else if pdf::is_pdf(path) {
+            // Pages processed in place are saved beside the document as a multipage TIFF
+            dest.set_extension(if args.in_place { "tif" } else { "png" });
+        }
+
+        let progress = &self.progress;
+        progress.set_message(filename.to_string());
+        let mut record = Record::new(path);
+        let pre = args.pre_cmd.as_ref().filter(|_| !args.dry_run);
+        let result = pre.map_or(Ok(()), |command| command.run(path, &dest)).map_err(cpar::Error::from);
+        let result = result.and_then(|()| self.process_file(path, &dest, &mut record)).and_then(|outcome| {
+            if let (Some(command), Outcome::Saved(saved)) = (&args.post_cmd, &outcome) {
+                command.run(path, saved)?;
+            }
+            if let Some(crop) = record.crop.as_ref().filter(|_| args.write_crops) {
+                report::write_crop(&crop_sidecar(path), crop)?;
+            }
+            if let Some(state) = self.state.as_ref().filter(|_| !matches!(outcome, Outcome::Resumed)) {
+                state.complete(path)?;
+            }
+            if let Some(manifest) = &self.manifest {
+                match &outcome {
+                    Outcome::Saved(saved) => manifest.record(path, self.settings(path), Some(saved))?,
+                    Outcome::Flagged => manifest.record(path, self.settings(path), None)?,
+                    _ => {}
+                }
+            }
+            Ok(outcome)
+        });
+        progress.inc(1);
+        match result {
+            Ok(outcome) => {
+                let pages = record.pages.map(|n| format!(" ({} pages)", n)).unwrap_or_default();
+                record.status = match outcome {
+                    Outcome::Saved(saved) => {
+                        if saved != dest {
+                            info!(image = filename, dest:% = saved.display(); "Finished {} as {}{}", filename, saved.display(), pages);
+                        } else {
+                            info!(image = filename, dest:% = saved.display(); "Finished {}{}", filename, pages);
+                        }
+                        record.dest = Some(saved);
+                        Status::Saved
+                    },
+                    Outcome::Skipped => {
+                        info!(image = filename, dest:% = dest.display(); "Skipped {}: output exists", filename);
+                        record.dest = Some(dest);
+                        Status::Skipped
+                    },
+                    Outcome::Resumed => {
+                        info!(image = filename; "Skipped {}: completed by an earlier run", filename);
+                        Status::Skipped
+                    },
+                    Outcome::Unchanged => {
+                        info!(image = filename; "Skipped {}: unchanged since it was last processed", filename);
+                        Status::Skipped
+                    },
+                    Outcome::Flagged => {
+                        let flag = record.flag.as_deref().unwrap_or_default();
+                        warn!(image = filename, flag = flag; "Skipped {}: {}", filename, flag);
+                        Status::Skipped
+                    },
+                    Outcome::Reported => {
+                        record.dest = Some(dest);
+                        Status::Reported
+                    }
+                };
+            },
+            Err(err) => {
+                let failed = self.failed.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.set_prefix(format!("{} failed", failed));
+                error!(image = filename, error:% = err; "Failed {}: {}", filename, err);
+                if args.error_sidecars {
+                    if let Err(err) = self.keep_failed(path, &dest, &err.to_string()) {
+                        warn!(image = filename, error:% = err; "{}: failed to keep it for review: {}", filename, err);
+                    }
                 }
+                record.error = Some(err.to_string());
             }
         }
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&record, start.elapsed());
+        }
+        record
+    }
+
+    /// Write the error sidecar of an image which failed beside its destination, along with a copy
+    /// of the image itself if requested
+    fn keep_failed(&self, path: &Path, dest: &Path, error: &str) -> io::Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        report::write_error(&dest.with_extension("error.txt"), path, error, &self.args.settings)?;
+        if !self.args.copy_failed {
+            return Ok(());
+        }
+        let copy = dest.with_file_name(path.file_name().unwrap_or_default());
+        save::save_claimed(&copy, self.args.existing(), |copy| fs::copy(path, copy).map(drop))?;
+        Ok(())
+    }
 
-        // Check bottom edge of image
-        for x in 0..img.width() {
-            for y in (0..img.height()).rev() {
-                if img.get_pixel(x, y).to_luma().0[0] < y_threshold {
-                    y_thresholds.push(y);
-                    break;
+    /// Process a single image file, saving the result to the destination path
+    fn process_file(&self, path: &Path, dest: &Path, record: &mut Record) -> cpar::Result<Outcome> {
+        let args = self.args;
+        if args.existing() == Existing::Skip && !args.dry_run && dest.exists() {
+            return Ok(Outcome::Skipped);
+        }
+        if self.state.as_ref().is_some_and(|state| state.is_completed(path)) {
+            return Ok(Outcome::Resumed);
+        }
+        if let Some(manifest) = &self.manifest {
+            if manifest.is_unchanged(path, self.settings(path))? {
+                return Ok(Outcome::Unchanged);
+            }
+        }
+        let fixed = match args.apply_crops {
+            true => Some(report::read_crop(&crop_sidecar(path))?),
+            false => args.boxes.get(path).copied().or(self.lock)
+        };
+        let params = &*self.file_params(path);
+        if archive::is_archive(path) {
+            return self.process_archive(path, params, dest, fixed, record);
+        }
+        if let Some(frames) = decode_animation(path)? {
+            return self.process_animation(frames, path, params, dest, fixed, record);
+        }
+        let filename = &*display_name(path);
+        record.dpi = match (svg::is_svg(path), pdf::is_pdf(path)) {
+            (true, _) => Some(args.svg_dpi),
+            (_, true) => Some(args.pdf_dpi),
+            _ => dpi::read(path)
+        };
+        if let Some(pages) = pages::decode_pages(path, args.pdf_dpi)? {
+            return self.process_pages(pages, filename, params, dest, fixed, record);
+        }
+        if args.streamed_decode {
+            if let Some(outcome) = self.process_streamed(path, filename, params, dest, fixed, record)? {
+                return Ok(outcome);
+            }
+        }
+        let (img, orientation) = open(path, !args.no_auto_orient, args.svg_dpi)?;
+        record.orientation = orientation;
+        self.process_image(img, filename, params, dest, fixed, record)
+    }
+
+    /// Process a TIFF image stored in strips with --streamed-decode, detecting its edges a strip at
+    /// a time and then decoding only the strips within its crop. Returns `None` without processing
+    /// the image if it cannot be decoded a strip at a time, or its options need the whole image.
+    fn process_streamed(
+        &self,
+        path: &Path,
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Option<Outcome>> {
+        let args = self.args;
+        let params = &*dpi::scaled_params(args, params, record.dpi);
+        let whole = params.rotate.is_some() || params.deskew || params.detection.trim_aspect.is_some()
+            || args.content_guard.is_some() || route::uses(&args.route, Measure::Confidence);
+        if whole {
+            return Ok(None);
+        }
+        let Some(mut strips) = Strips::open(path, !args.no_auto_orient)? else {
+            return Ok(None);
+        };
+        let (width, height) = strips.dimensions();
+        let rect = match fixed {
+            Some(fixed) => {
+                let rect = clamped(&fixed, width, height)?;
+                debug!(image = name, crop:% = rect; "{}: cropped to given {}", name, rect);
+                rect
+            },
+            None => {
+                let Some(detection) = StreamedDetection::new(width, height, &params.detection) else {
+                    return Ok(None);
+                };
+                match matched(args, || strips.detect_edges(detection)?.ok_or(cpar::Error::NoEdges))? {
+                    Some(edges) => {
+                        let rect = cpar::crop_rect(&edges, &params.detection.extra, width, height);
+                        let rect = cpar::snap_aspect(&rect, &params.detection.snap_aspect, width, height);
+                        record.edges = Some(edges.into());
+                        log_edges(name, &edges, &rect);
+                        rect
+                    },
+                    None => {
+                        record.flag = Some("no edges detected".to_string());
+                        if args.on_blank() == Blank::Skip {
+                            return Ok(Some(Outcome::Flagged));
+                        }
+                        warn!(image = name; "{}: no edges detected, saving it uncropped", name);
+                        CropRect { x: 0, y: 0, width, height }
+                    }
                 }
             }
+        };
+        record.original = Some((width, height).into());
+
+        // Skip images failing a guard before decoding any of their crop
+        let Some(rect) = guarded(args, rect, (width, height), || None, name, record) else {
+            return Ok(Some(Outcome::Flagged));
+        };
+        let cropped = strips.crop(&rect)?;
+        self.save_crop(Decoded::Crop(cropped, (width, height)), rect, name, params, dest, record).map(Some)
+    }
+
+    /// Process a decoded image, saving the result to the destination path
+    fn process_image(
+        &self,
+        img: DynamicImage,
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let args = self.args;
+        let (img, degrees) = cpar::orient(img, params);
+        if degrees != 0 {
+            debug!(image = name, rotation = degrees; "{}: rotated by {}°", name, degrees);
+            record.rotation = Some(degrees);
         }
+        record.original = Some(img.dimensions().into());
 
-        // Safety!
-        if x_thresholds.is_empty() || y_thresholds.is_empty() {
-            panic!("Failed to detect sides of image");
+        // Replace the image when corrected, so that only one copy of it is held
+        let img = match params.deskew.then(|| cpar::deskew(&img, &params.detection)).flatten() {
+            Some((corrected, angle)) => {
+                record.skew = Some(angle);
+                corrected
+            },
+            None => img
+        };
+        if args.split {
+            let panels = cpar::split_panels(&img, &params.detection, args.min_gutter);
+            if panels.len() > 1 {
+                return self.process_panels(&img, &panels, name, params, dest, record);
+            }
         }
+        self.crop_image(img, name, params, dest, fixed, record)
+    }
 
-        // Determine percentile-based depth into image from sides to declare image edge
-        x_thresholds.sort_unstable();
-        y_thresholds.sort_unstable();
-        let x_percentile = (x_percentile * (x_thresholds.len() - 1) as f32).floor() as usize;
-        let y_percentile = (y_percentile * (y_thresholds.len() - 1) as f32).floor() as usize;
-        let x_edge = (*x_thresholds.get(x_percentile).unwrap() - x_extra).max(0);
-        let y_edge = (*y_thresholds.get(y_percentile).unwrap() - y_extra).max(0);
+    /// Crop a decoded and corrected image, saving the result to the destination path
+    fn crop_image(
+        &self,
+        img: DynamicImage,
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let args = self.args;
+        let params = &*dpi::scaled_params(args, params, record.dpi);
+        let rect = match fixed {
+            Some(fixed) => {
+                let rect = clamped(&fixed, img.width(), img.height())?;
+                debug!(image = name, crop:% = rect; "{}: cropped to given {}", name, rect);
+                rect
+            },
+            None => match matched(args, || cpar::detect_edges(&img, &params.detection).ok_or(cpar::Error::NoEdges))? {
+                Some(edges) => {
+                    let rect = cpar::crop_rect(&edges, &params.detection.extra, img.width(), img.height());
+                    let rect = cpar::snap_aspect(&rect, &params.detection.snap_aspect, img.width(), img.height());
+                    let rect = match params.detection.trim_aspect {
+                        Some(aspect) => cpar::trim_aspect(&img, &rect, aspect, params.detection.gravity),
+                        None => rect
+                    };
+                    record.edges = Some(edges.into());
+                    log_detection(name, &img, params, &edges, &rect);
+                    if let Some(dir) = &args.debug_output {
+                        save_diagnostic(&img, params, &edges, &mirrored_dest(dir, &args.output, dest, "debug.png"))?;
+                    }
+                    rect
+                },
+                None => {
+                    record.flag = Some("no edges detected".to_string());
+                    if args.on_blank() == Blank::Skip {
+                        return Ok(Outcome::Flagged);
+                    }
+                    warn!(image = name; "{}: no edges detected, saving it uncropped", name);
+                    CropRect { x: 0, y: 0, width: img.width(), height: img.height() }
+                }
+            }
+        };
+        let (img_width, img_height) = img.dimensions();
+        let content = || guard::content(args, &img, &rect, &params.detection);
+        let Some(rect) = guarded(args, rect, (img_width, img_height), content, name, record) else {
+            return Ok(Outcome::Flagged);
+        };
+        self.save_crop(Decoded::Whole(img), rect, name, params, dest, record)
+    }
 
-        // Determine new dimensions for image, such that it is downscaled, restoring aspect ratio
-        let f_width = img.width() as f32;
-        let f_height = img.height() as f32;
-        let x_rel_size = x_edge as f32 / f_width;
-        let y_rel_size = y_edge as f32 / f_height;
-        let [new_x, new_y] = if x_rel_size < y_rel_size {
-            [x_edge as f32, x_rel_size * f_height.floor()]
-        } else {
-            [y_rel_size * f_width, y_edge as f32]
+    /// Save the crop of a decoded image to the destination path, or report it without saving
+    fn save_crop(
+        &self,
+        decoded: Decoded,
+        rect: CropRect,
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let (args, progress) = (self.args, &self.progress);
+        // Crops decoded alone are only measured and processed within themselves, as the options
+        // needing the rest of the image decode it whole instead
+        let (img, (img_width, img_height), cropped) = match decoded {
+            Decoded::Whole(img) => {
+                let dimensions = img.dimensions();
+                (img, dimensions, false)
+            },
+            Decoded::Crop(img, dimensions) => (img, dimensions, true)
         };
+        let view = match cropped {
+            true => CropRect { x: 0, y: 0, ..rect },
+            false => rect
+        };
+        let (width, height) = cpar::output_size(img_width, img_height, &rect, params);
+        record.crop = Some(rect.into());
+        record.output = Some((width, height).into());
+        if let Some(dedupe) = args.dedupe {
+            if let Some(original) = self.seen.original(&img, &view, &record.source, args.dedupe_distance) {
+                let flag = format!("duplicate of {}", original.display());
+                record.duplicate_of = Some(original);
+                if dedupe == Dedupe::Skip {
+                    record.flag = Some(flag);
+                    return Ok(Outcome::Flagged);
+                }
+                warn!(image = name, flag = flag.as_str(); "{}: {}, saving it flagged", name, flag);
+                record.flag = Some(flag);
+            }
+        }
+        let routed = route::folder(&args.route, |measure| match measure {
+            Measure::Cropped => route::cropped(&rect, img_width, img_height),
+            Measure::Width => width as f32,
+            Measure::Height => height as f32,
+            Measure::Confidence => record.edges.as_ref()
+                .map_or(100.0, |edges| cpar::edge_confidence(&img, &params.detection, &edges.into()))
+        }).map(|folder| route::routed(dest, &args.output, folder));
+        let dest = routed.as_deref().unwrap_or(dest);
+        if args.inspect {
+            let heatmap = cpar::heatmap(&img, &rect, args.inspect_width);
+            progress.suspend(|| println!("{}: crop {}\n{}", name, rect, heatmap));
+        }
 
-        // Perform image processing
-        let cropped = img.crop_imm(0, 0, x_edge, y_edge);
-        let blurred = if let Some(sigma) = args.blur {
-            cropped.blur(sigma)
+        // Report without processing
+        if args.dry_run {
+            progress.suspend(|| println!(
+                "{}: {}x{}{} cropped to {}, {} {}x{}, saved to {}",
+                name, img_width, img_height, deskewed(record.skew), rect, restored(params), width, height,
+                dest.display()
+            ));
+            return Ok(Outcome::Reported);
+        }
+
+        // Save preview of crop in place of processed image
+        let (processed, dest) = if args.preview {
+            let preview = cpar::draw_crop(&img, &rect, args.preview_color);
+            (DynamicImage::ImageRgba8(preview), dest.with_extension("preview.png"))
         } else {
-            cropped
+            let processed = match &args.diff_output {
+                _ if cropped => cpar::apply_cropped(img, img_width, img_height, &rect, params),
+                Some(dir) => {
+                    let processed = cpar::apply(&img, &rect, params);
+                    save_diff(&img, &rect, &processed, args, &mirrored_dest(dir, &args.output, dest, "diff.png"))?;
+                    processed
+                },
+                None => cpar::apply_owned(img, &rect, params)
+            };
+            (processed, dest.to_path_buf())
+        };
+
+        let options = match args.preview {
+            true => SaveOptions { format: Some(ImageFormat::Png.into()), colorspace: None, ..args.save_options() },
+            false => SaveOptions {
+                xmp: args.xmp.then(|| {
+                    xmp::packet(img_width, img_height, &rect, record.orientation, record.rotation, record.skew, params.restore)
+                }),
+                dpi: dpi::output_dpi(args, record.dpi, (img_width, img_height), &rect, (width, height), params),
+                ..args.save_options()
+            }
+        };
+        let filter = params.filter;
+        let thumbnail = args.thumbnail.map(|size| fit(&processed, size, filter));
+        let sizes = self.sizes(&processed, |img, size| fit(img, size, filter));
+        let outcome = self.save_claimed(&dest, |dest| save::save(&processed, dest, &options))?;
+        // Scaled copies print at other sizes, so leave out the resolution of the processed image
+        let options = SaveOptions { dpi: None, ..options };
+        self.save_sizes(sizes, &outcome, |img, dest| save::save(&img, dest, &options))?;
+        self.save_thumbnail(thumbnail, outcome)
+    }
+
+    /// Crop each panel of a split image independently, saving them to separate files
+    fn process_panels(
+        &self,
+        img: &DynamicImage,
+        panels: &[CropRect],
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let mut first = None;
+        for (n, panel) in panels.iter().enumerate() {
+            let mut panel_record = Record::new(&record.source);
+            panel_record.original = Some((panel.width, panel.height).into());
+            let panel_name = format!("{} panel {}", name, n + 1);
+            let panel_img = img.crop_imm(panel.x, panel.y, panel.width, panel.height);
+            let outcome = self.crop_image(panel_img, &panel_name, params, &panel_dest(dest, n + 1), None, &mut panel_record)?;
+            if first.is_none() {
+                first = Some(outcome);
+                *record = Record { skew: record.skew, panels: Some(panels.len()), ..panel_record };
+            }
+        }
+        Ok(first.expect("split images have at least two panels"))
+    }
+
+    /// Crop each image in a ZIP archive independently, saving them to a new archive with the same
+    /// structure. Other entries are kept as they are, as are images which cannot be cropped.
+    fn process_archive(
+        &self,
+        path: &Path,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let name = &*display_name(path);
+        let entries = archive::read(path)?;
+        let is_image = |entry: &archive::Entry| ImageFormat::from_path(&entry.name).is_ok();
+        record.pages = Some(entries.iter().filter(|entry| is_image(entry)).count());
+        let entries: Vec<_> = entries.into_par_iter()
+            .flat_map_iter(|entry| {
+                if !is_image(&entry) {
+                    return vec![entry];
+                }
+                let entry_name = format!("{}/{}", name, entry.name);
+                match self.crop_entry(&entry, &entry_name, params, fixed) {
+                    Ok(Some(cropped)) => cropped,
+                    Ok(None) => vec![entry],
+                    Err(err) => {
+                        warn!(image = entry_name.as_str(), error:% = err; "{}: {}, keeping it as it is", entry_name, err);
+                        vec![entry]
+                    }
+                }
+            })
+            .collect();
+        if self.args.dry_run {
+            return Ok(Outcome::Reported);
+        }
+        self.save_claimed(dest, |dest| save::save_archive(&entries, dest))
+    }
+
+    /// Crop an image held in an archive, returning it encoded as an entry followed by its XMP
+    /// sidecar where the format cannot embed it. Returns `None` if the image is to be kept as it
+    /// is, as when its crop is flagged by a guard or only reported.
+    fn crop_entry(
+        &self,
+        entry: &archive::Entry,
+        name: &str,
+        params: &CropParams,
+        fixed: Option<CropRect>
+    ) -> cpar::Result<Option<Vec<archive::Entry>>> {
+        let args = self.args;
+        let Detected { img, rect, orientation, rotation, skew, input_format, dpi } = pipe::detect(entry.data.clone(), args, params)?;
+        let params = &*dpi::scaled_params(args, params, dpi);
+        let rect = match fixed {
+            Some(fixed) => clamped(&fixed, img.width(), img.height())?,
+            None => rect
+        };
+        let (img_width, img_height) = img.dimensions();
+        let flag = guard::undersized(args, &rect, img_width, img_height)
+            .or_else(|| guard::overcropped(args, &rect, img_width, img_height))
+            .or_else(|| guard::content(args, &img, &rect, &params.detection));
+        if let Some(flag) = flag {
+            warn!(image = name, flag = flag.as_str(); "{}: {}, keeping it uncropped", name, flag);
+            return Ok(None);
+        }
+        let (width, height) = cpar::output_size(img_width, img_height, &rect, params);
+        if args.dry_run {
+            self.progress.suspend(|| println!(
+                "{}: {}x{}{} cropped to {}, {} {}x{}",
+                name, img_width, img_height, deskewed(skew), rect, restored(params), width, height
+            ));
+            return Ok(None);
+        }
+
+        let processed = cpar::apply(&img, &rect, params);
+        let format = pipe::output_format(args, input_format);
+        let options = SaveOptions {
+            xmp: args.xmp.then(|| xmp::packet(img_width, img_height, &rect, orientation, rotation, skew, params.restore)),
+            dpi: dpi::output_dpi(args, dpi, (img_width, img_height), &rect, (width, height), params),
+            ..args.save_options()
+        };
+        let mut buffer = Cursor::new(Vec::new());
+        save::write(&processed, &mut buffer, format, &options)?;
+        save::verify(Cursor::new(buffer.get_ref()), &processed, format, &options)?;
+        let entry_name = match args.format {
+            Some(format) => Path::new(&entry.name).with_extension(format.extension()).to_string_lossy().into_owned(),
+            None => entry.name.clone()
+        };
+        let mut cropped = Vec::new();
+        if let Some(packet) = options.xmp.filter(|_| !xmp::embeds(format)) {
+            let name = format!("{}.xmp", entry_name);
+            cropped.push(archive::Entry { name, data: packet.into_bytes(), modified: entry.modified });
+        }
+        cropped.insert(0, archive::Entry { name: entry_name, data: buffer.into_inner(), modified: entry.modified });
+        Ok(Some(cropped))
+    }
+
+    /// Process each page of a multipage image independently, saving them to separate files or a
+    /// single multipage TIFF
+    fn process_pages(
+        &self,
+        pages: Vec<DynamicImage>,
+        name: &str,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let args = self.args;
+        record.pages = Some(pages.len());
+        if (args.pages == PageOutput::Split && !args.in_place) || args.dry_run || args.preview {
+            let mut first = None;
+            for (n, page) in pages.into_iter().enumerate() {
+                let mut page_record = Record::new(&record.source);
+                let page_name = format!("{} page {}", name, n + 1);
+                let outcome = self.process_image(page, &page_name, params, &pages::page_dest(dest, n + 1), fixed, &mut page_record)?;
+                if first.is_none() {
+                    first = Some(outcome);
+                    *record = Record { pages: record.pages, ..page_record };
+                }
+            }
+            return Ok(first.expect("multipage images have at least one page"));
+        }
+
+        // Reassemble processed pages into a multipage TIFF
+        record.original = Some(pages[0].dimensions().into());
+        let processed = pages.into_iter()
+            .map(|page| match fixed {
+                Some(fixed) => {
+                    let (page, _) = cpar::orient(page, params);
+                    let deskewed = params.deskew.then(|| cpar::deskew(&page, &params.detection)).flatten();
+                    let page = deskewed.map_or(page, |(page, _)| page);
+                    let rect = clamped(&fixed, page.width(), page.height())?;
+                    Ok(cpar::apply_owned(page, &rect, params))
+                },
+                None => cpar::process(&page, params)
+            })
+            .collect::<cpar::Result<Vec<_>>>()?;
+        let dest = match args.in_place {
+            true => dest.to_path_buf(),
+            false => dest.with_extension("tif")
+        };
+        let filter = params.filter;
+        let thumbnail = args.thumbnail.map(|size| fit(&processed[0], size, filter));
+        let sizes = self.sizes(&processed, |pages, size| pages.iter().map(|page| fit(page, size, filter)).collect());
+        let outcome = self.save_claimed(&dest, |dest| save::save_pages(processed, dest, &args.save_options()))?;
+        self.save_sizes(sizes, &outcome, |pages, dest| save::save_pages(pages, dest, &args.save_options()))?;
+        self.save_thumbnail(thumbnail, outcome)
+    }
+
+    /// Process all frames of an animated image, saving the result as an animated GIF
+    fn process_animation(
+        &self,
+        frames: Vec<Frame>,
+        path: &Path,
+        params: &CropParams,
+        dest: &Path,
+        fixed: Option<CropRect>,
+        record: &mut Record
+    ) -> cpar::Result<Outcome> {
+        let (args, progress) = (self.args, &self.progress);
+        let filename = &*display_name(path);
+        let first = DynamicImage::ImageRgba8(frames[0].buffer().clone());
+        record.original = Some(first.dimensions().into());
+        let rect = match fixed {
+            Some(fixed) => clamped(&fixed, first.width(), first.height())?,
+            None => match matched(args, || cpar::detect_frames(&frames, params))? {
+                Some((_, rect)) => rect,
+                None => {
+                    record.flag = Some("no edges detected".to_string());
+                    if args.on_blank() == Blank::Skip {
+                        return Ok(Outcome::Flagged);
+                    }
+                    warn!(image = filename; "{}: no edges detected, saving it uncropped", filename);
+                    CropRect { x: 0, y: 0, width: first.width(), height: first.height() }
+                }
+            }
+        };
+        let (width, height) = cpar::output_size(first.width(), first.height(), &rect, params);
+        record.crop = Some(rect.into());
+        record.output = Some((width, height).into());
+        if args.inspect {
+            let heatmap = cpar::heatmap(&first, &rect, args.inspect_width);
+            progress.suspend(|| println!("{}: crop {} over first frame\n{}", filename, rect, heatmap));
+        }
+
+        // Report without processing, with detection confidence unmeasured across frames
+        let dest = route::folder(&args.route, |measure| match measure {
+            Measure::Cropped => route::cropped(&rect, first.width(), first.height()),
+            Measure::Width => width as f32,
+            Measure::Height => height as f32,
+            Measure::Confidence => 100.0
+        }).map_or(dest.to_path_buf(), |folder| route::routed(dest, &args.output, folder));
+        let dest = dest.with_extension("gif");
+        if args.dry_run {
+            progress.suspend(|| println!(
+                "{}: {}x{} animation of {} frames cropped to {}, {} {}x{}, saved to {}",
+                filename, first.width(), first.height(), frames.len(), rect, restored(params), width, height,
+                dest.display()
+            ));
+            return Ok(Outcome::Reported);
+        }
+
+        // Save preview of crop over first frame in place of processed animation
+        if args.preview {
+            let preview = DynamicImage::ImageRgba8(cpar::draw_crop(&first, &rect, args.preview_color));
+            let options = SaveOptions { format: Some(ImageFormat::Png.into()), colorspace: None, ..args.save_options() };
+            return self.save_claimed(&dest.with_extension("preview.png"), |dest| save::save(&preview, dest, &options));
+        }
+        let processed = match fixed {
+            Some(_) => cpar::crop_frames(frames, &rect, params),
+            None => cpar::process_frames(frames, params, args.frame_crop)?
+        };
+        let filter = params.filter;
+        let thumbnail = args.thumbnail.map(|size| {
+            fit(&DynamicImage::ImageRgba8(processed[0].buffer().clone()), size, filter)
+        });
+        let sizes = self.sizes(&processed, |frames, size| {
+            frames.iter()
+                .map(|frame| {
+                    let scaled = fit(&DynamicImage::ImageRgba8(frame.buffer().clone()), size, filter);
+                    Frame::from_parts(scaled.to_rgba8(), 0, 0, frame.delay())
+                })
+                .collect()
+        });
+        let outcome = self.save_claimed(&dest, |dest| save::save_frames(processed, dest))?;
+        self.save_sizes(sizes, &outcome, save::save_frames)?;
+        self.save_thumbnail(thumbnail, outcome)
+    }
+
+    /// Scale processed output down to each of the requested sizes, from largest to smallest so that
+    /// each size is scaled from the one before
+    fn sizes<T>(&self, processed: &T, scale: impl Fn(&T, u32) -> T) -> Vec<(u32, T)> {
+        let mut sizes = self.args.sizes.clone();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        sizes.dedup();
+        let mut scaled: Vec<(u32, T)> = Vec::with_capacity(sizes.len());
+        for size in sizes {
+            let next = scale(scaled.last().map_or(processed, |(_, last)| last), size);
+            scaled.push((size, next));
+        }
+        scaled
+    }
+
+    /// Save each size of output once the output itself is saved, beside it with the size suffix
+    /// appended to its name, replacing any earlier copy
+    fn save_sizes<T>(
+        &self,
+        sizes: Vec<(u32, T)>,
+        outcome: &Outcome,
+        save: impl Fn(T, &Path) -> ImageResult<()>
+    ) -> cpar::Result<()> {
+        let Outcome::Saved(saved) = outcome else {
+            return Ok(());
         };
-        let scaled = blurred.resize_exact(
-            (new_x / args.downscale).floor() as u32,
-            (new_y / args.downscale).floor() as u32,
-            FilterType::Gaussian
-        );
+        for (size, scaled) in sizes {
+            save(scaled, &save::affixed(saved, "", &self.args.size_suffix.replace("{size}", &size.to_string())))?;
+        }
+        Ok(())
+    }
+
+    /// Save the thumbnail of an image once the image itself is saved, mirroring its path within the
+    /// thumbnail folder, and replacing any earlier thumbnail
+    fn save_thumbnail(&self, thumbnail: Option<DynamicImage>, outcome: Outcome) -> cpar::Result<Outcome> {
+        if let (Some(thumbnail), Outcome::Saved(saved)) = (thumbnail, &outcome) {
+            let output = &self.args.output;
+            let relative = saved.strip_prefix(output).unwrap_or(Path::new(saved.file_name().unwrap_or_default()));
+            let dest = output.join(THUMBNAIL_DIR).join(relative);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            save::save(&thumbnail, &dest, &self.args.save_options())?;
+        }
+        Ok(outcome)
+    }
+
+    /// Claim destination according to the existing file policy and save to it, creating any
+    /// mirrored subdirectories
+    fn save_claimed(&self, dest: &Path, save: impl FnOnce(&Path) -> ImageResult<()>) -> cpar::Result<Outcome> {
+        if self.args.in_place {
+            return self.save_in_place(dest, save);
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(match save::save_claimed(dest, self.args.existing(), save)? {
+            Some(dest) => Outcome::Saved(dest),
+            None => Outcome::Skipped
+        })
+    }
+
+    /// Save over a source image, keeping it as a backup if requested. The image is saved beside
+    /// the source and then renamed over it, so that the source is not lost if saving fails.
+    fn save_in_place(&self, dest: &Path, save: impl FnOnce(&Path) -> ImageResult<()>) -> cpar::Result<Outcome> {
+        let backup = self.args.backup_suffix.as_ref().filter(|_| dest.exists()).map(|suffix| {
+            let mut backup = dest.as_os_str().to_owned();
+            backup.push(suffix);
+            PathBuf::from(backup)
+        });
+        // Never replace an earlier backup, which may be the only original left
+        if let Some(backup) = backup.as_ref().filter(|backup| backup.exists()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("backup {} already exists", backup.display())
+            ).into());
+        }
+        let partial = partial_dest(dest);
+        if let Err(err) = save(&partial) {
+            let _ = fs::remove_file(&partial);
+            return Err(err.into());
+        }
+        if let Some(backup) = &backup {
+            fs::rename(dest, backup)?;
+        }
+        fs::rename(&partial, dest)?;
+        Ok(Outcome::Saved(dest.to_path_buf()))
+    }
+}
+
+/// Log details of edge detection for diagnosing crops, computing them only at the levels logged
+fn log_detection(name: &str, img: &DynamicImage, params: &CropParams, edges: &Sides<u32>, rect: &CropRect) {
+    let detection = &params.detection;
+    if detection.auto_threshold && log_enabled!(Level::Debug) {
+        let threshold = cpar::auto_threshold(img, detection.mode);
+        debug!(image = name; "{}: automatic threshold {:?}", name, threshold);
+    }
+    if let Some(percent) = detection.range_threshold.filter(|_| log_enabled!(Level::Debug)) {
+        let threshold = cpar::range_threshold(img, detection.mode, percent);
+        debug!(image = name; "{}: threshold {:?} at {}% of range", name, threshold, percent);
+    }
+    log_edges(name, edges, rect);
+    if log_enabled!(Level::Trace) {
+        let boundary = cpar::boundary(img, detection);
+        for (side, points) in [
+            ("left", boundary.left),
+            ("right", boundary.right),
+            ("top", boundary.top),
+            ("bottom", boundary.bottom)
+        ] {
+            let histogram = logging::histogram(points.into_iter().map(|(_, depth)| depth));
+            trace!(image = name, side = side; "{}: {} threshold depths (depth:rows) {}", name, side, histogram);
+        }
+    }
+}
+
+/// Log the edges detected in an image and the crop placed from them
+fn log_edges(name: &str, edges: &Sides<u32>, rect: &CropRect) {
+    debug!(
+        image = name, left = edges.left, right = edges.right, top = edges.top, bottom = edges.bottom,
+        crop:% = rect;
+        "{}: edges left {}, right {}, top {}, bottom {}, cropped to {}",
+        name, edges.left, edges.right, edges.top, edges.bottom, rect
+    );
+}
+
+/// Run detection, which with --passthrough-unmatched finds no edges rather than failing if it
+/// panics. Returns `None` if no edges were found and the image is to be skipped or saved uncropped
+/// as --on-blank directs.
+fn matched<T>(args: &Cpar, detect: impl FnOnce() -> cpar::Result<T>) -> cpar::Result<Option<T>> {
+    let found = match args.passthrough_unmatched {
+        true => panic::catch_unwind(AssertUnwindSafe(detect)).unwrap_or(Err(cpar::Error::NoEdges)),
+        false => detect()
+    };
+    match (found, args.on_blank()) {
+        (Err(cpar::Error::NoEdges), Blank::Skip | Blank::Copy) => Ok(None),
+        (found, _) => found.map(Some)
+    }
+}
+
+/// Crop kept by the guards against undersized and overcropped crops and the content guard, whose
+/// flag is given by content, or `None` if the image is to be skipped as flagged. Crops the guards
+/// save uncropped span the whole image of the given dimensions.
+fn guarded(
+    args: &Cpar,
+    rect: CropRect,
+    (width, height): (u32, u32),
+    content: impl FnOnce() -> Option<String>,
+    name: &str,
+    record: &mut Record
+) -> Option<CropRect> {
+    match guard::flagged(args, &rect, (width, height), content) {
+        Some((flag, fallback)) => match fallback {
+            Fallback::Skip => {
+                record.flag = Some(flag);
+                None
+            },
+            Fallback::Uncropped => {
+                warn!(image = name, flag = flag.as_str(); "{}: {}, saving it uncropped", name, flag);
+                record.flag = Some(flag);
+                Some(CropRect { x: 0, y: 0, width, height })
+            }
+        },
+        None => Some(rect)
+    }
+}
+
+/// Crop given in place of detection clamped within an image of the given dimensions, for
+/// differently sized images
+fn clamped(fixed: &CropRect, width: u32, height: u32) -> cpar::Result<CropRect> {
+    let bounds = CropRect { x: 0, y: 0, width, height };
+    fixed.intersection(&bounds).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("crop {} lies outside image", fixed)).into()
+    })
+}
 
-        // Save image
-        let filename = path.file_name().unwrap().to_str().unwrap();
-        let dest = args.output.join(filename);
-        scaled.save(&dest).expect("Failed to save output");
+/// Sidecar file beside a source image holding its crop
+fn crop_sidecar(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".crop.json");
+    source.with_file_name(name)
+}
+
+/// Save a comparison of an image before and after processing
+fn save_diff(img: &DynamicImage, rect: &CropRect, processed: &DynamicImage, args: &Cpar, dest: &Path) -> cpar::Result<()> {
+    let comparison = match args.diff_mode {
+        DiffMode::SideBySide => cpar::side_by_side(img, rect, processed, args.preview_color),
+        DiffMode::Blend => cpar::blended(img, rect, processed)
+    };
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
     }
+    comparison.save_with_format(dest, ImageFormat::Png)?;
     Ok(())
 }
+
+/// Save a diagnostic image of the boundary points from which edges were selected
+fn save_diagnostic(img: &DynamicImage, params: &CropParams, edges: &Sides<u32>, dest: &Path) -> cpar::Result<()> {
+    // Gradient, variance and exact detection measure whole rows and columns, and bbox detection
+    // regions, rather than scanning for points
+    let boundary = match params.detection.mode {
+        DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact | DetectMode::Bbox { .. } => {
+            Sides { left: Vec::new(), right: Vec::new(), top: Vec::new(), bottom: Vec::new() }
+        },
+        _ => cpar::boundary(img, &params.detection)
+    };
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    cpar::draw_boundary(img, &boundary, edges).save_with_format(dest, ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Destination within dir of a diagnostic or comparison image of an image saved to dest, mirroring
+/// its path within the output folder with the given extension
+fn mirrored_dest(dir: &Path, output: &Path, dest: &Path, extension: &str) -> PathBuf {
+    dir.join(dest.strip_prefix(output).unwrap_or(dest)).with_extension(extension)
+}
+
+/// Temporary destination beside an image being replaced, keeping its extension so that it is saved
+/// in the same format
+fn partial_dest(dest: &Path) -> PathBuf {
+    save::affixed(dest, ".", ".partial")
+}
+
+/// Destination of a single panel of a split image
+fn panel_dest(dest: &Path, panel: usize) -> PathBuf {
+    save::affixed(dest, "", &format!("_{}", panel))
+}
+
+/// Filename of a path for logging, replacing any part which is not valid UTF-8
+fn display_name(path: &Path) -> Cow<'_, str> {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy()
+}
+
+/// Scale an image down to fit within size by size pixels, keeping its aspect ratio
+fn fit(img: &DynamicImage, size: u32, filter: FilterType) -> DynamicImage {
+    match img.width() > size || img.height() > size {
+        true => img.resize(size, size, filter),
+        false => img.clone()
+    }
+}
+
+/// Describe the rotation corrected before cropping, for reporting
+fn deskewed(skew: Option<f32>) -> String {
+    skew.map(|angle| format!(" deskewed by {:.2}°,", angle)).unwrap_or_default()
+}
+
+/// Describe how dimensions are restored, for reporting
+fn restored(params: &CropParams) -> &'static str {
+    match params.restore {
+        RestoreMode::Resize => "resized to",
+        RestoreMode::Pad => "padded to",
+        RestoreMode::Crop => "kept at"
+    }
+}
+
+/// Decode an image, rotating and flipping it as displayed according to its EXIF orientation if
+/// requested. Returns the EXIF orientation applied along with the image, where it was not upright.
+fn decode<R: BufRead + Seek>(reader: ImageReader<R>, auto_orient: bool) -> ImageResult<(DynamicImage, Option<u8>)> {
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    if !auto_orient || orientation == Orientation::NoTransforms {
+        return Ok((img, None));
+    }
+    img.apply_orientation(orientation);
+    Ok((img, Some(orientation.to_exif())))
+}
+
+/// Decode an image file, with libheif if it is a HEIF image or rawloader if it is a camera RAW image,
+/// or rasterizing it at a resolution in dots per inch if it is an SVG image. PDF documents give their
+/// first page at the default resolution, as their pages are rendered by [`pages::decode_pages`].
+/// Returns the EXIF orientation applied as [`decode`] does, which is not known of HEIF and RAW images
+/// as their decoders orient them.
+fn open(path: &Path, auto_orient: bool, svg_dpi: f32) -> cpar::Result<(DynamicImage, Option<u8>)> {
+    if svg::is_svg(path) {
+        return Ok((svg::rasterize(path, svg_dpi)?, None));
+    }
+    if pdf::is_pdf(path) {
+        return Ok((pdf::render(path, pdf::DEFAULT_DPI)?.swap_remove(0), None));
+    }
+    if raw::is_raw(path) {
+        return Ok((raw::decode(path, auto_orient)?, None));
+    }
+    match heif::is_heif(path) {
+        true => Ok((heif::decode(path, auto_orient)?, None)),
+        false => Ok(decode(ImageReader::open(path)?, auto_orient)?)
+    }
+}
+
+/// Decode all frames of an image if it is an animated GIF or WebP
+fn decode_animation(path: &Path) -> cpar::Result<Option<Vec<Frame>>> {
+    let reader = || -> io::Result<_> { Ok(BufReader::new(File::open(path)?)) };
+    let frames = match ImageFormat::from_path(path) {
+        Ok(ImageFormat::Gif) => GifDecoder::new(reader()?)?.into_frames().collect_frames()?,
+        Ok(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader()?)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames().collect_frames()?
+        },
+        _ => return Ok(None)
+    };
+    Ok(Some(frames).filter(|frames| frames.len() > 1))
+}
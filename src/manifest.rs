@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use crate::report::{from_lossless, from_lossless_option, lossless, lossless_option};
+
+/// Name of the manifest kept within the output folder by default
+pub const MANIFEST_FILE: &str = ".cpar-manifest.jsonl";
+
+/// Source image processed by a run, as one line of the manifest
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    #[serde(serialize_with = "lossless", deserialize_with = "from_lossless")]
+    source: PathBuf,
+    size: u64,
+    /// Nanoseconds since the Unix epoch
+    modified: u64,
+    /// CRC-32 checksum of the contents of the source
+    checksum: u32,
+    /// CRC-32 checksum of the settings it was processed with
+    settings: u32,
+    /// Output saved, which is processed again if it no longer exists
+    #[serde(serialize_with = "lossless_option", deserialize_with = "from_lossless_option")]
+    dest: Option<PathBuf>
+}
+
+/// Sources processed by previous runs and the settings they were processed with, appended to as
+/// each source of this run is processed
+pub struct Manifest {
+    entries: HashMap<PathBuf, Entry>,
+    /// Manifest to record processed sources in, unless only reading it
+    file: Option<Mutex<File>>
+}
+
+impl Manifest {
+    /// Load the manifest, keeping the last entry of each source and creating it if it does not
+    /// exist and processed sources are to be recorded. A line left incomplete by an interrupted run
+    /// is ignored.
+    pub fn open(path: &Path, record: bool) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err)
+        };
+        let entries: HashMap<_, _> = contents.lines()
+            .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+            .map(|entry| (entry.source.clone(), entry))
+            .collect();
+        if !record {
+            return Ok(Self { entries, file: None });
+        }
+
+        // Compact entries superseded by later runs before appending to them
+        let compacted = path.with_extension("jsonl.tmp");
+        let mut writer = BufWriter::new(File::create(&compacted)?);
+        for entry in entries.values() {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&compacted, path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { entries, file: Some(Mutex::new(file)) })
+    }
+
+    /// Check whether a source was processed by a previous run with the same settings, is unchanged
+    /// since, and its output still exists. Sources whose modification time changed are compared by
+    /// checksum, recording the new time when their contents are unchanged.
+    pub fn is_unchanged(&self, path: &Path, settings: u32) -> io::Result<bool> {
+        let Some(entry) = self.entries.get(path).filter(|entry| entry.settings == settings) else {
+            return Ok(false);
+        };
+        if entry.dest.as_ref().is_some_and(|dest| !dest.exists()) {
+            return Ok(false);
+        }
+        let (size, modified) = metadata(path)?;
+        if size != entry.size {
+            return Ok(false);
+        }
+        if modified == entry.modified {
+            return Ok(true);
+        }
+        if crc32fast::hash(&fs::read(path)?) != entry.checksum {
+            return Ok(false);
+        }
+        self.append(&Entry { modified, ..entry.clone() })?;
+        Ok(true)
+    }
+
+    /// Record a source as processed with the given settings, writing it through to the manifest at
+    /// once
+    pub fn record(&self, path: &Path, settings: u32, dest: Option<&Path>) -> io::Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        let (size, modified) = metadata(path)?;
+        let checksum = crc32fast::hash(&fs::read(path)?);
+        self.append(&Entry { source: path.to_path_buf(), size, modified, checksum, settings, dest: dest.map(Path::to_path_buf) })
+    }
+
+    fn append(&self, entry: &Entry) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+/// Size and modification time of a file, in nanoseconds since the Unix epoch
+fn metadata(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok((metadata.len(), modified.as_nanos() as u64))
+}
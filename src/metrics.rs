@@ -0,0 +1,191 @@
+use std::fmt::Write;
+use std::io;
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use tiny_http::{Header, Response, Server};
+use crate::report::{Record, Status};
+use crate::route;
+
+/// Upper bounds of the buckets of the processing time histogram, in seconds
+const SECONDS_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Upper bounds of the buckets of the cropped area histogram, in percent
+const CROPPED_BUCKETS: [f64; 8] = [1.0, 5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 100.0];
+
+/// Where metrics of processed images are sent
+#[derive(Clone, Debug)]
+pub enum Sink {
+    /// Serve metrics in the Prometheus text format at /metrics on an address for the duration of the
+    /// run
+    Prometheus(String),
+    /// Send a StatsD packet for each processed image to an address
+    Statsd(String)
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, address) = s.split_once(':').unwrap_or((s, ""));
+        let (host, default_port) = match kind {
+            "prometheus" => ("127.0.0.1", "9090"),
+            "statsd" => ("127.0.0.1", "8125"),
+            _ => return Err(format!("unknown metrics sink '{}', expected prometheus or statsd", kind))
+        };
+        let address = match address.rsplit_once(':') {
+            Some(_) => address.to_string(),
+            None if address.is_empty() => format!("{}:{}", host, default_port),
+            None => format!("{}:{}", host, address)
+        };
+        if address.rsplit_once(':').is_none_or(|(_, port)| port.parse::<u16>().is_err()) {
+            return Err(format!("invalid metrics address '{}', expected [HOST:]PORT", address));
+        }
+        Ok(match kind {
+            "prometheus" => Sink::Prometheus(address),
+            _ => Sink::Statsd(address)
+        })
+    }
+}
+
+/// Counts and histograms of the images processed so far
+#[derive(Default)]
+pub struct Totals {
+    /// Images processed with each status, in the order of [`STATUSES`]
+    images: [u64; 4],
+    /// Time taken to process each image, in seconds
+    seconds: Histogram,
+    /// Percentage of the area of each image cropped away
+    cropped: Histogram
+}
+
+/// Statuses counted by [`Totals`]
+const STATUSES: [Status; 4] = [Status::Saved, Status::Reported, Status::Skipped, Status::Failed];
+
+/// Count of observations at or below the bound of each bucket, with their count and sum
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64
+}
+
+impl Histogram {
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        self.buckets.resize(bounds.len(), 0);
+        for (bucket, &bound) in self.buckets.iter_mut().zip(bounds) {
+            *bucket += (value <= bound) as u64;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Append the histogram's lines in the Prometheus text format
+    fn write(&self, out: &mut String, name: &str, help: &str, bounds: &[f64]) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (i, bound) in bounds.iter().enumerate() {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, self.buckets.get(i).copied().unwrap_or(0));
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, self.sum, name, self.count);
+    }
+}
+
+/// Metrics of a run, recorded as each image is processed
+pub enum Metrics {
+    Prometheus(Arc<Mutex<Totals>>),
+    Statsd(UdpSocket)
+}
+
+impl Metrics {
+    /// Start serving or connect to the sink of metrics
+    pub fn start(sink: &Sink) -> io::Result<Self> {
+        match sink {
+            Sink::Prometheus(address) => {
+                let server = Server::http(address.as_str()).map_err(|err| io::Error::other(format!("{}: {}", address, err)))?;
+                info!("Serving metrics on http://{}/metrics", address);
+                let totals = Arc::new(Mutex::new(Totals::default()));
+                let served = Arc::clone(&totals);
+                thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        let response = match request.url() {
+                            "/metrics" => Response::from_string(served.lock().unwrap().exposition())
+                                .with_header("Content-Type: text/plain; version=0.0.4".parse::<Header>().unwrap()),
+                            _ => Response::from_string("Not found").with_status_code(404)
+                        };
+                        if let Err(err) = request.respond(response) {
+                            warn!(error:% = err; "Failed to respond to metrics request: {}", err);
+                        }
+                    }
+                });
+                Ok(Metrics::Prometheus(totals))
+            },
+            Sink::Statsd(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address.as_str()).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", address, err)))?;
+                Ok(Metrics::Statsd(socket))
+            }
+        }
+    }
+
+    /// Record the outcome of processing an image and the time it took
+    pub fn record(&self, record: &Record, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let cropped = record.original.as_ref().zip(record.crop.as_ref())
+            .map(|(original, &crop)| route::cropped(&crop.into(), original.width, original.height) as f64);
+        match self {
+            Metrics::Prometheus(totals) => {
+                let mut totals = totals.lock().unwrap();
+                let index = STATUSES.iter().position(|&status| status == record.status).expect("every status is counted");
+                totals.images[index] += 1;
+                totals.seconds.observe(&SECONDS_BUCKETS, seconds);
+                if let Some(cropped) = cropped {
+                    totals.cropped.observe(&CROPPED_BUCKETS, cropped);
+                }
+            },
+            Metrics::Statsd(socket) => {
+                let mut packet = format!("cpar.images.{}:1|c", status(record.status));
+                let _ = write!(packet, "\ncpar.image.time:{:.3}|ms", seconds * 1000.0);
+                if record.status == Status::Failed {
+                    packet.push_str("\ncpar.errors:1|c");
+                }
+                if let Some(cropped) = cropped {
+                    let _ = write!(packet, "\ncpar.image.cropped:{:.2}|h", cropped);
+                }
+                // Metrics are best effort, so a lost packet does not fail the image
+                if let Err(err) = socket.send(packet.as_bytes()) {
+                    warn!(error:% = err; "Failed to send metrics: {}", err);
+                }
+            }
+        }
+    }
+}
+
+impl Totals {
+    /// Metrics in the Prometheus text exposition format
+    fn exposition(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP cpar_images_total Images processed, by outcome\n# TYPE cpar_images_total counter\n");
+        for (&counted, count) in STATUSES.iter().zip(self.images) {
+            let _ = writeln!(out, "cpar_images_total{{status=\"{}\"}} {}", status(counted), count);
+        }
+        out.push_str("# HELP cpar_errors_total Images which failed\n# TYPE cpar_errors_total counter\n");
+        let _ = writeln!(out, "cpar_errors_total {}", self.images[3]);
+        self.seconds.write(&mut out, "cpar_image_seconds", "Time taken to process each image", &SECONDS_BUCKETS);
+        let help = "Percentage of the area of each image cropped away";
+        self.cropped.write(&mut out, "cpar_image_cropped_percent", help, &CROPPED_BUCKETS);
+        out
+    }
+}
+
+fn status(status: Status) -> &'static str {
+    match status {
+        Status::Saved => "saved",
+        Status::Reported => "reported",
+        Status::Skipped => "skipped",
+        Status::Failed => "failed"
+    }
+}
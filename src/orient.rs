@@ -0,0 +1,87 @@
+use image::{DynamicImage, GenericImageView};
+use crate::detect::thresholds;
+use crate::{CropParams, EdgeDetection, Rotation};
+
+/// Longest side of the copy of an image whose text lines are measured
+const SAMPLE_SIZE: u32 = 1024;
+
+/// Factor by which the ink of one axis must vary more from line to line than the other for lines
+/// of text to be taken as running across it
+const LINE_CONTRAST: f64 = 1.5;
+
+/// Rotate an image by quarter turns before its crop is detected, as set by the parameters.
+/// Returns the clockwise rotation applied in degrees along with the image.
+pub fn orient(img: DynamicImage, params: &CropParams) -> (DynamicImage, u16) {
+    let degrees = match params.rotate {
+        None => 0,
+        Some(Rotation::Rotate90) => 90,
+        Some(Rotation::Rotate180) => 180,
+        Some(Rotation::Rotate270) => 270,
+        Some(Rotation::Auto) => text_rotation(&img, &params.detection)
+    };
+    let img = match degrees {
+        90 => img.rotate90(),
+        180 => img.rotate180(),
+        270 => img.rotate270(),
+        _ => img
+    };
+    (img, degrees)
+}
+
+/// Estimate the clockwise rotation in degrees, 0, 90 or 270, which turns the lines of text of a
+/// page upright.
+///
+/// Lines run across the axis whose rows or columns of artwork vary most in ink from one to the
+/// next, alternating between lines and the gaps between them. The lines of a sideways page are
+/// taken to begin at the end where they are most evenly aligned, as left aligned text is, so upside
+/// down pages are not detected.
+pub fn text_rotation(img: &DynamicImage, detection: &EdgeDetection) -> u16 {
+    let sample = match img.width().max(img.height()) > SAMPLE_SIZE {
+        true => img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE),
+        false => img.clone()
+    };
+    let (width, height) = sample.dimensions();
+    let threshold = thresholds(&sample, detection).left;
+    let mode = detection.mode;
+    let ink: Vec<bool> = sample.to_rgba16().pixels().map(|&pixel| mode.is_content(pixel, threshold)).collect();
+    let inked = |x: u32, y: u32| ink[(y * width + x) as usize];
+
+    let rows: Vec<u32> = (0..height).map(|y| (0..width).filter(|&x| inked(x, y)).count() as u32).collect();
+    let columns: Vec<u32> = (0..width).map(|x| (0..height).filter(|&y| inked(x, y)).count() as u32).collect();
+    if variation(&columns) <= variation(&rows) * LINE_CONTRAST {
+        return 0;
+    }
+
+    // Lines now run down the columns, beginning at the top if the page was turned clockwise
+    let (mut starts, mut ends) = (Vec::new(), Vec::new());
+    for x in 0..width {
+        if let Some(start) = (0..height).find(|&y| inked(x, y)) {
+            starts.push(start);
+            ends.push((0..height).rev().find(|&y| inked(x, y)).unwrap_or(start));
+        }
+    }
+    match spread(&mut starts) <= spread(&mut ends) {
+        true => 270,
+        false => 90
+    }
+}
+
+/// Total change in ink from each row or column to the next, relative to the total ink
+fn variation(profile: &[u32]) -> f64 {
+    let total: u64 = profile.iter().map(|&ink| ink as u64).sum();
+    let change: u64 = profile.windows(2).map(|pair| pair[0].abs_diff(pair[1]) as u64).sum();
+    match total {
+        0 => 0.0,
+        _ => change as f64 / total as f64
+    }
+}
+
+/// Interquartile range of positions
+fn spread(positions: &mut [u32]) -> u32 {
+    if positions.is_empty() {
+        return 0;
+    }
+    positions.sort_unstable();
+    let quartile = |n: usize| positions[(positions.len() - 1) * n / 4];
+    quartile(3) - quartile(1)
+}
@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use cpar::{CropParams, Sides};
+use crate::{parse_threshold, Cpar, Threshold};
+
+/// Columns a parameter manifest may have besides path
+const COLUMNS: [&str; 3] = ["threshold", "percentile", "extra"];
+
+/// Detection options given for one image by a parameter manifest, where those left empty keep the
+/// options given for the run
+#[derive(Clone, Copy, Default)]
+pub struct Override {
+    threshold: Option<Threshold>,
+    percentile: Option<u8>,
+    extra: Option<i32>
+}
+
+/// Overrides read from a parameter manifest, by the canonical path of the image they are for
+pub type Overrides = HashMap<PathBuf, Override>;
+
+impl Override {
+    /// Parameters with the options of the override replacing those of the run on every side
+    fn apply<'a>(&self, params: &'a CropParams) -> Cow<'a, CropParams> {
+        if self.threshold.is_none() && self.percentile.is_none() && self.extra.is_none() {
+            return Cow::Borrowed(params);
+        }
+        let mut params = params.clone();
+        let detection = &mut params.detection;
+        match self.threshold {
+            Some(Threshold::Auto) => {
+                detection.auto_threshold = true;
+                detection.range_threshold = None;
+            },
+            Some(Threshold::Value(threshold)) => {
+                detection.threshold = Sides::all(threshold);
+                detection.auto_threshold = false;
+                detection.range_threshold = None;
+            },
+            None => ()
+        }
+        if let Some(percentile) = self.percentile {
+            detection.percentile = Sides::all(percentile);
+        }
+        if let Some(extra) = self.extra {
+            detection.extra = Sides::all(extra);
+        }
+        Cow::Owned(params)
+    }
+}
+
+impl fmt::Display for Override {
+    /// Options of the override, recorded with its image by --incremental
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.threshold {
+            Some(Threshold::Auto) => write!(f, " --threshold=auto")?,
+            Some(Threshold::Value(threshold)) => write!(f, " --threshold={}", threshold)?,
+            None => ()
+        }
+        if let Some(percentile) = self.percentile {
+            write!(f, " --percentile={}", percentile)?;
+        }
+        if let Some(extra) = self.extra {
+            write!(f, " --extra={}", extra)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a parameter manifest, a CSV file with a header naming its columns: path, and any of
+/// threshold, percentile and extra
+pub fn load(path: &Path) -> io::Result<Overrides> {
+    let text = fs::read_to_string(path).map_err(|err| {
+        io::Error::new(err.kind(), format!("failed to read parameter manifest '{}': {}", path.display(), err))
+    })?;
+    parse(&text, &path.to_string_lossy()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Parse the rows of a parameter manifest read from a path
+fn parse(text: &str, s: &str) -> Result<Overrides, String> {
+    let mut rows = text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| (n + 1, line));
+    let context = |n: usize, err: String| format!("{}:{}: {}", s, n, err);
+
+    let (n, header) = rows.next().ok_or_else(|| format!("parameter manifest '{}' is empty", s))?;
+    let header = fields(header).map_err(|err| context(n, err))?;
+    if let Some(unknown) = header.iter().find(|column| *column != "path" && !COLUMNS.contains(&column.as_str())) {
+        return Err(context(n, format!("unknown column '{}', expected path, threshold, percentile or extra", unknown)));
+    }
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let path = column("path").ok_or_else(|| context(n, "missing path column".to_string()))?;
+    let [threshold, percentile, extra] = COLUMNS.map(column);
+
+    let mut overrides = Overrides::new();
+    for (n, row) in rows {
+        let row = fields(row).map_err(|err| context(n, err))?;
+        if row.len() > header.len() {
+            return Err(context(n, format!("expected at most {} cells, found {}", header.len(), row.len())));
+        }
+        // Cells missing from the end of a row, like empty ones, keep the options of the run
+        let cell = |at: Option<usize>| at.and_then(|at| row.get(at)).map(String::as_str).filter(|cell| !cell.is_empty());
+        let Some(source) = cell(Some(path)) else {
+            return Err(context(n, "missing path".to_string()));
+        };
+        let entry = Override {
+            threshold: cell(threshold).map(parse_threshold).transpose().map_err(|err| context(n, err))?,
+            percentile: cell(percentile)
+                .map(|cell| cell.parse::<u8>().ok().filter(|percentile| *percentile <= 100)
+                    .ok_or_else(|| format!("invalid percentile '{}', expected 0 to 100", cell)))
+                .transpose()
+                .map_err(|err| context(n, err))?,
+            extra: cell(extra)
+                .map(|cell| cell.parse::<i32>().map_err(|_| format!("invalid extra '{}', expected a number of pixels", cell)))
+                .transpose()
+                .map_err(|err| context(n, err))?
+        };
+        overrides.insert(canonical(Path::new(source)), entry);
+    }
+    Ok(overrides)
+}
+
+/// Split a CSV row into its cells, unquoting those in double quotes and trimming the others
+fn fields(row: &str) -> Result<Vec<String>, String> {
+    let mut cells = Vec::new();
+    let mut chars = row.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let mut cell = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => cell.push('"'),
+                    Some('"') => break,
+                    Some(c) => cell.push(c),
+                    None => return Err("unterminated quoted cell".to_string())
+                }
+            }
+            while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+            match chars.peek() {
+                None | Some(',') => (),
+                Some(_) => return Err("unexpected text after quoted cell".to_string())
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                cell.push(c);
+            }
+            cell.truncate(cell.trim_end().len());
+        }
+        cells.push(cell);
+        if chars.next().is_none() {
+            return Ok(cells);
+        }
+    }
+}
+
+/// Path an image is looked up by, the same however it was given when it exists
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Override given for an image by the parameter manifest, if any
+pub fn find<'a>(args: &'a Cpar, path: &Path) -> Option<&'a Override> {
+    args.overrides.as_ref()?.get(&canonical(path))
+}
+
+/// Parameters to process an image with, with those given by the parameter manifest for it
+/// replacing the options of the run
+pub fn params<'a>(args: &Cpar, params: &'a CropParams, path: &Path) -> Cow<'a, CropParams> {
+    match find(args, path) {
+        Some(entry) => entry.apply(params),
+        None => Cow::Borrowed(params)
+    }
+}
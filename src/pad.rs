@@ -0,0 +1,53 @@
+use image::{imageops, DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage};
+
+/// Pad an image with a color to the given dimensions, centering it and keeping its bit depth.
+///
+/// Images gain color or alpha channels if the padding color requires them.
+pub fn pad(img: &DynamicImage, width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+    let x = width.saturating_sub(img.width()) / 2;
+    let y = height.saturating_sub(img.height()) / 2;
+    pad_at(img, width, height, x, y, color)
+}
+
+/// Pad an image with a color to the given dimensions, placing its top left corner at a position,
+/// as [`pad`] does
+pub fn pad_at(img: &DynamicImage, width: u32, height: u32, x: u32, y: u32, color: Rgba<u8>) -> DynamicImage {
+    let (x, y) = (x as i64, y as i64);
+    let fill = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, color));
+    let alpha = img.color().has_alpha() || color.0[3] < u8::MAX;
+    let grey = !img.color().has_color() && color.0[0] == color.0[1] && color.0[1] == color.0[2];
+
+    // Convert image and fill color to a common pixel type before copying
+    macro_rules! padded {
+        ($variant:ident, $convert:ident) => {
+            DynamicImage::$variant(fill_around(&img.$convert(), fill.$convert().get_pixel(0, 0), width, height, x, y))
+        };
+    }
+    let depth = img.color().bytes_per_pixel() / img.color().channel_count();
+    match (depth, alpha, grey) {
+        (4, false, _) => padded!(ImageRgb32F, to_rgb32f),
+        (4, true, _) => padded!(ImageRgba32F, to_rgba32f),
+        (2, false, true) => padded!(ImageLuma16, to_luma16),
+        (2, true, true) => padded!(ImageLumaA16, to_luma_alpha16),
+        (2, false, false) => padded!(ImageRgb16, to_rgb16),
+        (2, true, false) => padded!(ImageRgba16, to_rgba16),
+        (_, false, true) => padded!(ImageLuma8, to_luma8),
+        (_, true, true) => padded!(ImageLumaA8, to_luma_alpha8),
+        (_, false, false) => padded!(ImageRgb8, to_rgb8),
+        (_, true, false) => padded!(ImageRgba8, to_rgba8)
+    }
+}
+
+/// Copy an image onto a canvas of the fill color
+fn fill_around<P: Pixel>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    fill: &P,
+    width: u32,
+    height: u32,
+    x: i64,
+    y: i64
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::from_pixel(width, height, *fill);
+    imageops::replace(&mut canvas, img, x, y);
+    canvas
+}
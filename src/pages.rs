@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::str::FromStr;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use image::{DynamicImage, ImageBuffer, ImageFormat, ImageResult};
+use tiff::ColorType;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype, TiffEncoder};
+
+/// How the pages of a multipage image are saved
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PageOutput {
+    /// Save each page to its own file
+    Split,
+    /// Save all pages to a single multipage TIFF
+    Multipage
+}
+
+impl FromStr for PageOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "split" => Ok(PageOutput::Split),
+            "multipage" => Ok(PageOutput::Multipage),
+            _ => Err(format!("unknown page output '{}', expected split or multipage", s))
+        }
+    }
+}
+
+/// Destination of a single page of a split multipage image
+pub fn page_dest(dest: &Path, page: usize) -> std::path::PathBuf {
+    crate::save::affixed(dest, "", &format!("_p{}", page))
+}
+
+/// Decode all pages of an image if it is a multipage TIFF, or render them at a resolution in dots
+/// per inch if it is a PDF document, whose pages are processed as pages however many it has
+pub fn decode_pages(path: &Path, pdf_dpi: f32) -> cpar::Result<Option<Vec<DynamicImage>>> {
+    if crate::pdf::is_pdf(path) {
+        return crate::pdf::render(path, pdf_dpi).map(Some);
+    }
+    if ImageFormat::from_path(path).ok() != Some(ImageFormat::Tiff) {
+        return Ok(None);
+    }
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(invalid)?;
+    if !decoder.more_images() {
+        return Ok(None);
+    }
+
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions().map_err(invalid)?;
+        let color = decoder.colortype().map_err(invalid)?;
+        pages.push(page(width, height, color, decoder.read_image().map_err(invalid)?)?);
+
+        if !decoder.more_images() {
+            return Ok(Some(pages));
+        }
+        decoder.next_image().map_err(invalid)?;
+    }
+}
+
+/// Check whether a TIFF color type can be decoded into an image
+pub fn supported(color: ColorType) -> bool {
+    matches!(color, ColorType::Gray(8 | 16) | ColorType::GrayA(8 | 16) | ColorType::RGB(8 | 16) | ColorType::RGBA(8 | 16))
+}
+
+/// Image of the given dimensions from samples decoded from a TIFF of a color type
+pub fn page(width: u32, height: u32, color: ColorType, data: DecodingResult) -> io::Result<DynamicImage> {
+    let page = match (color, data) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        },
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        },
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        },
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        },
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        },
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+        },
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        },
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        },
+        (color, _) => return Err(invalid(format!("unsupported TIFF page color type {:?}", color)))
+    };
+    page.ok_or_else(|| invalid("TIFF page data does not match its dimensions"))
+}
+
+/// Encode pages as a multipage TIFF
+pub fn write_pages(pages: &[DynamicImage], path: &Path) -> ImageResult<()> {
+    let mut encoder = TiffEncoder::new(BufWriter::new(File::create(path)?)).map_err(invalid)?;
+    for page in pages {
+        let (width, height) = (page.width(), page.height());
+        match page {
+            DynamicImage::ImageLuma8(page) => encoder.write_image::<colortype::Gray8>(width, height, page),
+            DynamicImage::ImageRgb8(page) => encoder.write_image::<colortype::RGB8>(width, height, page),
+            DynamicImage::ImageRgba8(page) => encoder.write_image::<colortype::RGBA8>(width, height, page),
+            DynamicImage::ImageLuma16(page) => encoder.write_image::<colortype::Gray16>(width, height, page),
+            DynamicImage::ImageRgb16(page) => encoder.write_image::<colortype::RGB16>(width, height, page),
+            DynamicImage::ImageRgba16(page) => encoder.write_image::<colortype::RGBA16>(width, height, page),
+            page => encoder.write_image::<colortype::RGBA8>(width, height, &page.to_rgba8())
+        }.map_err(invalid)?;
+    }
+    Ok(())
+}
+
+/// Wrap TIFF errors as invalid data
+pub fn invalid(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,588 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use image::{GrayImage, Pixel, Rgba};
+use image::imageops::FilterType;
+use crate::CropRect;
+
+/// Values applied individually to each side of an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Sides<T> {
+    pub left: T,
+    pub right: T,
+    pub top: T,
+    pub bottom: T
+}
+
+impl<T: Copy> Sides<T> {
+    /// Use the same value for every side
+    pub fn all(value: T) -> Self {
+        Self::axes(value, value)
+    }
+
+    /// Use one value for the left and right sides, and another for the top and bottom sides
+    pub fn axes(x: T, y: T) -> Self {
+        Self { left: x, right: x, top: y, bottom: y }
+    }
+}
+
+/// Maximum of the 16-bit scale on which thresholds are compared
+const LEVEL_MAX: f32 = u16::MAX as f32;
+
+/// Pixel property used to distinguish artwork from its background
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DetectMode {
+    /// Pixels with luma below threshold are artwork
+    #[default]
+    Luma,
+    /// Pixels with alpha at or above threshold are artwork
+    Alpha,
+    /// Pixels with a color channel, or the maximum or minimum of their color channels, below
+    /// threshold are artwork
+    Channel(Channel),
+    /// Pixels further than tolerance from background color are artwork, ignoring threshold
+    Background {
+        color: Rgba<u8>,
+        tolerance: f32
+    },
+    /// Rows and columns with mean Sobel gradient magnitude above energy, in 8-bit levels, are
+    /// artwork, ignoring threshold and percentile
+    Gradient {
+        energy: f32
+    },
+    /// Rows and columns whose standard deviation of luma is above deviation, in 8-bit levels, are
+    /// artwork, ignoring threshold and percentile
+    Variance {
+        deviation: f32
+    },
+    /// Rows and columns of exactly the color of the top left corner pixel are background,
+    /// ignoring threshold, percentile and detection blur
+    Exact,
+    /// Pixels with luma below threshold are artwork, and the edges are the bounding box of its
+    /// largest connected region, or of every region of at least min_area pixels, ignoring
+    /// percentile
+    Bbox {
+        min_area: Option<u32>
+    }
+}
+
+/// Color channel, or combination of color channels, compared against threshold in channel mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    /// Brightest channel, so that pixels of any strong color are background
+    Max,
+    /// Darkest channel, so that pixels of any strong color are artwork, such as pale paper
+    /// against colored ink
+    Min
+}
+
+/// Default mean gradient magnitude separating background from artwork in gradient mode
+pub const DEFAULT_ENERGY: f32 = 12.0;
+
+/// Default standard deviation of luma separating background from artwork in variance mode
+pub const DEFAULT_DEVIATION: f32 = 8.0;
+
+impl DetectMode {
+    /// Check whether a pixel belongs to the artwork rather than its background, with pixel and
+    /// threshold on the 16-bit scale. Gradient, variance and exact modes, which detect whole rows and
+    /// columns, fall back to luma.
+    pub fn is_content(&self, pixel: Rgba<u16>, threshold: u16) -> bool {
+        self.crosses(self.level(pixel), threshold)
+    }
+
+    /// Level of a pixel compared against threshold, on the 16-bit scale. Background mode ignores
+    /// threshold, so its levels are 1 for artwork and 0 for background.
+    pub(crate) fn level(&self, pixel: Rgba<u16>) -> u16 {
+        match *self {
+            DetectMode::Luma | DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact
+            | DetectMode::Bbox { .. } => pixel.to_luma().0[0],
+            DetectMode::Alpha => pixel.0[3],
+            DetectMode::Channel(channel) => match channel {
+                Channel::Red => pixel.0[0],
+                Channel::Green => pixel.0[1],
+                Channel::Blue => pixel.0[2],
+                Channel::Max => pixel.0[0].max(pixel.0[1]).max(pixel.0[2]),
+                Channel::Min => pixel.0[0].min(pixel.0[1]).min(pixel.0[2])
+            },
+            DetectMode::Background { color, tolerance } => {
+                // Tolerance is measured in 8-bit levels
+                let distance = pixel.0.iter().zip(color.0).take(3)
+                    .map(|(&a, b)| (a as f32 / 257.0 - b as f32).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                (distance > tolerance) as u16
+            }
+        }
+    }
+
+    /// Check whether a level belongs to the artwork rather than its background
+    pub(crate) fn crosses(&self, level: u16, threshold: u16) -> bool {
+        match *self {
+            DetectMode::Luma | DetectMode::Channel(_) | DetectMode::Gradient { .. } | DetectMode::Variance { .. }
+            | DetectMode::Exact | DetectMode::Bbox { .. } => level < threshold,
+            DetectMode::Alpha => level >= threshold,
+            DetectMode::Background { .. } => level > 0
+        }
+    }
+
+    /// Color of the background the artwork is detected against
+    pub fn background(&self) -> Rgba<u8> {
+        match *self {
+            DetectMode::Luma | DetectMode::Channel(_) | DetectMode::Gradient { .. } | DetectMode::Variance { .. }
+            | DetectMode::Exact | DetectMode::Bbox { .. } => Rgba([255, 255, 255, 255]),
+            DetectMode::Alpha => Rgba([0, 0, 0, 0]),
+            DetectMode::Background { color, .. } => color
+        }
+    }
+}
+
+impl FromStr for DetectMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luma" => Ok(DetectMode::Luma),
+            "alpha" => Ok(DetectMode::Alpha),
+            "gradient" => Ok(DetectMode::Gradient { energy: DEFAULT_ENERGY }),
+            "variance" => Ok(DetectMode::Variance { deviation: DEFAULT_DEVIATION }),
+            "exact" => Ok(DetectMode::Exact),
+            "bbox" => Ok(DetectMode::Bbox { min_area: None }),
+            "channel:r" => Ok(DetectMode::Channel(Channel::Red)),
+            "channel:g" => Ok(DetectMode::Channel(Channel::Green)),
+            "channel:b" => Ok(DetectMode::Channel(Channel::Blue)),
+            "channel:max" => Ok(DetectMode::Channel(Channel::Max)),
+            "channel:min" => Ok(DetectMode::Channel(Channel::Min)),
+            _ => Err(format!(
+                "unknown detection mode '{}', expected luma, alpha, channel:r, channel:g, channel:b, channel:max, \
+                channel:min, gradient, variance, exact or bbox",
+                s
+            ))
+        }
+    }
+}
+
+/// Brightness of the background that artwork is detected against by threshold
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Polarity {
+    /// Background is lighter than artwork
+    #[default]
+    Light,
+    /// Background is darker than artwork, so levels are inverted before comparing them against
+    /// threshold
+    Dark,
+    /// Background is light or dark as the corners of each image are
+    Auto
+}
+
+impl FromStr for Polarity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "light" => Ok(Polarity::Light),
+            "dark" => Ok(Polarity::Dark),
+            "auto" => Ok(Polarity::Auto),
+            _ => Err(format!("unknown polarity '{}', expected auto, light or dark", s))
+        }
+    }
+}
+
+/// Statistic of the depths at which rows or columns cross threshold that places an edge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EdgeStat {
+    /// Percentile of the depths, interpolated linearly between ranks
+    #[default]
+    Percentile,
+    /// Median of the depths, after rejecting those more than three scaled median absolute
+    /// deviations from it, such as rows beside a torn corner
+    Median,
+    /// Mean of the depths, after discarding the shallowest and the deepest 10%
+    TrimmedMean
+}
+
+impl FromStr for EdgeStat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "percentile" => Ok(EdgeStat::Percentile),
+            "median" => Ok(EdgeStat::Median),
+            "trimmed-mean" => Ok(EdgeStat::TrimmedMean),
+            _ => Err(format!("unknown edge statistic '{}', expected percentile, median or trimmed-mean", s))
+        }
+    }
+}
+
+/// Rotation of images before their crop is detected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// Rotate clockwise by a quarter turn
+    Rotate90,
+    /// Rotate by a half turn
+    Rotate180,
+    /// Rotate clockwise by three quarter turns
+    Rotate270,
+    /// Rotate sideways images a quarter turn either way so that their lines of text run
+    /// horizontally, as estimated by [`text_rotation`](crate::text_rotation)
+    Auto
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "90" => Ok(Rotation::Rotate90),
+            "180" => Ok(Rotation::Rotate180),
+            "270" => Ok(Rotation::Rotate270),
+            "auto" => Ok(Rotation::Auto),
+            _ => Err(format!("unknown rotation '{}', expected 90, 180, 270 or auto", s))
+        }
+    }
+}
+
+/// How the original aspect ratio is restored after cropping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestoreMode {
+    /// Resize the cropped image to the original aspect ratio
+    #[default]
+    Resize,
+    /// Pad the cropped image with its background to the original aspect ratio
+    Pad,
+    /// Keep the cropped image without restoring the aspect ratio or downscaling
+    Crop
+}
+
+impl FromStr for RestoreMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resize" => Ok(RestoreMode::Resize),
+            "pad" => Ok(RestoreMode::Pad),
+            _ => Err(format!("unknown restore mode '{}', expected resize or pad", s))
+        }
+    }
+}
+
+/// Aspect ratio of an image, as width to height
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aspect {
+    pub width: f32,
+    pub height: f32
+}
+
+impl FromStr for Aspect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid aspect ratio '{}', expected W:H such as 2:3", s);
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let width = width.parse::<f32>().map_err(|_| invalid())?;
+        let height = height.parse::<f32>().map_err(|_| invalid())?;
+        match width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite() {
+            true => Ok(Aspect { width, height }),
+            false => Err(invalid())
+        }
+    }
+}
+
+impl Aspect {
+    /// Aspect ratio of a standard paper size (a4, letter, legal) or a square
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            // All ISO A sizes share the ratio of 1 to the square root of 2
+            "a4" => Some(Aspect { width: 1.0, height: std::f32::consts::SQRT_2 }),
+            "letter" => Some(Aspect { width: 8.5, height: 11.0 }),
+            "legal" => Some(Aspect { width: 8.5, height: 14.0 }),
+            "square" => Some(Aspect { width: 1.0, height: 1.0 }),
+            _ => None
+        }
+    }
+
+    /// Ratio of width to height
+    pub fn ratio(&self) -> f32 {
+        self.width / self.height
+    }
+}
+
+/// Part of the artwork kept when trimming its crop to an aspect ratio
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Gravity {
+    /// Keep the middle
+    #[default]
+    Center,
+    /// Keep the top, or the middle when trimming the width
+    Top,
+    /// Keep the part with the most varied levels, such as detailed artwork rather than flat sky
+    Entropy,
+    /// Keep the part with the most edges and saturated color, which tends to draw attention
+    Saliency
+}
+
+impl FromStr for Gravity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "center" => Ok(Gravity::Center),
+            "top" => Ok(Gravity::Top),
+            "entropy" => Ok(Gravity::Entropy),
+            "saliency" => Ok(Gravity::Saliency),
+            _ => Err(format!("unknown gravity '{}', expected center, top, entropy or saliency", s))
+        }
+    }
+}
+
+/// Parse an aspect ratio given as W:H or by the name of a standard paper size
+pub fn parse_aspect(s: &str) -> Result<Aspect, String> {
+    Aspect::named(s).map_or_else(
+        || s.parse().map_err(|err| format!("{}, or a4, letter, legal or square", err)),
+        Ok
+    )
+}
+
+/// Parse a region of an image given as X,Y,W,H in pixels
+pub fn parse_region(s: &str) -> Result<CropRect, String> {
+    let values: Option<Vec<u32>> = s.split(',').map(|value| value.trim().parse().ok()).collect();
+    match values.as_deref() {
+        Some(&[x, y, width, height]) if width > 0 && height > 0 => Ok(CropRect { x, y, width, height }),
+        _ => Err(format!("invalid region '{}', expected X,Y,W,H in pixels such as 50,50,2400,3300", s))
+    }
+}
+
+/// Unsharp mask sharpening an image after resizing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sharpen {
+    /// Multiple of the difference from the blurred image added to each pixel
+    pub amount: f32,
+    /// Sigma of the blur the image is compared against
+    pub radius: f32,
+    /// Smallest difference from the blurred image to sharpen, in 8-bit levels, leaving smooth areas
+    /// and noise unchanged
+    pub threshold: f32
+}
+
+impl FromStr for Sharpen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid sharpening '{}', expected amount[,radius,threshold] such as 0.8,1.5,2", s);
+        let values = s.split(',')
+            .map(|value| value.trim().parse::<f32>().ok().filter(|value| value.is_finite()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        let (amount, radius, threshold) = match values[..] {
+            [amount] => (amount, 1.0, 0.0),
+            [amount, radius] => (amount, radius, 0.0),
+            [amount, radius, threshold] => (amount, radius, threshold),
+            _ => return Err(invalid())
+        };
+        match amount > 0.0 && radius > 0.0 && threshold >= 0.0 {
+            true => Ok(Sharpen { amount, radius, threshold }),
+            false => Err(invalid())
+        }
+    }
+}
+
+/// Parse a hex color in the form `#RGB`, `#RRGGBB` or `#RRGGBBAA`, with optional leading `#`
+pub fn parse_color(s: &str) -> Result<Rgba<u8>, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let digits = hex.chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("invalid hex color '{}'", s))?;
+    match digits[..] {
+        [r, g, b] => Ok(Rgba([r * 17, g * 17, b * 17, 255])),
+        [r1, r2, g1, g2, b1, b2] => Ok(Rgba([r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2, 255])),
+        [r1, r2, g1, g2, b1, b2, a1, a2] => Ok(Rgba([r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2, a1 << 4 | a2])),
+        _ => Err(format!("invalid hex color '{}', expected #RGB, #RRGGBB or #RRGGBBAA", s))
+    }
+}
+
+/// Parse the name of a resampling filter
+pub fn parse_filter(s: &str) -> Result<FilterType, String> {
+    match s {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmullrom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => Err(format!(
+            "unknown filter '{}', expected nearest, triangle, catmullrom, gaussian or lanczos3",
+            s
+        ))
+    }
+}
+
+/// Parse a threshold level onto the 16-bit scale, given as an 8-bit level (`250`), a percentage
+/// (`98%`) or a level out of a maximum (`64000/65535`)
+pub fn parse_level(s: &str) -> Result<u16, String> {
+    let invalid = || format!(
+        "invalid level '{}', expected 0-255, a percentage such as 98%, or a fraction such as 64000/65535",
+        s
+    );
+    let fraction = if let Some(percentage) = s.strip_suffix('%') {
+        percentage.parse::<f32>().map_err(|_| invalid())? / 100.0
+    } else if let Some((value, max)) = s.split_once('/') {
+        match (value.parse::<u32>(), max.parse::<u32>()) {
+            (Ok(value), Ok(max)) if max > 0 => value as f32 / max as f32,
+            _ => return Err(invalid())
+        }
+    } else {
+        return s.parse::<u8>().map(|value| value as u16 * 257).map_err(|_| invalid());
+    };
+    match fraction {
+        fraction if (0.0..=1.0).contains(&fraction) => Ok((fraction * LEVEL_MAX).round() as u16),
+        _ => Err(format!("level '{}' is out of range", s))
+    }
+}
+
+/// Parameters controlling how the edges of artwork are found
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeDetection {
+    /// Pixel property compared against threshold
+    pub mode: DetectMode,
+    /// Brightness of the background in luma, channel and bbox modes
+    pub polarity: Polarity,
+    /// Threshold value on the 16-bit scale separating background from artwork
+    pub threshold: Sides<u16>,
+    /// Compute threshold per image from its border region, falling back to `threshold` on failure
+    pub auto_threshold: bool,
+    /// Compute threshold per image as a percentage (0-100) of the way from its lowest to its highest
+    /// level, in place of `threshold` and `auto_threshold`
+    pub range_threshold: Option<f32>,
+    /// Percentile (0-100) of the depths at which rows/columns cross threshold to place each edge at,
+    /// interpolated linearly between ranks: 0 keeps all artwork, and 100 crops to the deepest depth
+    pub percentile: Sides<u8>,
+    /// Statistic of the depths placing each edge, using percentile only if it is a percentile
+    pub stat: EdgeStat,
+    /// Median filter levels over windows of this many pixels before finding the boundary, so that
+    /// isolated specks in the margin are ignored. Has no effect in gradient mode.
+    pub despeckle: Option<u32>,
+    /// Blur a copy of the image by sigma before detecting edges, leaving the cropped image sharp
+    pub blur: Option<f32>,
+    /// Part of the image to detect edges within, ignoring anything outside it such as scanner
+    /// edge artifacts. Clamped to the image.
+    pub region: Option<CropRect>,
+    /// Pixels at each side of the image, or of the region, to ignore when detecting edges
+    pub ignore_margin: u32,
+    /// Mask whose nonzero pixels are ignored when detecting edges, as over stamps or binding
+    /// shadows in the margins. Stretched to the size of each image.
+    pub mask: Option<Arc<GrayImage>>,
+    /// Extra margin to crop beyond found edge, or to keep beyond it if negative
+    pub extra: Sides<i32>,
+    /// Aspect ratios, in either orientation, to adjust the crop to the nearest of after removing
+    /// extra margin
+    pub snap_aspect: Vec<Aspect>,
+    /// Aspect ratio to trim the crop to after adjusting it, shortening whichever dimension is too
+    /// long for it
+    pub trim_aspect: Option<Aspect>,
+    /// Part of the artwork kept when trimming the crop to an aspect ratio
+    pub gravity: Gravity,
+    /// Detect edges again within the coarse crop on a downscaled copy with tighter thresholds,
+    /// shaving residual border such as soft shadows. Has no effect in gradient, variance and
+    /// exact modes.
+    pub refine: bool,
+    /// Find the boundary along every this many rows and columns, interpolating between them where
+    /// they agree and checking those between them where they do not. Has no effect in gradient,
+    /// variance, exact and bbox modes.
+    pub sample_stride: u32,
+    /// Move edges inward past a dark frame around the artwork of at most this many pixels thick,
+    /// cropping the margin within it too. Has no effect in gradient, variance and exact modes.
+    pub strip_frame: Option<u32>
+}
+
+impl Default for EdgeDetection {
+    fn default() -> Self {
+        Self {
+            mode: DetectMode::Luma,
+            polarity: Polarity::Light,
+            threshold: Sides::all(250 * 257),
+            auto_threshold: false,
+            range_threshold: None,
+            percentile: Sides::all(95),
+            stat: EdgeStat::Percentile,
+            despeckle: None,
+            blur: None,
+            region: None,
+            ignore_margin: 0,
+            mask: None,
+            extra: Sides::all(0),
+            snap_aspect: Vec::new(),
+            trim_aspect: None,
+            gravity: Gravity::Center,
+            refine: false,
+            sample_stride: 1,
+            strip_frame: None
+        }
+    }
+}
+
+/// Parameters controlling the full crop and restore process
+#[derive(Clone, Debug, PartialEq)]
+pub struct CropParams {
+    /// Edge detection parameters
+    pub detection: EdgeDetection,
+    /// Rotate images by quarter turns before deskewing and detecting their crop
+    pub rotate: Option<Rotation>,
+    /// Correct rotation of the artwork before cropping
+    pub deskew: bool,
+    /// How the original aspect ratio is restored
+    pub restore: RestoreMode,
+    /// Aspect ratio to restore in place of the original
+    pub aspect: Option<Aspect>,
+    /// Color to pad with, defaulting to the background of the detection mode
+    pub pad_color: Option<Rgba<u8>>,
+    /// Resampling filter used when resizing
+    pub filter: FilterType,
+    /// Blur and resize in linear light rather than on sRGB encoded levels, so that fine dark
+    /// details such as line art are not darkened
+    pub linear_resize: bool,
+    /// Blur the cropped image by sigma before restoring it. Detection uses its own blur.
+    pub blur: Option<f32>,
+    /// Sharpen image after resizing
+    pub sharpen: Option<Sharpen>,
+    /// Stretch the luma histogram of the restored image to the full range
+    pub auto_levels: bool,
+    /// Gamma correction of the restored image, brightening midtones above 1
+    pub gamma: f32,
+    /// Downscale image by factor
+    pub downscale: f32,
+    /// Scale image to this width in pixels in place of downscaling it by factor, fitting within
+    /// the target height too if both are given
+    pub target_width: Option<u32>,
+    /// Scale image to this height in pixels in place of downscaling it by factor
+    pub target_height: Option<u32>,
+    /// Downscale image further where needed so that its longer side is at most this many pixels
+    pub max_dimension: Option<u32>,
+    /// Blur and resize on the GPU where available, falling back to the CPU. Has no effect unless
+    /// built with the `gpu` feature.
+    pub gpu: bool
+}
+
+impl Default for CropParams {
+    fn default() -> Self {
+        Self {
+            detection: EdgeDetection::default(),
+            rotate: None,
+            deskew: false,
+            restore: RestoreMode::Resize,
+            aspect: None,
+            pad_color: None,
+            filter: FilterType::Lanczos3,
+            linear_resize: false,
+            blur: None,
+            sharpen: None,
+            auto_levels: false,
+            gamma: 1.0,
+            downscale: 1.0,
+            target_width: None,
+            target_height: None,
+            max_dimension: None,
+            gpu: false
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::Path;
+use image::DynamicImage;
+
+/// Resolution PDF pages are rendered at by default, which scanners commonly scan at
+pub const DEFAULT_DPI: f32 = 300.0;
+
+/// Check whether a path has the extension of a PDF document
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// Render every page of a PDF document with hayro at a resolution in dots per inch, over a white
+/// background so that the blank paper around its content is cropped away as margin. Fonts which
+/// are not embedded are substituted.
+#[cfg(feature = "pdf")]
+pub fn render(path: &Path, dpi: f32) -> cpar::Result<Vec<DynamicImage>> {
+    use std::{fs, io};
+    use hayro::hayro_interpret::InterpreterSettings;
+    use hayro::hayro_syntax::{LoadPdfError, Pdf};
+    use hayro::vello_cpu::color::palette::css::WHITE;
+    use hayro::vello_cpu::peniko::ImageAlphaType;
+    use hayro::{PixmapSettings, RenderCache, RenderSettings};
+    use image::RgbaImage;
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("PDF: {}", message));
+    let pdf = Pdf::new(fs::read(path)?).map_err(|err| match err {
+        LoadPdfError::Decryption(_) => invalid("encrypted documents are not supported"),
+        LoadPdfError::Invalid => invalid("document is invalid")
+    })?;
+    // Page sizes are given in points, of which there are 72 to an inch
+    let scale = dpi / 72.0;
+    let settings = PixmapSettings { x_scale: scale, y_scale: scale, bg_color: WHITE };
+    let cache = RenderCache::new();
+    let mut pages = Vec::with_capacity(pdf.pages().len());
+    for (n, page) in pdf.pages().iter().enumerate() {
+        // Pages are rendered to pixmaps at most 65535 pixels across
+        let (width, height) = page.render_dimensions();
+        if (width * scale).max(height * scale) > u16::MAX as f32 {
+            return Err(invalid(&format!("page {} is too large to render at this resolution", n + 1)).into());
+        }
+        let pixmap = hayro::render(page, &cache, &InterpreterSettings::default(), &RenderSettings::default(), &settings);
+        let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+        if width == 0 || height == 0 {
+            return Err(invalid(&format!("page {} is empty at this resolution", n + 1)).into());
+        }
+        // Pixels are premultiplied, which makes no difference as the background is opaque
+        let img = RgbaImage::from_raw(width, height, pixmap.take_rgba8(ImageAlphaType::AlphaPremultiplied));
+        pages.push(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img.expect("pixmap matches its dimensions")).into_rgb8()));
+    }
+    match pages.is_empty() {
+        true => Err(invalid("document has no pages").into()),
+        false => Ok(pages)
+    }
+}
+
+/// Fail to render a PDF document, as support for them was not built
+#[cfg(not(feature = "pdf"))]
+pub fn render(_: &Path, _: f32) -> cpar::Result<Vec<DynamicImage>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "rendering PDF pages requires building with the pdf feature").into())
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+use cpar::{CropParams, CropRect};
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader};
+use log::warn;
+use crate::guard::{self, Blank, Fallback};
+use crate::save::OutputFormat;
+use crate::{dpi, save, xmp, Cpar};
+
+/// Path standing for stdin as a source, or stdout as the output
+pub const STDIO: &str = "-";
+
+/// Check whether a source or output path refers to stdin/stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// Process a single image read from stdin or a file, writing it to stdout or the output folder
+pub fn pipe(args: &Cpar, params: &CropParams) -> cpar::Result<()> {
+    let [source] = &args.source[..] else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "piping requires a single source").into());
+    };
+    check(args)?;
+
+    // Read source image, guessing format from its contents
+    let buffer = match is_stdio(source) {
+        true => {
+            let mut buffer = Vec::new();
+            io::stdin().lock().read_to_end(&mut buffer)?;
+            buffer
+        },
+        false => fs::read(source)?
+    };
+    let detected = detect(buffer, args, params)?;
+    let rect = guarded(args, params, &detected).map_err(|flag| io::Error::new(io::ErrorKind::InvalidData, flag))?;
+    let Detected { img, orientation, rotation, skew, input_format, dpi, .. } = detected;
+    let params = &*dpi::scaled_params(args, params, dpi);
+    if args.inspect {
+        eprintln!("crop {}\n{}", rect, cpar::heatmap(&img, &rect, args.inspect_width));
+    }
+    if args.dry_run {
+        let (width, height) = cpar::output_size(img.width(), img.height(), &rect, params);
+        eprintln!(
+            "{}x{}{} cropped to {}, {} {}x{}",
+            img.width(), img.height(), crate::deskewed(skew), rect, crate::restored(params), width, height
+        );
+        return Ok(());
+    }
+    let processed = cpar::apply(&img, &rect, params);
+
+    // Write in the requested format, otherwise the format of the source
+    let format = output_format(args, input_format);
+    let size = cpar::output_size(img.width(), img.height(), &rect, params);
+    let options = save::SaveOptions {
+        xmp: args.xmp.then(|| xmp::packet(img.width(), img.height(), &rect, orientation, rotation, skew, params.restore)),
+        dpi: dpi::output_dpi(args, dpi, (img.width(), img.height()), &rect, size, params),
+        ..args.save_options()
+    };
+    if is_stdio(&args.output) {
+        let mut buffer = Cursor::new(Vec::new());
+        save::write(&processed, &mut buffer, format, &options)?;
+        save::verify(Cursor::new(buffer.get_ref()), &processed, format, &options)?;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(buffer.get_ref())?;
+        stdout.flush()?;
+    } else {
+        fs::create_dir_all(&args.output)?;
+        let name = source.file_name().filter(|_| !is_stdio(source)).unwrap_or("stdin".as_ref());
+        let dest = args.output.join(name).with_extension(format.extension());
+        let options = save::SaveOptions { format: Some(format), ..options };
+        save::save_claimed(&dest, args.existing(), |dest| save::save(&processed, dest, &options))?;
+    }
+    Ok(())
+}
+
+/// Reject options which only apply to batches of images, when processing a single image
+pub fn check(args: &Cpar) -> cpar::Result<()> {
+    if args.split {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "splitting panels cannot be piped").into());
+    }
+    if args.lock_crop.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "locking crops cannot be piped").into());
+    }
+    if args.write_crops || args.apply_crops {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "crop sidecars cannot be piped").into());
+    }
+    if args.in_place {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "processing in place cannot be piped").into());
+    }
+    if args.files_from {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "reading sources from lists cannot be piped").into());
+    }
+    if args.resume || args.incremental {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "resuming and incremental runs cannot be piped").into());
+    }
+    if args.thumbnail.is_some() || !args.sizes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "thumbnails and sizes cannot be piped").into());
+    }
+    if args.error_sidecars {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "error sidecars cannot be written for piped images").into());
+    }
+    if args.report.is_some() || args.stats_json.is_some() || args.failed_list.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "reports cannot be written for piped images").into());
+    }
+    if args.preview || args.debug_output.is_some() || args.diff_output.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "previews and debug images cannot be saved for piped images").into());
+    }
+    if args.metrics.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "metrics cannot be reported for piped images").into());
+    }
+    if args.dedupe.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "deduplicating a batch cannot be piped").into());
+    }
+    if args.param_manifest.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "piped images cannot be tuned by a parameter manifest").into());
+    }
+    if args.streamed_decode {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "piped images cannot be decoded a strip at a time").into());
+    }
+    if !args.route.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "routing outputs into folders cannot be piped").into());
+    }
+    if args.pre_cmd.is_some() || args.post_cmd.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "commands cannot be run on piped images").into());
+    }
+    Ok(())
+}
+
+/// Image decoded from a buffer and corrected, along with its detected crop
+pub struct Detected {
+    pub img: DynamicImage,
+    pub rect: CropRect,
+    /// EXIF orientation applied when decoding, where it was not upright
+    pub orientation: Option<u8>,
+    /// Quarter turn applied by --rotate, in degrees clockwise
+    pub rotation: Option<u16>,
+    /// Rotation corrected before cropping
+    pub skew: Option<f32>,
+    /// Format of the source, guessed from its contents
+    pub input_format: Option<ImageFormat>,
+    /// Resolution of the source in dots per inch, where recorded
+    pub dpi: Option<f32>
+}
+
+/// Decode an image from a buffer, correcting its skew if requested, and detect its crop
+pub fn detect(buffer: Vec<u8>, args: &Cpar, params: &CropParams) -> cpar::Result<Detected> {
+    let dpi = dpi::read_from(&mut Cursor::new(&buffer));
+    let reader = ImageReader::new(Cursor::new(buffer)).with_guessed_format()?;
+    let input_format = reader.format();
+    let (img, orientation) = crate::decode(reader, !args.no_auto_orient)?;
+    let (mut img, rotation) = cpar::orient(img, params);
+    let rotation = Some(rotation).filter(|&degrees| degrees != 0);
+    let corrected = params.deskew.then(|| cpar::deskew(&img, &params.detection)).flatten();
+    let skew = corrected.as_ref().map(|&(_, angle)| angle);
+    if let Some((corrected, _)) = corrected {
+        img = corrected;
+    }
+    let rect = match crate::matched(args, || cpar::detect(&img, &params.detection).ok_or(cpar::Error::NoEdges))? {
+        Some(rect) => rect,
+        None if args.on_blank() == Blank::Skip => return Err(cpar::Error::NoEdges),
+        None => {
+            warn!("No edges detected, saving the image uncropped");
+            CropRect { x: 0, y: 0, width: img.width(), height: img.height() }
+        }
+    };
+    Ok(Detected { img, rect, orientation, rotation, skew, input_format, dpi })
+}
+
+/// Crop of a detected image kept by the guards against undersized and overcropped crops and the
+/// content guard, failing with the flag of images they skip, as there is no batch to leave them out
+/// of
+pub fn guarded(args: &Cpar, params: &CropParams, detected: &Detected) -> Result<CropRect, String> {
+    let Detected { img, rect, .. } = detected;
+    let content = || guard::content(args, img, rect, &params.detection);
+    match guard::flagged(args, rect, img.dimensions(), content) {
+        Some((flag, Fallback::Skip)) => Err(flag),
+        Some((flag, Fallback::Uncropped)) => {
+            warn!("{}, saving the image uncropped", flag);
+            Ok(CropRect { x: 0, y: 0, width: img.width(), height: img.height() })
+        },
+        None => Ok(*rect)
+    }
+}
+
+/// Format to write a processed image in, as requested, otherwise the format of the source
+pub fn output_format(args: &Cpar, input_format: Option<ImageFormat>) -> OutputFormat {
+    args.format.or(input_format.map(OutputFormat::from)).unwrap_or(ImageFormat::Png.into())
+}
@@ -0,0 +1,155 @@
+use std::mem;
+use image::{DynamicImage, GenericImageView, Rgba};
+use image::imageops::FilterType;
+use crate::{crop, deskew, detect, levels, output_size, pad, resize, restore, sharpen};
+use crate::{CropParams, CropRect, EdgeDetection, Error, RestoreMode, Result, Sharpen};
+
+/// Image passing through the stages of a [`Pipeline`]
+pub struct Artwork {
+    /// Image as left by the stages so far
+    pub img: DynamicImage,
+    /// Dimensions of the image given to the pipeline, whose aspect ratio is restored by resizing or
+    /// padding
+    pub original: (u32, u32),
+    /// Crop found by the latest detection, until it is cropped to
+    pub rect: Option<CropRect>
+}
+
+/// Step of a pipeline, transforming the artwork passing through it. Implemented by closures, so
+/// that custom steps can be inserted anywhere in a pipeline.
+pub trait Stage: Send + Sync {
+    fn apply(&self, artwork: &mut Artwork) -> Result<()>;
+}
+
+impl<F: Fn(&mut Artwork) -> Result<()> + Send + Sync> Stage for F {
+    fn apply(&self, artwork: &mut Artwork) -> Result<()> {
+        self(artwork)
+    }
+}
+
+/// Sequence of stages processing an image, built by chaining stages in the order they are run,
+/// such as `Pipeline::new().detect(detection).crop().then(custom).resize(filter)`.
+///
+/// [`process`](crate::process) is equivalent to deskewing if requested, detecting, cropping and
+/// then restoring with the same parameters.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a custom stage
+    pub fn then(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Correct the rotation of the artwork, leaving it as is if no skew is found
+    pub fn deskew(self, detection: EdgeDetection) -> Self {
+        self.then(move |artwork: &mut Artwork| {
+            if let Some((corrected, _)) = deskew(&artwork.img, &detection) {
+                artwork.img = corrected;
+            }
+            Ok(())
+        })
+    }
+
+    /// Detect the crop of the artwork, failing with [`Error::NoEdges`] if its edges are not found
+    pub fn detect(self, detection: EdgeDetection) -> Self {
+        self.then(move |artwork: &mut Artwork| {
+            artwork.rect = Some(detect(&artwork.img, &detection).ok_or(Error::NoEdges)?);
+            Ok(())
+        })
+    }
+
+    /// Crop the artwork to its detected crop, within its own buffer. Artwork is left as is if no
+    /// crop was detected.
+    pub fn crop(self) -> Self {
+        self.then(|artwork: &mut Artwork| {
+            if let Some(rect) = artwork.rect.take() {
+                artwork.img = crop::crop_in_place(mem::take(&mut artwork.img), &rect);
+            }
+            Ok(())
+        })
+    }
+
+    /// Blur the artwork by sigma
+    pub fn blur(self, sigma: f32) -> Self {
+        self.then(move |artwork: &mut Artwork| {
+            artwork.img = artwork.img.blur(sigma);
+            Ok(())
+        })
+    }
+
+    /// Resize the artwork with the resampling filter to restore the original aspect ratio,
+    /// keeping its longer side
+    pub fn resize(self, filter: FilterType) -> Self {
+        let params = CropParams { restore: RestoreMode::Resize, filter, ..CropParams::default() };
+        self.then(move |artwork: &mut Artwork| {
+            let (width, height) = restored_size(artwork, &params);
+            if artwork.img.dimensions() != (width, height) {
+                artwork.img = resize(&artwork.img, width, height, &params);
+            }
+            Ok(())
+        })
+    }
+
+    /// Pad the artwork with a color to restore the original aspect ratio, centering it
+    pub fn pad(self, color: Rgba<u8>) -> Self {
+        let params = CropParams { restore: RestoreMode::Pad, ..CropParams::default() };
+        self.then(move |artwork: &mut Artwork| {
+            let (width, height) = restored_size(artwork, &params);
+            artwork.img = pad(&artwork.img, width, height, color);
+            Ok(())
+        })
+    }
+
+    /// Sharpen the artwork with an unsharp mask
+    pub fn sharpen(self, amount: Sharpen) -> Self {
+        let params = CropParams::default();
+        self.then(move |artwork: &mut Artwork| {
+            artwork.img = sharpen::sharpen(&artwork.img, &amount, &params);
+            Ok(())
+        })
+    }
+
+    /// Stretch the luma histogram of the artwork to the full range if requested, then apply the
+    /// gamma correction
+    pub fn levels(self, auto_levels: bool, gamma: f32) -> Self {
+        let params = CropParams { auto_levels, gamma, ..CropParams::default() };
+        self.then(move |artwork: &mut Artwork| {
+            artwork.img = levels::levels(mem::take(&mut artwork.img), &params);
+            Ok(())
+        })
+    }
+
+    /// Blur, restore the aspect ratio, sharpen and adjust the levels of the artwork as set by the
+    /// parameters, as [`apply`](crate::apply) does after cropping
+    pub fn restore(self, params: CropParams) -> Self {
+        self.then(move |artwork: &mut Artwork| {
+            let size = restored_size(artwork, &params);
+            artwork.img = restore(mem::take(&mut artwork.img), size, &params);
+            Ok(())
+        })
+    }
+
+    /// Run an image through each stage in turn, stopping at the first to fail
+    pub fn run(&self, img: DynamicImage) -> Result<Artwork> {
+        let mut artwork = Artwork { original: img.dimensions(), img, rect: None };
+        for stage in &self.stages {
+            stage.apply(&mut artwork)?;
+        }
+        Ok(artwork)
+    }
+}
+
+/// Dimensions restoring the original aspect ratio of the artwork as it now is
+fn restored_size(artwork: &Artwork, params: &CropParams) -> (u32, u32) {
+    let (width, height) = artwork.img.dimensions();
+    let (original_width, original_height) = artwork.original;
+    output_size(original_width, original_height, &CropRect { x: 0, y: 0, width, height }, params)
+}
@@ -0,0 +1,168 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use image::imageops::FilterType;
+use crate::{CropRect, Sides};
+
+/// Width of the outline drawn around the crop rectangle
+const OUTLINE_WIDTH: u32 = 2;
+
+/// Draw a crop rectangle over an image, tinting the area to be removed in the given color and
+/// outlining the area kept
+pub fn draw_crop(img: &DynamicImage, rect: &CropRect, color: Rgba<u8>) -> RgbaImage {
+    let right = rect.x + rect.width;
+    let bottom = rect.y + rect.height;
+    let mut preview = img.to_rgba8();
+    for (x, y, pixel) in preview.enumerate_pixels_mut() {
+        let inside = x >= rect.x && x < right && y >= rect.y && y < bottom;
+        let outline = inside && (
+            x < rect.x + OUTLINE_WIDTH || x + OUTLINE_WIDTH >= right ||
+            y < rect.y + OUTLINE_WIDTH || y + OUTLINE_WIDTH >= bottom
+        );
+        if outline {
+            *pixel = color;
+        } else if !inside {
+            *pixel = blend(*pixel, color);
+        }
+    }
+    preview
+}
+
+/// Gap between the images of a side by side comparison
+const GAP: u32 = 8;
+
+/// Place an image with its crop drawn over it beside the processed result, scaled to the same
+/// height, to review crops of a batch at a glance
+pub fn side_by_side(img: &DynamicImage, rect: &CropRect, processed: &DynamicImage, color: Rgba<u8>) -> RgbaImage {
+    let before = draw_crop(img, rect, color);
+    let (width, height) = before.dimensions();
+    let scaled_width = (processed.width() as u64 * height as u64 / processed.height().max(1) as u64).max(1) as u32;
+    let after = imageops::resize(&processed.to_rgba8(), scaled_width, height, FilterType::Triangle);
+    let mut comparison = RgbaImage::from_pixel(width + GAP + scaled_width, height, Rgba([255, 255, 255, 255]));
+    imageops::replace(&mut comparison, &before, 0, 0);
+    imageops::replace(&mut comparison, &after, (width + GAP) as i64, 0);
+    comparison
+}
+
+/// Mix an image evenly with the processed result stretched back over its crop rectangle, fading
+/// the area removed, so that artwork lost or shifted by processing shows as ghosting
+pub fn blended(img: &DynamicImage, rect: &CropRect, processed: &DynamicImage) -> RgbaImage {
+    let mut comparison = img.to_rgba8();
+    let after = imageops::resize(&processed.to_rgba8(), rect.width.max(1), rect.height.max(1), FilterType::Triangle);
+    for (x, y, pixel) in comparison.enumerate_pixels_mut() {
+        let inside = x >= rect.x && x < rect.x + rect.width && y >= rect.y && y < rect.y + rect.height;
+        *pixel = match inside {
+            true => blend(*pixel, *after.get_pixel(x - rect.x, y - rect.y)),
+            false => blend(*pixel, Rgba([255, 255, 255, 255]))
+        };
+    }
+    comparison
+}
+
+/// Colors of the points and edges of each side drawn over diagnostic images
+const SIDE_COLORS: Sides<Rgba<u8>> = Sides {
+    left: Rgba([230, 0, 0, 255]),
+    right: Rgba([0, 160, 0, 255]),
+    top: Rgba([0, 0, 230, 255]),
+    bottom: Rgba([200, 0, 200, 255])
+};
+
+/// Draw the boundary points at which each row and column crossed the threshold over a faded
+/// image, with a line at the edge selected from them on each side, to show why a crop was chosen.
+///
+/// Points are given as in [`crate::boundary`], and may be empty where detection does not scan
+/// for them.
+pub fn draw_boundary(img: &DynamicImage, boundary: &Sides<Vec<(u32, u32)>>, edges: &Sides<u32>) -> RgbaImage {
+    let mut diagnostic = img.to_rgba8();
+    let (width, height) = diagnostic.dimensions();
+    for pixel in diagnostic.pixels_mut() {
+        *pixel = blend(*pixel, Rgba([255, 255, 255, 255]));
+    }
+    let mut plot = |x: u32, y: u32, color: Rgba<u8>| {
+        if x < width && y < height {
+            diagnostic.put_pixel(x, y, color);
+        }
+    };
+
+    // Left and right points are given by row, top and bottom points by column
+    for &(y, x) in &boundary.left {
+        plot(x, y, SIDE_COLORS.left);
+    }
+    for &(y, x) in &boundary.right {
+        plot(x, y, SIDE_COLORS.right);
+    }
+    for &(x, y) in &boundary.top {
+        plot(x, y, SIDE_COLORS.top);
+    }
+    for &(x, y) in &boundary.bottom {
+        plot(x, y, SIDE_COLORS.bottom);
+    }
+    for y in 0..height {
+        plot(edges.left, y, SIDE_COLORS.left);
+        plot(edges.right, y, SIDE_COLORS.right);
+    }
+    for x in 0..width {
+        plot(x, edges.top, SIDE_COLORS.top);
+        plot(x, edges.bottom, SIDE_COLORS.bottom);
+    }
+    diagnostic
+}
+
+/// Shades of heatmap cells from lightest to darkest
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Render the luma of an image as a heatmap of unicode blocks columns wide, darker cells being
+/// denser, with the crop rectangle outlined by box drawing characters. Cells are twice as tall as
+/// they are wide, roughly matching the proportions of terminal characters.
+pub fn heatmap(img: &DynamicImage, rect: &CropRect, columns: u32) -> String {
+    let luma = img.to_luma16();
+    let (width, height) = luma.dimensions();
+    let columns = columns.clamp(1, width.max(1));
+    let rows = ((height as u64 * columns as u64).div_ceil(2 * width.max(1) as u64) as u32).max(1);
+    let column = |x: u32| (x as u64 * columns as u64 / width as u64) as u32;
+    let row = |y: u32| (y as u64 * rows as u64 / height as u64) as u32;
+
+    // Average the luma of pixels within each cell
+    let mut sums = vec![(0u64, 0u64); columns as usize * rows as usize];
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        let cell = &mut sums[row(y) as usize * columns as usize + column(x) as usize];
+        cell.0 += pixel.0[0] as u64;
+        cell.1 += 1;
+    }
+
+    let (left, right) = (column(rect.x), column((rect.x + rect.width).max(1) - 1));
+    let (top, bottom) = (row(rect.y), row((rect.y + rect.height).max(1) - 1));
+    let mut map = String::new();
+    for y in 0..rows {
+        if y > 0 {
+            map.push('\n');
+        }
+        for x in 0..columns {
+            let across = (left..=right).contains(&x);
+            let along = (top..=bottom).contains(&y);
+            let vertical = along && (x == left || x == right);
+            let horizontal = across && (y == top || y == bottom);
+            map.push(match (vertical, horizontal) {
+                (true, true) => match (y == top, x == left) {
+                    (true, true) => '┌',
+                    (true, false) => '┐',
+                    (false, true) => '└',
+                    (false, false) => '┘'
+                },
+                (true, false) => '│',
+                (false, true) => '─',
+                (false, false) => {
+                    let (sum, count) = sums[y as usize * columns as usize + x as usize];
+                    let level = sum as f64 / count.max(1) as f64 / u16::MAX as f64;
+                    SHADES[((1.0 - level) * (SHADES.len() - 1) as f64).round() as usize]
+                }
+            });
+        }
+    }
+    map
+}
+
+/// Mix two colors evenly, keeping the opacity of the first
+fn blend(pixel: Rgba<u8>, color: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b, a] = pixel.0;
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    Rgba([mix(r, color.0[0]), mix(g, color.0[1]), mix(b, color.0[2]), a.max(128)])
+}
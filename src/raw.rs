@@ -0,0 +1,42 @@
+use std::path::Path;
+use image::DynamicImage;
+
+/// Extensions of camera RAW images, which are demosaiced with rawloader rather than decoded by the image crate
+const EXTENSIONS: [&str; 10] = ["cr2", "nef", "nrw", "arw", "dng", "orf", "rw2", "raf", "pef", "srw"];
+
+/// Check whether a path has the extension of a camera RAW image
+pub fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|raw| ext.eq_ignore_ascii_case(raw)))
+}
+
+/// Demosaic a camera RAW image with rawloader and imagepipe at 16 bits per channel, with the white
+/// balance of the camera and the sRGB transfer function, rotating it as displayed if requested
+#[cfg(feature = "raw")]
+pub fn decode(path: &Path, auto_orient: bool) -> cpar::Result<DynamicImage> {
+    use std::io;
+    use image::ImageBuffer;
+    use imagepipe::color_conversions::output16bit;
+    use imagepipe::transform::OpTransform;
+    use imagepipe::{ImageSource, Pipeline, Rotation};
+
+    let invalid = |err: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidData, format!("RAW: {}", err));
+    let raw = rawloader::decode_file(path).map_err(|err| invalid(&err))?;
+    let mut pipeline = Pipeline::new_from_source(ImageSource::Raw(raw)).map_err(|err| invalid(&err))?;
+    if !auto_orient {
+        pipeline.ops.transform = OpTransform { rotation: Rotation::Normal, fliph: false, flipv: false };
+    }
+    // Output of 16 bits per channel is otherwise left in linear light
+    pipeline.globals.settings.linear = false;
+    let buffer = pipeline.run(None);
+    let samples = buffer.data.iter().map(|&sample| output16bit(sample)).collect();
+    let img = ImageBuffer::from_raw(buffer.width as u32, buffer.height as u32, samples);
+    Ok(DynamicImage::ImageRgb16(img.ok_or_else(|| invalid(&"image data does not match its dimensions"))?))
+}
+
+/// Fail to decode a camera RAW image, as support for them was not built
+#[cfg(not(feature = "raw"))]
+pub fn decode(_: &Path, _: bool) -> cpar::Result<DynamicImage> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "decoding RAW images requires building with the raw feature").into())
+}
@@ -0,0 +1,74 @@
+use image::DynamicImage;
+use image::imageops::FilterType;
+use crate::detect::{detect_edges, thresholds};
+use crate::{DetectMode, EdgeDetection, Sides};
+
+/// Longest side of the copy of the coarse crop that the refining pass detects edges within
+const REFINE_SIZE: u32 = 1024;
+
+/// Fraction of the 16-bit scale, or of the 8-bit tolerance in background mode, by which the
+/// refining pass moves thresholds toward the artwork
+const TIGHTEN: f32 = 0.1;
+
+/// Detection for the refining pass within the coarse crop of an image, with the thresholds found
+/// for the whole image moved toward the artwork so that faint shadows count as background.
+///
+/// Returns `None` for gradient, variance and exact modes, which do not compare against a
+/// threshold.
+pub(crate) fn tightened(img: &DynamicImage, detection: &EdgeDetection) -> Option<EdgeDetection> {
+    let step = (TIGHTEN * u16::MAX as f32) as u16;
+    let mode = match detection.mode {
+        DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact => return None,
+        DetectMode::Background { color, tolerance } => DetectMode::Background { color, tolerance: tolerance + TIGHTEN * 255.0 },
+        mode => mode
+    };
+    let tighten = |threshold: u16| match mode {
+        DetectMode::Alpha => threshold.saturating_add(step),
+        _ => threshold.saturating_sub(step)
+    };
+    let threshold = thresholds(img, detection);
+    let threshold = Sides {
+        left: tighten(threshold.left),
+        right: tighten(threshold.right),
+        top: tighten(threshold.top),
+        bottom: tighten(threshold.bottom)
+    };
+    Some(EdgeDetection {
+        mode,
+        threshold,
+        auto_threshold: false,
+        range_threshold: None,
+        region: None,
+        ignore_margin: 0,
+        mask: None,
+        refine: false,
+        ..detection.clone()
+    })
+}
+
+/// Move coarse edges inward by detecting edges again within them, on a copy downscaled to at most
+/// [`REFINE_SIZE`] pixels on its longer side. Edges are kept where nothing is detected.
+pub(crate) fn refine(img: &DynamicImage, coarse: Sides<u32>, tightened: &EdgeDetection) -> Sides<u32> {
+    // Edges are the positions of the outermost rows and columns of artwork, so are kept within
+    let (x, y) = (coarse.left, coarse.top);
+    let width = (coarse.right.saturating_sub(x) + 1).min(img.width() - x);
+    let height = (coarse.bottom.saturating_sub(y) + 1).min(img.height() - y);
+    let region = img.crop_imm(x, y, width, height);
+    let scaled = match width.max(height) > REFINE_SIZE {
+        true => region.resize(REFINE_SIZE, REFINE_SIZE, FilterType::Triangle),
+        false => region
+    };
+    let Some(edges) = detect_edges(&scaled, tightened) else {
+        return coarse;
+    };
+
+    // Scale edges back outward, so that scaling never removes artwork the refined edges keep
+    let (scale_x, scale_y) = (width as f32 / scaled.width() as f32, height as f32 / scaled.height() as f32);
+    let outward = |at: u32, scale: f32, round: fn(f32) -> f32| round(at as f32 * scale) as u32;
+    Sides {
+        left: (x + outward(edges.left, scale_x, f32::floor)).clamp(coarse.left, coarse.right),
+        right: (x + outward(edges.right, scale_x, f32::ceil)).clamp(coarse.left, coarse.right),
+        top: (y + outward(edges.top, scale_y, f32::floor)).clamp(coarse.top, coarse.bottom),
+        bottom: (y + outward(edges.bottom, scale_y, f32::ceil)).clamp(coarse.top, coarse.bottom)
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use cpar::{CropRect, Sides};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Result of processing a single source image
+#[derive(Serialize)]
+pub struct Record {
+    #[serde(serialize_with = "lossy")]
+    pub source: PathBuf,
+    #[serde(serialize_with = "lossy_option")]
+    pub dest: Option<PathBuf>,
+    pub status: Status,
+    pub error: Option<String>,
+    /// Dimensions of source image
+    pub original: Option<Size>,
+    /// Detected edges, before removing extra margin
+    pub edges: Option<Edges>,
+    /// Rectangle of source image kept
+    pub crop: Option<Rect>,
+    /// Dimensions of output image
+    pub output: Option<Size>,
+    /// EXIF orientation applied when decoding the source, where it was not upright
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u8>,
+    /// Clockwise quarter turn in degrees applied by --rotate before cropping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<u16>,
+    /// Clockwise rotation in degrees corrected before cropping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skew: Option<f32>,
+    /// Resolution of source image in dots per inch, where recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<f32>,
+    /// Number of pages of a multipage source, whose other fields describe its first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+    /// Number of panels of a split source, whose other fields describe its first panel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panels: Option<usize>,
+    /// Reason the image was flagged for review, such as its crop falling below the minimum size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    /// Source processed earlier in the batch whose crop this image duplicates, with --dedupe
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "lossy_option")]
+    pub duplicate_of: Option<PathBuf>
+}
+
+impl Record {
+    pub fn new(source: &Path) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            dest: None,
+            status: Status::Failed,
+            error: None,
+            original: None,
+            edges: None,
+            crop: None,
+            output: None,
+            orientation: None,
+            rotation: None,
+            skew: None,
+            dpi: None,
+            pages: None,
+            panels: None,
+            flag: None,
+            duplicate_of: None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Saved,
+    Reported,
+    Skipped,
+    Failed
+}
+
+#[derive(Serialize)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32
+}
+
+impl From<(u32, u32)> for Size {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self { width, height }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Edges {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32
+}
+
+impl From<Sides<u32>> for Edges {
+    fn from(sides: Sides<u32>) -> Self {
+        Self { left: sides.left, right: sides.right, top: sides.top, bottom: sides.bottom }
+    }
+}
+
+impl From<&Edges> for Sides<u32> {
+    fn from(edges: &Edges) -> Self {
+        Self { left: edges.left, right: edges.right, top: edges.top, bottom: edges.bottom }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl From<CropRect> for Rect {
+    fn from(rect: CropRect) -> Self {
+        Self { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+}
+
+impl From<Rect> for CropRect {
+    fn from(rect: Rect) -> Self {
+        Self { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+}
+
+/// Crop detected in a source image, as listed by `cpar detect` and read back by `cpar apply`,
+/// which also reads the records of a report
+#[derive(Serialize, Deserialize)]
+pub struct Detection {
+    #[serde(serialize_with = "lossy")]
+    pub source: PathBuf,
+    pub crop: Option<Rect>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+/// Serialize a path as a string, replacing any part which is not valid UTF-8 rather than failing
+pub fn lossy<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// Serialize an optional path as with [`lossy`]
+pub fn lossy_option<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&*path.to_string_lossy()),
+        None => serializer.serialize_none()
+    }
+}
+
+/// Serialize a path so that it can be read back as it was, as a string where it is valid UTF-8
+/// and otherwise as its bytes
+pub fn lossless<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(path) => serializer.serialize_str(path),
+        None => serializer.serialize_bytes(&crate::sources::to_bytes(path))
+    }
+}
+
+/// Deserialize a path serialized by [`lossless`]
+pub fn from_lossless<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Text(PathBuf),
+        Bytes(Vec<u8>)
+    }
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Text(path) => path,
+        Stored::Bytes(bytes) => crate::sources::from_bytes(&bytes)
+    })
+}
+
+/// Serialize an optional path as with [`lossless`]
+pub fn lossless_option<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => lossless(path, serializer),
+        None => serializer.serialize_none()
+    }
+}
+
+/// Deserialize an optional path serialized by [`lossless_option`]
+pub fn from_lossless_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathBuf>, D::Error> {
+    #[derive(Deserialize)]
+    struct Stored(#[serde(deserialize_with = "from_lossless")] PathBuf);
+    Ok(Option::<Stored>::deserialize(deserializer)?.map(|Stored(path)| path))
+}
+
+/// Write processing records as a JSON report
+pub fn write(path: &Path, records: &[Record]) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, records)?;
+    Ok(())
+}
+
+/// Write the source paths of records, one per line
+pub fn write_failed(path: &Path, records: &[&Record]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for record in records {
+        writer.write_all(&crate::sources::to_bytes(&record.source))?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Write the error of an image which failed, and the options it was processed with, as text
+pub fn write_error(path: &Path, source: &Path, error: &str, settings: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "source: {}\nerror: {}\noptions: {}", source.display(), error, settings)?;
+    writer.flush()
+}
+
+/// Write a crop rectangle as JSON
+pub fn write_crop(path: &Path, crop: &Rect) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, crop)?;
+    Ok(())
+}
+
+/// Read a crop rectangle written as JSON, in the form of the crop of a record
+pub fn read_crop(path: &Path) -> io::Result<CropRect> {
+    let context = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+    let file = File::open(path).map_err(|err| io::Error::new(err.kind(), context(&err)))?;
+    let rect: Rect = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, context(&err)))?;
+    if rect.width == 0 || rect.height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, context(&"crop is empty")));
+    }
+    Ok(rect.into())
+}
+
+/// Read the crops of source images written as JSON by `cpar detect`, or the records of a report
+pub fn read_detections(path: &Path) -> io::Result<Vec<Detection>> {
+    let context = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+    let file = File::open(path).map_err(|err| io::Error::new(err.kind(), context(&err)))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, context(&err)))
+}
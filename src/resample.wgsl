@@ -0,0 +1,41 @@
+// Separable resampling of RGBA pixels along one axis, each output pixel a weighted sum of a run
+// of consecutive input pixels starting at its own offset
+
+struct Params {
+    src_width: u32,
+    src_height: u32,
+    dst_width: u32,
+    dst_height: u32,
+    taps: u32,
+    vertical: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> src: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> dst: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read> starts: array<u32>;
+@group(0) @binding(4) var<storage, read> weights: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.dst_width || id.y >= params.dst_height {
+        return;
+    }
+    var sum = vec4<f32>(0.0);
+    if params.vertical == 1u {
+        let start = starts[id.y];
+        for (var i = 0u; i < params.taps; i++) {
+            let y = min(start + i, params.src_height - 1u);
+            sum += weights[id.y * params.taps + i] * src[y * params.src_width + id.x];
+        }
+    } else {
+        // The horizontal pass is last, so clamps to the displayable range as on the CPU
+        let start = starts[id.x];
+        for (var i = 0u; i < params.taps; i++) {
+            let x = min(start + i, params.src_width - 1u);
+            sum += weights[id.x * params.taps + i] * src[id.y * params.src_width + x];
+        }
+        sum = clamp(sum, vec4<f32>(0.0), vec4<f32>(1.0));
+    }
+    dst[id.y * params.dst_width + id.x] = sum;
+}
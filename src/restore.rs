@@ -0,0 +1,130 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::Args;
+use cpar::RestoreMode;
+use image::{DynamicImage, GenericImageView, Rgba};
+use image::imageops::FilterType;
+use crate::colorspace::Dither;
+use crate::save::{self, SaveOptions};
+use crate::{dpi, open, svg, xmp};
+
+#[derive(Args)]
+#[command(override_usage = "cpar restore [OPTIONS] <IMAGE>... <OUTPUT>")]
+/// Pad images saved with --xmp back to the dimensions of their originals, placing the artwork
+/// where it was cropped from and filling the border cropped away with a background color. A
+/// best-effort undo where the originals were lost: the border itself cannot be recovered
+pub struct Restore {
+    /// Images saved with --xmp, whose crops are read from their XMP metadata or FILE.xmp sidecars,
+    /// followed by the output folder to place restored images within
+    #[clap(num_args = 2.., required = true)]
+    images: Vec<PathBuf>,
+    /// Color filling the border cropped away
+    #[clap(long, default_value = "#FFFFFF", value_parser = cpar::parse_color)]
+    background: Rgba<u8>,
+    /// Quality (1-100) of lossy formats
+    #[clap(short, long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Replace images already in the output folder
+    #[clap(long)]
+    overwrite: bool
+}
+
+/// Restore each image into the output folder, exiting with 1 if any could not be restored
+pub fn run(mut restore: Restore) -> ExitCode {
+    let output = restore.images.pop().expect("at least two paths are required");
+    if let Err(err) = fs::create_dir_all(&output) {
+        eprintln!("Error: {}: {}", output.display(), err);
+        return ExitCode::from(crate::FATAL);
+    }
+    let mut failed = false;
+    for path in &restore.images {
+        let dest = output.join(path.file_name().unwrap_or(path.as_os_str()));
+        match restore_into(path, &dest, &restore) {
+            Ok((width, height)) => println!("Restored {} to {}x{}, saved to {}", path.display(), width, height, dest.display()),
+            Err(err) => {
+                eprintln!("Error: {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS
+    }
+}
+
+/// Restore an image and save it to its destination, returning its restored dimensions
+fn restore_into(path: &Path, dest: &Path, restore: &Restore) -> cpar::Result<(u32, u32)> {
+    if !restore.overwrite && dest.exists() {
+        let message = format!("{} already exists, use --overwrite", dest.display());
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
+    }
+    let (img, dpi) = restored(path, restore.background)?;
+    let options = SaveOptions {
+        format: None,
+        quality: restore.quality,
+        speed: None,
+        png_compression: None,
+        colorspace: None,
+        dither: Dither::None,
+        deterministic: false,
+        xmp: None,
+        dpi,
+        verify: None
+    };
+    save::save(&img, dest, &options)?;
+    Ok(img.dimensions())
+}
+
+/// Image padded back to the dimensions of its original and turned back as it was displayed, with
+/// the resolution it then has
+fn restored(path: &Path, background: Rgba<u8>) -> cpar::Result<(DynamicImage, Option<f32>)> {
+    let data = match fs::read(save::xmp_sidecar(path)) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => fs::read(path)?,
+        Err(err) => return Err(err.into())
+    };
+    let recorded = xmp::read(&data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no crop recorded, expected an image saved with --xmp"))?;
+    let rect = recorded.rect;
+    if rect.width == 0 || rect.height == 0 || rect.x + rect.width > recorded.width || rect.y + rect.height > recorded.height {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("recorded crop {} lies outside the original {}x{}", rect, recorded.width, recorded.height)
+        ).into());
+    }
+
+    // Padded images hold the artwork centered, spanning the side which was not padded
+    let (img, _) = open(path, false, svg::DEFAULT_DPI)?;
+    let artwork = match recorded.restore {
+        RestoreMode::Pad => {
+            let scale = (img.width() as f32 / rect.width as f32).min(img.height() as f32 / rect.height as f32);
+            let width = ((rect.width as f32 * scale).round() as u32).clamp(1, img.width());
+            let height = ((rect.height as f32 * scale).round() as u32).clamp(1, img.height());
+            img.crop_imm((img.width() - width) / 2, (img.height() - height) / 2, width, height)
+        },
+        RestoreMode::Resize | RestoreMode::Crop => img
+    };
+    let dpi = dpi::read(path).map(|dpi| dpi * rect.width as f32 / artwork.width() as f32);
+    let artwork = match artwork.dimensions() == (rect.width, rect.height) {
+        true => artwork,
+        false => artwork.resize_exact(rect.width, rect.height, FilterType::Lanczos3)
+    };
+    let restored = cpar::pad_at(&artwork, recorded.width, recorded.height, rect.x, rect.y, background);
+
+    // Rotate back by the skew corrected before cropping, then by the quarter turn before that,
+    // leaving the EXIF orientation applied as restored images are saved without one
+    let restored = match recorded.skew {
+        Some(angle) => cpar::rotate(&restored, angle, background),
+        None => restored
+    };
+    let restored = match recorded.rotation {
+        Some(90) => restored.rotate270(),
+        Some(180) => restored.rotate180(),
+        Some(270) => restored.rotate90(),
+        _ => restored
+    };
+    Ok((restored, dpi))
+}
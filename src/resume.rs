@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+
+/// Name of the state file kept within the output folder by default
+pub const STATE_FILE: &str = ".cpar-state.jsonl";
+
+/// Source image completed by a run, as one line of the state file
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(serialize_with = "crate::report::lossless", deserialize_with = "crate::report::from_lossless")]
+    source: PathBuf,
+    #[serde(flatten)]
+    fingerprint: Fingerprint,
+    /// CRC-32 checksum of the contents of the source
+    checksum: u32
+}
+
+/// Size and modification time of a source image, which recognize it once renamed
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Fingerprint {
+    size: u64,
+    /// Nanoseconds since the Unix epoch
+    modified: u64
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(Self { size: metadata.len(), modified: modified.as_nanos() as u64 })
+    }
+}
+
+/// Sources completed by previous runs, appended to as each source of this run is completed so
+/// that an interrupted run can be resumed
+pub struct State {
+    completed: HashMap<PathBuf, Fingerprint>,
+    /// Checksums of completed sources by their fingerprint, to confirm that a renamed source has
+    /// the contents of one completed under its old name
+    checksums: HashMap<Fingerprint, HashSet<u32>>,
+    /// State file to record completed sources in, unless only reading it
+    file: Option<Mutex<File>>
+}
+
+impl State {
+    /// Load the state file, creating it if it does not exist and completed sources are to be
+    /// recorded. A line left incomplete by an interrupted run is ignored.
+    pub fn open(path: &Path, record: bool) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err)
+        };
+        let (mut completed, mut checksums) = (HashMap::new(), HashMap::<_, HashSet<_>>::new());
+        for entry in contents.lines().filter_map(|line| serde_json::from_str::<Entry>(line).ok()) {
+            checksums.entry(entry.fingerprint).or_default().insert(entry.checksum);
+            completed.insert(entry.source, entry.fingerprint);
+        }
+        let file = match record {
+            true => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                // Start a fresh line after one left incomplete
+                if !contents.is_empty() && !contents.ends_with('\n') {
+                    file.write_all(b"\n")?;
+                }
+                Some(Mutex::new(file))
+            },
+            false => None
+        };
+        Ok(Self { completed, checksums, file })
+    }
+
+    /// Check whether a source was completed by a previous run, under its path or renamed since. A
+    /// source with the size and modification time of one completed under another path is only
+    /// taken to be renamed when its checksum matches too.
+    pub fn is_completed(&self, path: &Path) -> bool {
+        let Ok(fingerprint) = Fingerprint::of(path) else {
+            return false;
+        };
+        if self.completed.get(path) == Some(&fingerprint) {
+            return true;
+        }
+        self.checksums.get(&fingerprint)
+            .is_some_and(|checksums| fs::read(path).is_ok_and(|data| checksums.contains(&crc32fast::hash(&data))))
+    }
+
+    /// Record a source as completed, writing it through to the state file at once
+    pub fn complete(&self, path: &Path) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let (fingerprint, checksum) = (Fingerprint::of(path)?, crc32fast::hash(&fs::read(path)?));
+        let entry = Entry { source: path.to_path_buf(), fingerprint, checksum };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
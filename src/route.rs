@@ -0,0 +1,107 @@
+use std::cell::OnceCell;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use cpar::CropRect;
+
+/// Rule sending the outputs whose crop meets a condition into a folder within the output folder,
+/// such as `cropped>50%:heavily_cropped/`
+#[derive(Clone, Debug)]
+pub struct Route {
+    measure: Measure,
+    comparison: Comparison,
+    value: f32,
+    folder: PathBuf
+}
+
+/// Property of a crop compared by a route
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measure {
+    /// Percentage of the area of the image removed by its crop
+    Cropped,
+    /// Width of the output image in pixels
+    Width,
+    /// Height of the output image in pixels
+    Height,
+    /// Percentage of the rows and columns crossing threshold near the edges placed
+    Confidence
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Above,
+    AtLeast,
+    Below,
+    AtMost
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid route '{}', expected CONDITION:FOLDER such as cropped>50%:heavily_cropped/", s);
+        let (condition, folder) = s.split_once(':').ok_or_else(invalid)?;
+        let (at, op) = [">=", "<=", ">", "<"].iter()
+            .find_map(|&op| Some((condition.find(op)?, op)))
+            .ok_or_else(invalid)?;
+        let comparison = match op {
+            ">=" => Comparison::AtLeast,
+            "<=" => Comparison::AtMost,
+            ">" => Comparison::Above,
+            _ => Comparison::Below
+        };
+        let (name, value) = (condition[..at].trim(), condition[at + op.len()..].trim());
+        let (measure, unit) = match name {
+            "cropped" => (Measure::Cropped, "%"),
+            "width" => (Measure::Width, "px"),
+            "height" => (Measure::Height, "px"),
+            "confidence" => (Measure::Confidence, "%"),
+            _ => return Err(format!("unknown route measure '{}', expected cropped, width, height or confidence", name))
+        };
+        let value = value.strip_suffix(unit).unwrap_or(value).parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(invalid)?;
+        let folder = PathBuf::from(folder);
+        let inside = folder.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if folder.as_os_str().is_empty() || !inside {
+            return Err(format!("invalid route folder '{}', expected a folder within the output folder", folder.display()));
+        }
+        Ok(Route { measure, comparison, value, folder })
+    }
+}
+
+impl Route {
+    fn matches(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.value,
+            Comparison::AtLeast => value >= self.value,
+            Comparison::Below => value < self.value,
+            Comparison::AtMost => value <= self.value
+        }
+    }
+}
+
+/// Folder of the first route whose condition a crop meets, measuring it only as needed
+pub fn folder(routes: &[Route], measure: impl Fn(Measure) -> f32) -> Option<&Path> {
+    let measures = [Measure::Cropped, Measure::Width, Measure::Height, Measure::Confidence].map(|_| OnceCell::new());
+    routes.iter()
+        .find(|route| route.matches(*measures[route.measure as usize].get_or_init(|| measure(route.measure))))
+        .map(|route| route.folder.as_path())
+}
+
+/// Check whether any route compares a measure
+pub fn uses(routes: &[Route], measure: Measure) -> bool {
+    routes.iter().any(|route| route.measure == measure)
+}
+
+/// Percentage of the area of an image removed by a crop
+pub fn cropped(rect: &CropRect, width: u32, height: u32) -> f32 {
+    100.0 * (1.0 - (rect.width as f64 * rect.height as f64) / (width as f64 * height as f64)) as f32
+}
+
+/// Destination of an output moved into a folder within the output folder, keeping its path
+/// relative to the output folder
+pub fn routed(dest: &Path, output: &Path, folder: &Path) -> PathBuf {
+    let relative = dest.strip_prefix(output).unwrap_or(dest.file_name().map_or(dest, Path::new));
+    output.join(folder).join(relative)
+}
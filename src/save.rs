@@ -0,0 +1,518 @@
+use std::{fs, io, process};
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use image::{ColorType, DynamicImage, Frame, GenericImageView, ImageError, ImageFormat, ImageReader, ImageResult};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::error::{EncodingError, ImageFormatHint};
+use zune_core::bit_depth::BitDepth;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::EncoderOptions;
+use zune_jpegxl::JxlSimpleEncoder;
+use crate::colorspace::{self, Colorspace, Dither};
+use crate::{archive, dpi, pages, xmp};
+
+/// Default encoding speed (1-10) and quality (1-100) of AVIF output, as used by its encoder
+const AVIF_SPEED: u8 = 4;
+const AVIF_QUALITY: u8 = 80;
+
+/// Default encoding quality (1-100) of JPEG output, as used by its encoder
+const JPEG_QUALITY: u8 = 75;
+
+/// Offset of the end of the IHDR chunk of a PNG, after the 8 byte signature, which metadata chunks
+/// are inserted at
+pub const PNG_HEADER_END: usize = 8 + 25;
+
+/// Format images are saved in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Format encoded by the image crate
+    Image(ImageFormat),
+    /// Lossless JPEG XL
+    Jxl
+}
+
+impl OutputFormat {
+    /// Determine the format from the extension of a path
+    pub fn from_path(path: &Path) -> ImageResult<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("jxl") => Ok(OutputFormat::Jxl),
+            _ => ImageFormat::from_path(path).map(OutputFormat::Image)
+        }
+    }
+
+    /// Preferred file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Image(format) => format.extensions_str()[0],
+            OutputFormat::Jxl => "jxl"
+        }
+    }
+
+    /// MIME type of the format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Image(format) => format.to_mime_type(),
+            OutputFormat::Jxl => "image/jxl"
+        }
+    }
+}
+
+impl From<ImageFormat> for OutputFormat {
+    fn from(format: ImageFormat) -> Self {
+        OutputFormat::Image(format)
+    }
+}
+
+/// Encoder settings for saved images
+pub struct SaveOptions {
+    /// Output format, otherwise determined from the destination extension
+    pub format: Option<OutputFormat>,
+    /// Quality (1-100) of lossy formats
+    pub quality: Option<u8>,
+    /// Encoding speed (1-10) of AVIF output
+    pub speed: Option<u8>,
+    /// Compression level of PNG output
+    pub png_compression: Option<CompressionType>,
+    /// Colorspace to convert images to, otherwise keeping their own
+    pub colorspace: Option<Colorspace>,
+    /// Dithering applied when converting to 8 bits per channel
+    pub dither: Dither,
+    /// Encode with fixed settings on a single thread, rather than leaving settings to encoders, so
+    /// that output is byte-identical between runs
+    pub deterministic: bool,
+    /// XMP packet embedded in saved images, or written beside those in formats which cannot hold
+    /// it
+    pub xmp: Option<String>,
+    /// Resolution in dots per inch recorded in saved images, in formats which can hold it
+    pub dpi: Option<f32>,
+    /// Check made of saved images by decoding them again
+    pub verify: Option<Verify>
+}
+
+/// Check made of saved images by decoding them again, catching encoders which fail silently
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Verify {
+    /// Check that images decode at the dimensions they were saved at
+    Dimensions,
+    /// Also check that a checksum of the decoded pixels matches that of the pixels encoded, for
+    /// images saved in lossless formats
+    Checksum
+}
+
+/// Behaviour when the destination of an image already exists
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// Fail to process the image
+    Fail,
+    /// Replace the existing file
+    Overwrite,
+    /// Skip processing the image
+    Skip,
+    /// Append a numeric suffix to the destination filename
+    Rename
+}
+
+/// Claim the destination of an image according to the existing file policy.
+///
+/// Destinations are created exclusively, so images processed concurrently never share one. Returns
+/// `None` if the image should be skipped.
+pub fn claim(dest: &Path, existing: Existing) -> io::Result<Option<PathBuf>> {
+    if existing == Existing::Overwrite {
+        return Ok(Some(dest.to_path_buf()));
+    }
+    for n in 0.. {
+        let candidate = match n {
+            0 => dest.to_path_buf(),
+            _ => numbered(dest, n)
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&candidate) {
+            Ok(_) => return Ok(Some(candidate)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => match existing {
+                Existing::Fail => return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists, use --overwrite, --skip-existing or --rename", dest.display())
+                )),
+                Existing::Skip => return Ok(None),
+                _ => continue
+            },
+            Err(err) => return Err(err)
+        }
+    }
+    unreachable!()
+}
+
+/// Claim the destination of an image as with [`claim`] and save it there, releasing the claimed
+/// destination again if saving fails so that it does not block a later run. Returns the
+/// destination saved to, or `None` if the image should be skipped.
+pub fn save_claimed<E: From<io::Error>>(
+    dest: &Path,
+    existing: Existing,
+    save: impl FnOnce(&Path) -> Result<(), E>
+) -> Result<Option<PathBuf>, E> {
+    let Some(dest) = claim(dest, existing)? else {
+        return Ok(None);
+    };
+    if let Err(err) = save(&dest) {
+        if existing != Existing::Overwrite {
+            let _ = fs::remove_file(&dest);
+        }
+        return Err(err);
+    }
+    Ok(Some(dest))
+}
+
+/// Destination with a numeric suffix appended to its filename
+fn numbered(dest: &Path, n: usize) -> PathBuf {
+    affixed(dest, "", &format!("-{}", n))
+}
+
+/// Destination with a prefix and suffix around the stem of its filename, keeping its extension.
+/// Filenames are joined as OS strings, so that names which are not valid UTF-8 are kept intact.
+pub fn affixed(dest: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(prefix);
+    name.push(dest.file_stem().unwrap_or_default());
+    name.push(suffix);
+    if let Some(ext) = dest.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    dest.with_file_name(name)
+}
+
+/// Parse an output format from its name or file extension
+pub fn parse_format(s: &str) -> Result<OutputFormat, String> {
+    if s.eq_ignore_ascii_case("jxl") {
+        return Ok(OutputFormat::Jxl);
+    }
+    ImageFormat::from_extension(s)
+        .filter(ImageFormat::writing_enabled)
+        .map(OutputFormat::Image)
+        .ok_or_else(|| format!("unsupported output format '{}'", s))
+}
+
+/// Parse a PNG compression level
+pub fn parse_png_compression(s: &str) -> Result<CompressionType, String> {
+    match s {
+        "fast" => Ok(CompressionType::Fast),
+        "default" => Ok(CompressionType::Default),
+        "best" => Ok(CompressionType::Best),
+        _ => Err(format!("unknown PNG compression '{}', expected fast, default or best", s))
+    }
+}
+
+/// Save an image, using the configured format or otherwise the format of the destination extension.
+///
+/// The image is written to a temporary file alongside the destination which then replaces it, so
+/// the destination never contains a partially written image.
+pub fn save(img: &DynamicImage, dest: &Path, options: &SaveOptions) -> ImageResult<()> {
+    let format = match options.format {
+        Some(format) => format,
+        None => OutputFormat::from_path(dest)?
+    };
+    persist(dest, |temp| {
+        encode(img, temp, format, options)?;
+        verify(BufReader::new(File::open(temp)?), img, format, options)
+    })?;
+    match &options.xmp {
+        Some(packet) if !xmp::embeds(format) => persist(&xmp_sidecar(dest), |temp| Ok(fs::write(temp, packet)?)),
+        _ => Ok(())
+    }
+}
+
+/// Sidecar file beside a saved image holding its XMP packet
+pub fn xmp_sidecar(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".xmp");
+    dest.with_file_name(name)
+}
+
+/// Check an encoded image by decoding it again, if requested, failing if it does not decode as it
+/// was encoded. Formats and color types which cannot be decoded, such as JPEG XL, are not checked.
+pub fn verify<R: BufRead + Seek>(reader: R, img: &DynamicImage, format: OutputFormat, options: &SaveOptions) -> ImageResult<()> {
+    let (Some(check), OutputFormat::Image(format)) = (options.verify, format) else {
+        return Ok(());
+    };
+    let decoded = match ImageReader::with_format(reader, format).decode() {
+        Ok(decoded) => decoded,
+        Err(ImageError::Unsupported(_)) => return Ok(()),
+        Err(err) => return Err(unverified(format!("saved image cannot be decoded: {}", err)))
+    };
+    let encoded = encoded(img, format, options);
+    let ((width, height), (expected_width, expected_height)) = (decoded.dimensions(), encoded.dimensions());
+    if (width, height) != (expected_width, expected_height) {
+        return Err(unverified(format!(
+            "saved image decodes at {}x{} rather than {}x{}", width, height, expected_width, expected_height
+        )));
+    }
+    if check == Verify::Checksum && lossless(format, options) {
+        let found = crc32fast::hash(cpar::convert(decoded, encoded.color()).as_bytes());
+        let expected = crc32fast::hash(encoded.as_bytes());
+        if found != expected {
+            return Err(unverified(format!("saved image pixels have checksum {:08x} rather than {:08x}", found, expected)));
+        }
+    }
+    Ok(())
+}
+
+/// Image as its pixels are encoded in a format, converted to the configured colorspace and to a
+/// color type the format can encode
+fn encoded<'a>(img: &'a DynamicImage, format: ImageFormat, options: &SaveOptions) -> Cow<'a, DynamicImage> {
+    let mut img = match options.colorspace.and_then(|colorspace| colorspace::convert(img, colorspace, options.dither)) {
+        Some(converted) => Cow::Owned(converted),
+        None => Cow::Borrowed(img)
+    };
+    while let Some(converted) = encodable(&img, format) {
+        img = Cow::Owned(converted);
+    }
+    img
+}
+
+/// Check whether a format encodes pixels exactly, rather than approximating them as JPEG, AVIF,
+/// lossy WebP, GIF palettes and HDR's shared exponents do
+fn lossless(format: ImageFormat, options: &SaveOptions) -> bool {
+    match format {
+        ImageFormat::WebP => options.quality.is_none(),
+        _ => !matches!(format, ImageFormat::Jpeg | ImageFormat::Avif | ImageFormat::Gif | ImageFormat::Hdr)
+    }
+}
+
+/// Error of a saved image which does not decode as it was encoded
+fn unverified(message: String) -> ImageError {
+    ImageError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Save frames of an animation as an infinitely repeating GIF
+pub fn save_frames(frames: Vec<Frame>, dest: &Path) -> ImageResult<()> {
+    persist(dest, |temp| {
+        let mut encoder = GifEncoder::new(BufWriter::new(File::create(temp)?));
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)
+    })
+}
+
+/// Save entries to a new ZIP archive
+pub fn save_archive(entries: &[archive::Entry], dest: &Path) -> ImageResult<()> {
+    persist(dest, |temp| archive::write(entries, temp))
+}
+
+/// Save pages as a multipage TIFF, converted to the configured colorspace
+pub fn save_pages(pages: Vec<DynamicImage>, dest: &Path, options: &SaveOptions) -> ImageResult<()> {
+    let pages: Vec<_> = match options.colorspace {
+        Some(colorspace) => pages.into_iter()
+            .map(|page| colorspace::convert(&page, colorspace, options.dither).unwrap_or(page))
+            .collect(),
+        None => pages
+    };
+    persist(dest, |temp| pages::write_pages(&pages, temp))
+}
+
+/// Convert an image to a color type the format can encode, keeping its bit depth where the format
+/// supports it. Returns `None` if the image can be encoded as is.
+fn encodable(img: &DynamicImage, format: ImageFormat) -> Option<DynamicImage> {
+    let color = img.color();
+    let alpha = color.has_alpha();
+    match format {
+        // JPEG has no support for transparency or high bit depths
+        ImageFormat::Jpeg => match color {
+            ColorType::L8 | ColorType::Rgb8 => None,
+            ColorType::L16 => Some(DynamicImage::ImageLuma8(img.to_luma8())),
+            _ => Some(DynamicImage::ImageRgb8(img.to_rgb8()))
+        },
+        // Floating point images are reduced to 16 bits
+        ImageFormat::Png | ImageFormat::Pnm | ImageFormat::Avif | ImageFormat::Ico => match color {
+            ColorType::Rgb32F => Some(DynamicImage::ImageRgb16(img.to_rgb16())),
+            ColorType::Rgba32F => Some(DynamicImage::ImageRgba16(img.to_rgba16())),
+            ColorType::La16 if format == ImageFormat::Pnm => Some(DynamicImage::ImageRgba16(img.to_rgba16())),
+            _ => None
+        },
+        // TIFF has no support for grayscale with alpha
+        ImageFormat::Tiff => match color {
+            ColorType::La8 => Some(DynamicImage::ImageRgba8(img.to_rgba8())),
+            ColorType::La16 => Some(DynamicImage::ImageRgba16(img.to_rgba16())),
+            _ => None
+        },
+        ImageFormat::OpenExr => match color {
+            ColorType::Rgb32F | ColorType::Rgba32F => None,
+            _ if alpha => Some(DynamicImage::ImageRgba32F(img.to_rgba32f())),
+            _ => Some(DynamicImage::ImageRgb32F(img.to_rgb32f()))
+        },
+        ImageFormat::Hdr if color != ColorType::Rgb32F => Some(DynamicImage::ImageRgb32F(img.to_rgb32f())),
+        ImageFormat::Farbfeld if color != ColorType::Rgba16 => Some(DynamicImage::ImageRgba16(img.to_rgba16())),
+        ImageFormat::Qoi => match color {
+            ColorType::Rgb8 | ColorType::Rgba8 => None,
+            _ if alpha => Some(DynamicImage::ImageRgba8(img.to_rgba8())),
+            _ => Some(DynamicImage::ImageRgb8(img.to_rgb8()))
+        },
+        _ => None
+    }
+}
+
+/// Write a file through a temporary path alongside the destination which then replaces it
+fn persist(dest: &Path, write: impl FnOnce(&Path) -> ImageResult<()>) -> ImageResult<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut temp = OsString::from(".");
+    temp.push(dest.file_name().unwrap_or_default());
+    temp.push(format!(".{}-{}.partial", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let temp = dest.with_file_name(temp);
+    let result = write(&temp).and_then(|()| Ok(fs::rename(&temp, dest)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Encode an image to a file in the given format
+fn encode(img: &DynamicImage, path: &Path, format: OutputFormat, options: &SaveOptions) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(img, &mut writer, format, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encode an image in the given format
+pub fn write<W: Write + Seek>(
+    img: &DynamicImage,
+    writer: &mut W,
+    format: OutputFormat,
+    options: &SaveOptions
+) -> ImageResult<()> {
+    // Convert to the configured colorspace, unless the format cannot hold it
+    let converted = options.colorspace.and_then(|colorspace| colorspace::convert(img, colorspace, options.dither));
+    let img = converted.as_ref().unwrap_or(img);
+
+    // Encode to memory first where metadata is to be inserted among the encoded data
+    let packet = options.xmp.as_ref().filter(|_| xmp::embeds(format));
+    let dpi = options.dpi.filter(|_| dpi::embeds(format));
+    if packet.is_none() && dpi.is_none() {
+        return encode_format(img, writer, format, options);
+    }
+    let mut buffer = Cursor::new(Vec::new());
+    encode_format(img, &mut buffer, format, options)?;
+    let mut data = buffer.into_inner();
+    if let Some(dpi) = dpi {
+        data = dpi::embed(data, format, dpi);
+    }
+    if let Some(packet) = packet {
+        data = xmp::embed(data, format, img, packet);
+    }
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+fn encode_format<W: Write + Seek>(
+    img: &DynamicImage,
+    writer: &mut W,
+    format: OutputFormat,
+    options: &SaveOptions
+) -> ImageResult<()> {
+    match format {
+        OutputFormat::Image(format) => encode_image(img, writer, format, options),
+        OutputFormat::Jxl => write_jxl(img, writer)
+    }
+}
+
+/// Encode an image in a format of the image crate, converting it to a color type the format can
+/// encode
+fn encode_image<W: Write + Seek>(
+    img: &DynamicImage,
+    writer: &mut W,
+    format: ImageFormat,
+    options: &SaveOptions
+) -> ImageResult<()> {
+    if let Some(converted) = encodable(img, format) {
+        return encode_image(&converted, writer, format, options);
+    }
+
+    // Use configured encoder settings where given, otherwise the encoder defaults, which are fixed
+    // here when deterministic in case encoders change them
+    let deterministic = options.deterministic;
+    match (format, options.quality, options.png_compression) {
+        (ImageFormat::Jpeg, quality, _) if quality.is_some() || deterministic => {
+            img.write_with_encoder(JpegEncoder::new_with_quality(writer, quality.unwrap_or(JPEG_QUALITY)))
+        },
+        (ImageFormat::Avif, quality, _) if quality.is_some() || options.speed.is_some() || deterministic => {
+            let speed = options.speed.unwrap_or(AVIF_SPEED);
+            let encoder = AvifEncoder::new_with_speed_quality(writer, speed, quality.unwrap_or(AVIF_QUALITY));
+            img.write_with_encoder(match deterministic {
+                true => encoder.with_num_threads(Some(1)),
+                false => encoder
+            })
+        },
+        (ImageFormat::WebP, Some(quality), _) => write_webp(img, writer, quality),
+        (ImageFormat::Png, _, compression) if compression.is_some() || deterministic => {
+            let compression = compression.unwrap_or(CompressionType::Default);
+            img.write_with_encoder(PngEncoder::new_with_quality(writer, compression, FilterType::Adaptive))
+        },
+        _ => img.write_to(writer, format)
+    }
+}
+
+/// Encode an image as lossy WebP with libwebp at a quality (1-100), at 8 bits per channel
+#[cfg(feature = "webp")]
+fn write_webp<W: Write>(img: &DynamicImage, writer: &mut W, quality: u8) -> ImageResult<()> {
+    let (converted, alpha) = match img.color().has_alpha() {
+        true => (DynamicImage::ImageRgba8(img.to_rgba8()), true),
+        false => (DynamicImage::ImageRgb8(img.to_rgb8()), false)
+    };
+    let encoder = match alpha {
+        true => webp::Encoder::from_rgba(converted.as_bytes(), img.width(), img.height()),
+        false => webp::Encoder::from_rgb(converted.as_bytes(), img.width(), img.height())
+    };
+    let data = encoder.encode_simple(false, quality as f32)
+        .map_err(|err| ImageError::Encoding(EncodingError::new(ImageFormat::WebP.into(), format!("{:?}", err))))?;
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+/// Fail to encode lossy WebP, as support for it was not built
+#[cfg(not(feature = "webp"))]
+fn write_webp<W: Write>(_: &DynamicImage, _: &mut W, _: u8) -> ImageResult<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "lossy WebP output requires building with the webp feature").into())
+}
+
+/// Encode an image as lossless JPEG XL, at 8 or 16 bits per channel
+fn write_jxl<W: Write>(img: &DynamicImage, writer: &mut W) -> ImageResult<()> {
+    let (color, depth) = match img.color() {
+        ColorType::L8 => (ColorSpace::Luma, BitDepth::Eight),
+        ColorType::La8 => (ColorSpace::LumaA, BitDepth::Eight),
+        ColorType::Rgb8 => (ColorSpace::RGB, BitDepth::Eight),
+        ColorType::Rgba8 => (ColorSpace::RGBA, BitDepth::Eight),
+        ColorType::L16 => (ColorSpace::Luma, BitDepth::Sixteen),
+        ColorType::La16 => (ColorSpace::LumaA, BitDepth::Sixteen),
+        ColorType::Rgb16 => (ColorSpace::RGB, BitDepth::Sixteen),
+        ColorType::Rgba16 => (ColorSpace::RGBA, BitDepth::Sixteen),
+        // Floating point images are reduced to 16 bits
+        ColorType::Rgb32F => return write_jxl(&DynamicImage::ImageRgb16(img.to_rgb16()), writer),
+        _ => return write_jxl(&DynamicImage::ImageRgba16(img.to_rgba16()), writer)
+    };
+    let options = EncoderOptions::new(img.width() as usize, img.height() as usize, color, depth);
+    JxlSimpleEncoder::new(img.as_bytes(), options)
+        .encode(writer)
+        .map_err(|err| ImageError::Encoding(EncodingError::new(ImageFormatHint::Name("JPEG XL".into()), format!("{:?}", err))))?;
+    Ok(())
+}
+
+/// Insert bytes into encoded data at an offset
+pub fn splice(data: Vec<u8>, at: usize, insert: &[u8]) -> Vec<u8> {
+    let mut spliced = Vec::with_capacity(data.len() + insert.len());
+    spliced.extend(&data[..at]);
+    spliced.extend(insert);
+    spliced.extend(&data[at..]);
+    spliced
+}
+
+/// Chunk of a PNG, with its length and checksum
+pub fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend(kind);
+    chunk.extend(data);
+    chunk.extend(crc32fast::hash(&chunk[4..]).to_be_bytes());
+    chunk
+}
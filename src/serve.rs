@@ -0,0 +1,216 @@
+use std::ffi::OsString;
+use std::io::{Cursor, Read};
+use std::process::ExitCode;
+use std::thread;
+use clap::{ArgAction, Args, CommandFactory, Parser};
+use log::{error, info};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::logging::{self, LogFormat};
+use crate::{dpi, pipe, save, xmp, Cpar};
+
+#[derive(Args)]
+/// Serve cropping over HTTP. POST an image as the body of a request to /crop, giving options as
+/// query parameters named as their long form, such as /crop?threshold=240&format=webp, to receive
+/// the processed image with its crop in the X-Crop header
+pub struct Serve {
+    /// Port to listen on
+    #[clap(short, long, default_value_t = 8080)]
+    port: u16,
+    /// Address to listen on, such as 0.0.0.0 to accept connections from other machines
+    #[clap(long, default_value = "127.0.0.1")]
+    host: String,
+    /// Largest image in megabytes to accept
+    #[clap(long, value_name = "MB", default_value_t = 64, value_parser = clap::value_parser!(u64).range(1..=1 << 20))]
+    max_size: u64,
+    /// Maximum number of requests to process concurrently [default: number of CPUs]
+    #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: Option<u32>,
+    /// Format of log lines written to stderr (text, json)
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat
+}
+
+/// Options of how an uploaded image is detected, cropped and saved, which alone may be requested,
+/// as the others read files or run commands on the server, only write to its log, or apply to
+/// batches of images
+const IMAGE_OPTIONS: [&str; 78] = [
+    "format", "quality", "speed", "png-compression", "colorspace", "dither", "deterministic", "verify", "verify-checksum",
+    "xmp", "detect", "background", "tolerance", "energy", "deviation", "min-component", "exact-trim", "polarity",
+    "threshold", "threshold-pct", "x-threshold", "y-threshold", "left-threshold", "right-threshold", "top-threshold",
+    "bottom-threshold", "percentile", "x-percentile", "y-percentile", "left-percentile", "right-percentile",
+    "top-percentile", "bottom-percentile", "edge-stat", "despeckle", "detect-blur", "refine", "sample-stride",
+    "strip-frame", "detect-region", "ignore-margin", "extra", "x-extra", "y-extra", "left-extra", "right-extra",
+    "top-extra", "bottom-extra", "snap-aspect", "no-auto-orient", "rotate", "deskew", "mode", "crop-only", "aspect",
+    "gravity", "pad-color", "filter", "linear-resize", "blur", "sharpen", "auto-levels", "gamma", "downscale",
+    "target-width", "target-height", "max-dimension", "target-dpi", "physical-size", "min-width", "min-height",
+    "min-crop-ratio", "on-undersized", "max-crop", "on-overcropped", "content-guard", "on-blank", "passthrough-unmatched"
+];
+
+/// Failed request, as its status code and a message for the body of the response
+type Failure = (u16, String);
+
+/// Serve requests until the process is stopped
+pub fn run(serve: Serve) -> ExitCode {
+    logging::init(false, 0, serve.log_format);
+    let server = match Server::http((serve.host.as_str(), serve.port)) {
+        Ok(server) => server,
+        Err(err) => {
+            error!(error:% = err; "Failed to listen on {}:{}: {}", serve.host, serve.port, err);
+            return ExitCode::from(crate::FATAL);
+        }
+    };
+    info!("Listening on http://{}:{}", serve.host, serve.port);
+
+    // Take requests on a thread per job, each processing one at a time
+    let jobs = match serve.jobs {
+        Some(jobs) => jobs as usize,
+        None => thread::available_parallelism().map_or(1, usize::from)
+    };
+    let max_size = serve.max_size * 1024 * 1024;
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                match server.recv() {
+                    Ok(request) => handle(request, max_size),
+                    Err(err) => error!(error:% = err; "Failed to receive request: {}", err)
+                }
+            });
+        }
+    });
+    ExitCode::SUCCESS
+}
+
+/// Respond to a request with its processed image, or the reason it failed
+fn handle(mut request: Request, max_size: u64) {
+    let (method, url) = (request.method().clone(), request.url().to_string());
+    let response = match crop(&mut request, max_size) {
+        Ok((image, mime_type, rect)) => {
+            info!(method:% = method, url = url.as_str(), status = 200, crop:% = rect; "{} {}: cropped to {}", method, url, rect);
+            Response::from_data(image)
+                .with_header(header("Content-Type", mime_type))
+                .with_header(header("X-Crop", &rect.to_string()))
+        },
+        Err((status, message)) => {
+            match status {
+                500.. => error!(method:% = method, url = url.as_str(), status; "{} {}: {}", method, url, message),
+                _ => info!(method:% = method, url = url.as_str(), status; "{} {}: {}", method, url, message)
+            }
+            Response::from_string(format!("{}\n", message))
+                .with_status_code(status)
+                .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+        }
+    };
+    if let Err(err) = request.respond(response) {
+        error!(error:% = err; "Failed to respond to {} {}: {}", method, url, err);
+    }
+}
+
+/// Process the image uploaded by a request, encoding it in the requested format
+fn crop(request: &mut Request, max_size: u64) -> Result<(Vec<u8>, &'static str, cpar::CropRect), Failure> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    if path != "/crop" {
+        return Err((404, format!("no endpoint at {}, POST images to /crop", path)));
+    }
+    if *request.method() != Method::Post {
+        return Err((405, "images must be sent to /crop by POST".to_string()));
+    }
+    let args = parse_query(query).map_err(|message| (400, message))?;
+    pipe::check(&args).map_err(|err| (400, err.to_string()))?;
+    let params = crate::crop_params(&args);
+
+    // Read the uploaded image, refusing it once it grows beyond the limit
+    if request.body_length().is_some_and(|length| length as u64 > max_size) {
+        return Err((413, format!("image exceeds the limit of {}MB", max_size / 1024 / 1024)));
+    }
+    let mut buffer = Vec::new();
+    request.as_reader().take(max_size + 1).read_to_end(&mut buffer).map_err(|err| (400, err.to_string()))?;
+    if buffer.len() as u64 > max_size {
+        return Err((413, format!("image exceeds the limit of {}MB", max_size / 1024 / 1024)));
+    }
+
+    let detected = pipe::detect(buffer, &args, &params).map_err(|err| match err {
+        cpar::Error::NoEdges => (422, err.to_string()),
+        _ => (400, err.to_string())
+    })?;
+    let rect = &pipe::guarded(&args, &params, &detected).map_err(|flag| (422, flag))?;
+    let params = dpi::scaled_params(&args, &params, detected.dpi);
+    let img = &detected.img;
+    let processed = cpar::apply(img, rect, &params);
+    let format = pipe::output_format(&args, detected.input_format);
+    let size = (processed.width(), processed.height());
+    let options = save::SaveOptions {
+        xmp: args.xmp.then(|| {
+            xmp::packet(img.width(), img.height(), rect, detected.orientation, detected.rotation, detected.skew, params.restore)
+        }),
+        dpi: dpi::output_dpi(&args, detected.dpi, (img.width(), img.height()), rect, size, &params),
+        ..args.save_options()
+    };
+    let mut image = Cursor::new(Vec::new());
+    save::write(&processed, &mut image, format, &options).map_err(|err| (500, err.to_string()))?;
+    save::verify(Cursor::new(image.get_ref()), &processed, format, &options).map_err(|err| (500, err.to_string()))?;
+    Ok((image.into_inner(), format.mime_type(), *rect))
+}
+
+/// Parse query parameters as options of a piped image, given by long name or alias, with booleans
+/// given without a value or as true or false
+fn parse_query(query: &str) -> Result<Cpar, String> {
+    let command = Cpar::command();
+    let mut argv = vec![OsString::from("cpar")];
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (name, value) = (decode(name)?, decode(value)?);
+        // Names are looked up once decoded, so that one holding an encoded = names no option
+        let arg = command.get_arguments()
+            .find(|arg| {
+                arg.get_long() == Some(&name) || arg.get_all_aliases().is_some_and(|aliases| aliases.contains(&name.as_str()))
+            })
+            .ok_or_else(|| format!("unknown option '{}'", name))?;
+        let long = arg.get_long().expect("options are found by long name");
+        if !IMAGE_OPTIONS.contains(&long) {
+            return Err(format!("option '{}' cannot be requested", name));
+        }
+        let flag = matches!(arg.get_action(), ArgAction::SetTrue);
+        match (flag, value.as_str()) {
+            (true, "" | "true") => argv.push(format!("--{}", long).into()),
+            (true, "false") => {},
+            (true, _) => return Err(format!("option '{}' is given as true or false", name)),
+            (false, _) => argv.extend([format!("--{}", long).into(), value.into()])
+        }
+    }
+    // Values are given apart from their options, and sources after --, so neither can be taken as
+    // another option
+    argv.extend(["--".into(), pipe::STDIO.into(), pipe::STDIO.into()]);
+    // Report only the first line of parse errors, leaving out usage of the command line
+    let mut args = Cpar::try_parse_from(argv).map_err(|err| {
+        err.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+    })?;
+    args.output = args.source.pop().expect("sources are given");
+    Ok(args)
+}
+
+/// Decode a percent-encoded query component, in which + also stands for a space
+fn decode(component: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut rest = component.bytes();
+    while let Some(byte) = rest.next() {
+        bytes.push(match byte {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [rest.next(), rest.next()];
+                let [Some(high), Some(low)] = hex else {
+                    return Err(format!("invalid percent-encoding in '{}'", component));
+                };
+                std::str::from_utf8(&[high, low]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("invalid percent-encoding in '{}'", component))?
+            },
+            _ => byte
+        });
+    }
+    String::from_utf8(bytes).map_err(|_| format!("query parameter '{}' is not valid UTF-8", component))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("headers are valid")
+}
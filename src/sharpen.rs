@@ -0,0 +1,24 @@
+use image::{ColorType, DynamicImage};
+use crate::{blur, convert, CropParams, Sharpen};
+
+/// Sharpen an image with an unsharp mask, adding the difference of each pixel from a blurred copy
+/// scaled by amount wherever it exceeds threshold. Alpha is left unchanged.
+pub(crate) fn sharpen(img: &DynamicImage, sharpen: &Sharpen, params: &CropParams) -> DynamicImage {
+    let blurred = blur(img, sharpen.radius, params).to_rgba32f();
+    let mut sharpened = img.to_rgba32f();
+    let threshold = sharpen.threshold / 255.0;
+    // Floating point images may hold levels outside the displayable range
+    let clamp = !matches!(img.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    for (pixel, blurred) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for (level, blurred) in pixel.0.iter_mut().zip(blurred.0).take(3) {
+            let difference = *level - blurred;
+            if difference.abs() > threshold {
+                *level += sharpen.amount * difference;
+                if clamp {
+                    *level = level.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+    convert(sharpened.into(), img.color())
+}
@@ -0,0 +1,163 @@
+use std::{fs, io};
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf, Prefix};
+use image::ImageFormat;
+
+/// Image file to process, along with its path relative to the source it was found within
+pub struct Source {
+    pub path: PathBuf,
+    pub relative: PathBuf
+}
+
+/// Expand source arguments into the list of image files they refer to.
+///
+/// Directories are walked recursively for files with a recognised image extension, and paths which
+/// do not exist are treated as glob patterns.
+pub fn expand(sources: &[PathBuf]) -> io::Result<Vec<Source>> {
+    let mut files = Vec::new();
+    for source in sources {
+        if source.is_dir() {
+            walk(source, source, &mut files)?;
+        } else if source.exists() || !is_pattern(source) {
+            files.push(Source {
+                path: source.clone(),
+                relative: source.file_name().map(PathBuf::from).unwrap_or_default()
+            });
+        } else {
+            // Glob matches nothing under a verbatim prefix, so match without it
+            let pattern = without_verbatim(source);
+            let text = pattern.to_str().ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("glob pattern is not valid UTF-8: {}", source.display())
+            ))?;
+            let paths = glob::glob(text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            let base = pattern_base(&pattern);
+            for path in paths {
+                let path = path.map_err(io::Error::from)?;
+                if path.is_file() && is_image(&path) {
+                    let relative = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+                    files.push(Source { path, relative });
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Read a list of sources, one per line, from a file or from stdin if given as `-`. Blank lines
+/// are ignored.
+pub fn read_list(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let reader: Box<dyn BufRead> = match path == Path::new("-") {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(BufReader::new(File::open(path).map_err(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        })?))
+    };
+    let mut sources = Vec::new();
+    for line in reader.split(b'\n') {
+        let line = line?;
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        if !line.is_empty() {
+            sources.push(from_bytes(line));
+        }
+    }
+    Ok(sources)
+}
+
+/// Bytes of a path as written to lists of sources, which are kept as they are on Unix so that
+/// names which are not valid UTF-8 can be read back by [`read_list`]
+pub fn to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match path.to_string_lossy() {
+            Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+            Cow::Owned(path) => Cow::Owned(path.into_bytes())
+        }
+    }
+}
+
+/// Path of a line read from a list of sources, or of bytes given by [`to_bytes`]
+pub fn from_bytes(line: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(line))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(line).into_owned())
+    }
+}
+
+/// Recursively collect image files within a directory, in a stable order
+fn walk(base: &Path, dir: &Path, files: &mut Vec<Source>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk(base, &path, files)?;
+        } else if is_image(&path) {
+            let relative = path.strip_prefix(base).unwrap_or(&path).to_path_buf();
+            files.push(Source { path, relative });
+        }
+    }
+    Ok(())
+}
+
+/// Check whether a path has an extension of a supported image format, or of an archive of images
+pub fn is_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok() || crate::heif::is_heif(path) || crate::svg::is_svg(path)
+        || crate::raw::is_raw(path) || crate::pdf::is_pdf(path) || crate::archive::is_archive(path)
+}
+
+/// Check whether a path contains glob wildcards, other than the `?` of a Windows verbatim prefix
+fn is_pattern(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => name.to_str().is_some_and(|name| name.contains(['*', '?', '['])),
+        _ => false
+    })
+}
+
+/// Path with a Windows verbatim prefix such as `\\?\C:\` replaced by its plain form. The
+/// standard library adds the prefix back itself where needed to open paths over 260 characters.
+fn without_verbatim(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(disk) => OsString::from(format!("{}:", disk as char)),
+            Prefix::VerbatimUNC(server, share) => {
+                let mut prefix = OsString::from(r"\\");
+                prefix.push(server);
+                prefix.push(r"\");
+                prefix.push(share);
+                prefix
+            },
+            _ => return path.to_path_buf()
+        },
+        _ => return path.to_path_buf()
+    };
+    let mut path = PathBuf::from(prefix);
+    path.extend(components);
+    path
+}
+
+/// Leading components of a glob pattern which contain no wildcards
+fn pattern_base(pattern: &Path) -> PathBuf {
+    pattern.components()
+        .take_while(|component| match component {
+            Component::Normal(name) => !is_pattern(Path::new(name)),
+            _ => true
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+use image::DynamicImage;
+use crate::detect::{row_levels, thresholds};
+use crate::{CropRect, EdgeDetection, Sides};
+
+/// Find panels of artwork separated by interior gutters of whitespace at least min_gutter pixels
+/// wide, such as facing pages scanned together. Panels are cut down the middle of each gutter,
+/// across columns first and then rows, recursively within each panel so that grids are found too.
+///
+/// Columns are compared against the left threshold, and rows against the top threshold. Returns
+/// the whole image as a single panel if it has no interior gutters.
+pub fn split_panels(img: &DynamicImage, detection: &EdgeDetection, min_gutter: u32) -> Vec<CropRect> {
+    let threshold = thresholds(img, detection);
+    let whole = CropRect { x: 0, y: 0, width: img.width(), height: img.height() };
+    let mut panels = Vec::new();
+    split(img, detection, &threshold, min_gutter.max(1), whole, &mut panels);
+    panels
+}
+
+/// Split a region of an image at its gutters, adding panels without gutters to panels
+fn split(
+    img: &DynamicImage,
+    detection: &EdgeDetection,
+    threshold: &Sides<u16>,
+    min_gutter: u32,
+    region: CropRect,
+    panels: &mut Vec<CropRect>
+) {
+    let (columns, rows) = blank(img, detection, threshold, &region);
+    if let Some(spans) = spans(&columns, min_gutter) {
+        for (x, width) in spans {
+            let panel = CropRect { x: region.x + x, width, ..region };
+            split(img, detection, threshold, min_gutter, panel, panels);
+        }
+    } else if let Some(spans) = spans(&rows, min_gutter) {
+        for (y, height) in spans {
+            let panel = CropRect { y: region.y + y, height, ..region };
+            split(img, detection, threshold, min_gutter, panel, panels);
+        }
+    } else {
+        panels.push(region);
+    }
+}
+
+/// Check whether each column and row of a region of an image contains no artwork
+fn blank(
+    img: &DynamicImage,
+    detection: &EdgeDetection,
+    threshold: &Sides<u16>,
+    region: &CropRect
+) -> (Vec<bool>, Vec<bool>) {
+    let mode = detection.mode;
+    let mut columns = vec![true; region.width as usize];
+    let mut rows = vec![true; region.height as usize];
+    let mut levels = Vec::with_capacity(img.width() as usize);
+    for (y, row) in (region.y..region.y + region.height).zip(&mut rows) {
+        row_levels(img, y, mode, &mut levels);
+        let within = &levels[region.x as usize..(region.x + region.width) as usize];
+        for (&level, column) in within.iter().zip(&mut columns) {
+            *column &= !mode.crosses(level, threshold.left);
+            *row &= !mode.crosses(level, threshold.top);
+        }
+    }
+    (columns, rows)
+}
+
+/// Start and length of the spans between cuts down the middle of each interior run of blank
+/// lines at least min_gutter long, or `None` if there are none
+fn spans(blank: &[bool], min_gutter: u32) -> Option<Vec<(u32, u32)>> {
+    let first = blank.iter().position(|&blank| !blank)?;
+    let last = blank.iter().rposition(|&blank| !blank)?;
+
+    // Find runs of blank lines between the outermost lines of artwork
+    let mut cuts = Vec::new();
+    let mut run = None;
+    for (n, &blank) in blank.iter().enumerate().take(last + 1).skip(first) {
+        match (blank, run) {
+            (true, None) => run = Some(n),
+            (false, Some(start)) => {
+                if n - start >= min_gutter as usize {
+                    cuts.push(((start + n) / 2) as u32);
+                }
+                run = None;
+            },
+            _ => {}
+        }
+    }
+    if cuts.is_empty() {
+        return None;
+    }
+    let bounds: Vec<u32> = std::iter::once(0).chain(cuts).chain([blank.len() as u32]).collect();
+    Some(bounds.windows(2).map(|bounds| (bounds[0], bounds[1] - bounds[0])).collect())
+}
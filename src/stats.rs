@@ -0,0 +1,89 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::Duration;
+use serde::Serialize;
+use crate::report::{Record, Status};
+
+/// Summary of a batch processing run
+#[derive(Serialize)]
+pub struct Stats {
+    pub processed: usize,
+    pub saved: usize,
+    pub reported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Total pixels of source images outside their crops
+    pub pixels_removed: u64,
+    /// Mean percentage of each dimension removed by cropping
+    pub average_crop: Axes,
+    /// Wall-clock time of the run
+    pub seconds: f64
+}
+
+#[derive(Serialize)]
+pub struct Axes {
+    pub horizontal: f64,
+    pub vertical: f64
+}
+
+impl Stats {
+    /// Summarise the records of a run
+    pub fn new(records: &[Record], elapsed: Duration) -> Self {
+        let count = |status| records.iter().filter(|record| record.status == status).count();
+        let crops: Vec<_> = records.iter()
+            .filter_map(|record| Some((record.original.as_ref()?, record.crop.as_ref()?)))
+            .collect();
+        let (horizontal, vertical) = crops.iter().fold((0.0, 0.0), |(x, y), (original, crop)| (
+            x + 1.0 - crop.width as f64 / original.width as f64,
+            y + 1.0 - crop.height as f64 / original.height as f64
+        ));
+        let average = |sum: f64| match crops.len() {
+            0 => 0.0,
+            len => 100.0 * sum / len as f64
+        };
+        Self {
+            processed: records.len(),
+            saved: count(Status::Saved),
+            reported: count(Status::Reported),
+            skipped: count(Status::Skipped),
+            failed: count(Status::Failed),
+            pixels_removed: crops.iter()
+                .map(|(original, crop)| {
+                    original.width as u64 * original.height as u64 - crop.width as u64 * crop.height as u64
+                })
+                .sum(),
+            average_crop: Axes { horizontal: average(horizontal), vertical: average(vertical) },
+            seconds: elapsed.as_secs_f64()
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.saved, "saved"),
+            (self.reported, "reported"),
+            (self.skipped, "skipped"),
+            (self.failed, "failed")
+        ];
+        let counts: Vec<_> = counts.iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, status)| format!("{} {}", count, status))
+            .collect();
+        writeln!(f, "Processed {} images in {:.2}s: {}", self.processed, self.seconds, counts.join(", "))?;
+        write!(
+            f,
+            "Removed {} pixels, cropping {:.1}% horizontally and {:.1}% vertically on average",
+            self.pixels_removed, self.average_crop.horizontal, self.average_crop.vertical
+        )
+    }
+}
+
+/// Write run statistics as JSON
+pub fn write(path: &Path, stats: &Stats) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, stats)?;
+    Ok(())
+}
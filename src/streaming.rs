@@ -0,0 +1,130 @@
+use std::ops::Range;
+use image::DynamicImage;
+use crate::detect::{boundary_edges, detection_region, row_levels};
+use crate::{CropRect, DetectMode, EdgeDetection, Polarity, Sides};
+
+/// Edge detection over an image decoded a band of rows at a time, top to bottom, so that edges are
+/// found without holding the whole image in memory. Edges are placed as [`detect_edges`] places
+/// them, holding only the crossings found so far.
+///
+/// Only detection comparing each pixel against a fixed threshold can be streamed, so not with
+/// gradient, variance, exact or bbox detection, an automatic background polarity, an automatic
+/// or range threshold, a mask, blur, despeckling, sampling, refinement or frame stripping, which
+/// all need the whole image at once.
+///
+/// [`detect_edges`]: crate::detect_edges
+pub struct StreamedDetection {
+    detection: EdgeDetection,
+    /// Part of the image detected within, or `None` if nothing of it is left to detect within
+    region: Option<CropRect>,
+    /// Whether bands are inverted for a dark background
+    invert: bool,
+    /// First column crossing threshold along each row crossing it, as row and column
+    left: Vec<(u32, u32)>,
+    /// Last column crossing threshold along each row crossing it
+    right: Vec<(u32, u32)>,
+    /// First row crossing threshold along each column of the region
+    top: Vec<Option<u32>>,
+    /// Last row crossing threshold along each column of the region so far
+    bottom: Vec<Option<u32>>,
+    levels: Vec<u16>
+}
+
+impl StreamedDetection {
+    /// Begin detecting the edges of an image of the given dimensions, or `None` if the detection
+    /// cannot be streamed
+    pub fn new(width: u32, height: u32, detection: &EdgeDetection) -> Option<StreamedDetection> {
+        let thresholded = matches!(detection.mode, DetectMode::Luma | DetectMode::Channel(_));
+        let compared = matches!(
+            detection.mode,
+            DetectMode::Luma | DetectMode::Channel(_) | DetectMode::Alpha | DetectMode::Background { .. }
+        );
+        let streams = compared
+            && !(thresholded && detection.polarity == Polarity::Auto)
+            && !detection.auto_threshold
+            && detection.range_threshold.is_none()
+            && detection.mask.is_none()
+            && detection.blur.is_none()
+            && detection.despeckle.is_none()
+            && !detection.refine
+            && detection.sample_stride <= 1
+            && detection.strip_frame.is_none();
+        if !streams {
+            return None;
+        }
+        let region = match detection_region(width, height, detection) {
+            Some((region, _)) => region,
+            None => Some(CropRect { x: 0, y: 0, width, height })
+        };
+        let columns = region.map_or(0, |region| region.width as usize);
+        Some(StreamedDetection {
+            detection: detection.clone(),
+            region,
+            invert: thresholded && detection.polarity == Polarity::Dark,
+            left: Vec::new(),
+            right: Vec::new(),
+            top: vec![None; columns],
+            bottom: vec![None; columns],
+            levels: Vec::with_capacity(width as usize)
+        })
+    }
+
+    /// Rows of the image taking part in detection, outside of which bands need not be scanned
+    pub fn rows(&self) -> Range<u32> {
+        self.region.map_or(0..0, |region| region.y..region.y + region.height)
+    }
+
+    /// Scan a band of whole rows of the image, starting at the given row. Bands are scanned in
+    /// order from the top of the image.
+    pub fn scan(&mut self, band: &DynamicImage, y: u32) {
+        let Some(region) = self.region else {
+            return;
+        };
+        let inverted;
+        let band = match self.invert {
+            true => {
+                let mut copy = band.clone();
+                copy.invert();
+                inverted = copy;
+                &inverted
+            },
+            false => band
+        };
+        let (mode, threshold) = (self.detection.mode, self.detection.threshold);
+        let rows = self.rows();
+        for row in (y..y + band.height()).filter(|row| rows.contains(row)) {
+            row_levels(band, row - y, mode, &mut self.levels);
+            let levels = &self.levels[region.x as usize..(region.x + region.width) as usize];
+            if let Some(x) = levels.iter().position(|&level| mode.crosses(level, threshold.left)) {
+                self.left.push((row, region.x + x as u32));
+            }
+            if let Some(x) = levels.iter().rposition(|&level| mode.crosses(level, threshold.right)) {
+                self.right.push((row, region.x + x as u32));
+            }
+            for (x, &level) in levels.iter().enumerate() {
+                if self.top[x].is_none() && mode.crosses(level, threshold.top) {
+                    self.top[x] = Some(row);
+                }
+                if mode.crosses(level, threshold.bottom) {
+                    self.bottom[x] = Some(row);
+                }
+            }
+        }
+    }
+
+    /// Place the edges of the artwork once every band has been scanned, before applying extra
+    /// margin.
+    ///
+    /// Returns `None` if any side of the image contains no pixels crossing the threshold.
+    pub fn edges(self) -> Option<Sides<u32>> {
+        let x = self.region?.x;
+        let columns = |rows: Vec<Option<u32>>| (x..).zip(rows).filter_map(|(x, y)| Some((x, y?))).collect();
+        let boundary = Sides {
+            left: self.left,
+            right: self.right,
+            top: columns(self.top),
+            bottom: columns(self.bottom)
+        };
+        boundary_edges(boundary, &self.detection)
+    }
+}
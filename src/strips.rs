@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use cpar::{CropRect, Sides, StreamedDetection};
+use image::{DynamicImage, ImageFormat};
+use tiff::decoder::{ChunkType, Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+use crate::pages::{self, invalid};
+
+/// TIFF image stored in strips of rows, decoded a strip at a time rather than all at once
+pub struct Strips {
+    decoder: Decoder<BufReader<File>>,
+    width: u32,
+    height: u32,
+    color: ColorType,
+    rows_per_strip: u32
+}
+
+impl Strips {
+    /// Open an image for decoding a strip at a time, if it is a single page TIFF stored in strips
+    /// of interleaved samples of a color type which can be decoded, and has no orientation to
+    /// apply
+    pub fn open(path: &Path, auto_orient: bool) -> cpar::Result<Option<Strips>> {
+        if ImageFormat::from_path(path).ok() != Some(ImageFormat::Tiff) {
+            return Ok(None);
+        }
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(invalid)?;
+        let color = decoder.colortype().map_err(invalid)?;
+        let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration).map_err(invalid)?;
+        let orientation = decoder.find_tag_unsigned::<u16>(Tag::Orientation).map_err(invalid)?;
+        let streams = !decoder.more_images()
+            && decoder.get_chunk_type() == ChunkType::Strip
+            && pages::supported(color)
+            && planar.unwrap_or(1) == 1
+            && !(auto_orient && orientation.unwrap_or(1) != 1);
+        if !streams {
+            return Ok(None);
+        }
+        let (width, height) = decoder.dimensions().map_err(invalid)?;
+        let rows_per_strip = decoder.chunk_dimensions().1.max(1);
+        Ok(Some(Strips { decoder, width, height, color, rows_per_strip }))
+    }
+
+    /// Width and height of the image
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Detect the edges of the artwork, decoding only the strips holding rows detected within
+    pub fn detect_edges(&mut self, mut detection: StreamedDetection) -> cpar::Result<Option<Sides<u32>>> {
+        let rows = detection.rows();
+        for strip in self.overlapping(rows.start, rows.end) {
+            let (y, band) = (strip * self.rows_per_strip, self.strip(strip)?);
+            detection.scan(&band, y);
+        }
+        Ok(detection.edges())
+    }
+
+    /// Decode the part of the image within a rectangle, decoding only the strips holding its rows
+    pub fn crop(&mut self, rect: &CropRect) -> cpar::Result<DynamicImage> {
+        let (channels, depth) = match self.color {
+            ColorType::Gray(depth) => (1, depth),
+            ColorType::GrayA(depth) => (2, depth),
+            ColorType::RGB(depth) => (3, depth),
+            ColorType::RGBA(depth) => (4, depth),
+            color => return Err(invalid(format!("unsupported TIFF page color type {:?}", color)).into())
+        };
+        let (start, length) = (rect.x as usize * channels, rect.width as usize * channels);
+        let samples = rect.width as usize * rect.height as usize * channels;
+        let mut cropped = match depth {
+            8 => DecodingResult::U8(Vec::with_capacity(samples)),
+            _ => DecodingResult::U16(Vec::with_capacity(samples))
+        };
+        for strip in self.overlapping(rect.y, rect.y + rect.height) {
+            let y = strip * self.rows_per_strip;
+            let (width, height) = self.decoder.chunk_data_dimensions(strip);
+            let stride = width as usize * channels;
+            // Rows of the strip within the rectangle
+            let rows = rect.y.max(y) - y..(rect.y + rect.height).min(y + height) - y;
+            let data = self.decoder.read_chunk(strip).map_err(invalid)?;
+            match (&mut cropped, &data) {
+                (DecodingResult::U8(cropped), DecodingResult::U8(data)) => for row in rows {
+                    cropped.extend_from_slice(&data[row as usize * stride + start..][..length]);
+                },
+                (DecodingResult::U16(cropped), DecodingResult::U16(data)) => for row in rows {
+                    cropped.extend_from_slice(&data[row as usize * stride + start..][..length]);
+                },
+                _ => return Err(invalid("TIFF strip data does not match its color type").into())
+            }
+        }
+        Ok(pages::page(rect.width, rect.height, self.color, cropped)?)
+    }
+
+    /// Indices of the strips holding any of a range of rows
+    fn overlapping(&self, start: u32, end: u32) -> std::ops::Range<u32> {
+        match start < end {
+            true => start / self.rows_per_strip..(end - 1) / self.rows_per_strip + 1,
+            false => 0..0
+        }
+    }
+
+    /// Decode a strip into a band of whole rows of the image
+    fn strip(&mut self, strip: u32) -> cpar::Result<DynamicImage> {
+        let (width, height) = self.decoder.chunk_data_dimensions(strip);
+        let data = self.decoder.read_chunk(strip).map_err(invalid)?;
+        Ok(pages::page(width, height, self.color, data)?)
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+use image::DynamicImage;
+
+/// Extensions of SVG images, which are rasterized with resvg rather than decoded
+const EXTENSIONS: [&str; 2] = ["svg", "svgz"];
+
+/// Resolution SVG images are rasterized at by default, at which each CSS pixel is one pixel
+pub const DEFAULT_DPI: f32 = 96.0;
+
+/// Check whether a path has the extension of an SVG image
+pub fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|svg| ext.eq_ignore_ascii_case(svg)))
+}
+
+/// Rasterize an SVG image with resvg at a resolution in dots per inch, over a white background so
+/// that the empty canvas around its artwork is cropped away as margin. Text is set in system fonts
+/// and images are linked relative to the SVG.
+#[cfg(feature = "svg")]
+pub fn rasterize(path: &Path, dpi: f32) -> cpar::Result<DynamicImage> {
+    use std::{fs, io};
+    use image::RgbaImage;
+    use resvg::tiny_skia::{Color, Pixmap, Transform};
+    use resvg::usvg::{Options, Tree};
+
+    let invalid = |err: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidData, format!("SVG: {}", err));
+    let mut options = Options { resources_dir: path.parent().map(Path::to_path_buf), ..Options::default() };
+    options.fontdb_mut().load_system_fonts();
+    let tree = Tree::from_data(&fs::read(path)?, &options).map_err(|err| invalid(&err))?;
+    let zoom = dpi / DEFAULT_DPI;
+    let size = tree.size().to_int_size().scale_by(zoom).ok_or_else(|| invalid(&"image is empty at this resolution"))?;
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or_else(|| invalid(&"image is too large to rasterize"))?;
+    pixmap.fill(Color::WHITE);
+    resvg::render(&tree, Transform::from_scale(zoom, zoom), &mut pixmap.as_mut());
+
+    // Pixels are premultiplied, which makes no difference as the background is opaque
+    let img = RgbaImage::from_raw(size.width(), size.height(), pixmap.take());
+    Ok(DynamicImage::ImageRgba8(img.expect("pixmap matches its dimensions")))
+}
+
+/// Fail to rasterize an SVG image, as support for them was not built
+#[cfg(not(feature = "svg"))]
+pub fn rasterize(_: &Path, _: f32) -> cpar::Result<DynamicImage> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "rasterizing SVG images requires building with the svg feature").into())
+}
@@ -0,0 +1,88 @@
+use image::{DynamicImage, GenericImageView};
+use crate::detect::row_levels;
+use crate::DetectMode;
+
+/// Fraction of each dimension, from each side, considered the border region of an image
+const BORDER_FRACTION: u32 = 8;
+
+/// Compute a threshold on the 16-bit scale for the border region of an image using Otsu's method.
+///
+/// Returns `None` for detection modes which do not use a threshold, or if the border region does
+/// not contain two distinct classes of pixel.
+pub fn auto_threshold(img: &DynamicImage, mode: DetectMode) -> Option<u16> {
+    if let DetectMode::Background { .. } | DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact = mode {
+        return None;
+    }
+
+    // Build histogram of border region
+    let (width, height) = img.dimensions();
+    let border_x = (width / BORDER_FRACTION).max(1) as usize;
+    let border_y = (height / BORDER_FRACTION).max(1);
+    let mut histogram = vec![0u64; 1 << 16];
+    let mut levels = Vec::with_capacity(width as usize);
+    for y in 0..height {
+        row_levels(img, y, mode, &mut levels);
+        let border_row = y < border_y || y >= height - border_y;
+        for (x, &level) in levels.iter().enumerate() {
+            if border_row || x < border_x || x >= width as usize - border_x {
+                histogram[level as usize] += 1;
+            }
+        }
+    }
+
+    // Pixels at or below the Otsu threshold form the lower class, and artwork is detected by
+    // comparison against the first value of the upper class
+    otsu(&histogram).map(|threshold| threshold as u16 + 1)
+}
+
+/// Compute a threshold on the 16-bit scale a percentage of the way from the lowest to the highest
+/// level of an image, so that one setting suits images of any bit depth or exposure.
+///
+/// Returns `None` for detection modes which do not use a threshold.
+pub fn range_threshold(img: &DynamicImage, mode: DetectMode, percent: f32) -> Option<u16> {
+    if let DetectMode::Background { .. } | DetectMode::Gradient { .. } | DetectMode::Variance { .. } | DetectMode::Exact = mode {
+        return None;
+    }
+    let mut range = None;
+    let mut levels = Vec::with_capacity(img.width() as usize);
+    for y in 0..img.height() {
+        row_levels(img, y, mode, &mut levels);
+        for &level in &levels {
+            let (low, high) = range.get_or_insert((level, level));
+            *low = level.min(*low);
+            *high = level.max(*high);
+        }
+    }
+    let (low, high) = range?;
+    Some((low as f32 + (high - low) as f32 * percent / 100.0).round() as u16)
+}
+
+/// Find the value which splits a histogram into two classes with maximum between-class variance.
+///
+/// Returns `None` if the histogram has fewer than two distinct values.
+pub fn otsu(histogram: &[u64]) -> Option<usize> {
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram.iter().enumerate().map(|(value, &count)| value as f64 * count as f64).sum();
+
+    let mut best = None;
+    let mut best_variance = 0.0;
+    let mut lower_count = 0;
+    let mut lower_sum = 0.0;
+    for (value, &count) in histogram.iter().enumerate().take(histogram.len().saturating_sub(1)) {
+        lower_count += count;
+        lower_sum += value as f64 * count as f64;
+        let upper_count = total - lower_count;
+        if lower_count == 0 || upper_count == 0 {
+            continue;
+        }
+
+        let lower_mean = lower_sum / lower_count as f64;
+        let upper_mean = (sum - lower_sum) / upper_count as f64;
+        let variance = lower_count as f64 * upper_count as f64 * (lower_mean - upper_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = Some(value);
+        }
+    }
+    best
+}
@@ -0,0 +1,75 @@
+use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Pixel};
+use crate::Sides;
+
+/// Detect the position of each edge of the artwork within an image as the outermost rows and
+/// columns containing a pixel which differs in any way from the top left corner pixel, as for
+/// screenshots whose border is exactly one color.
+///
+/// Pixels are compared at the bit depth of the image, without converting or blurring them.
+/// Returns `None` if every pixel matches the corner.
+pub fn exact_edges(img: &DynamicImage) -> Option<Sides<u32>> {
+    match img {
+        DynamicImage::ImageLuma8(buf) => trim(buf),
+        DynamicImage::ImageLumaA8(buf) => trim(buf),
+        DynamicImage::ImageRgb8(buf) => trim(buf),
+        DynamicImage::ImageRgba8(buf) => trim(buf),
+        DynamicImage::ImageLuma16(buf) => trim(buf),
+        DynamicImage::ImageLumaA16(buf) => trim(buf),
+        DynamicImage::ImageRgb16(buf) => trim(buf),
+        DynamicImage::ImageRgba16(buf) => trim(buf),
+        DynamicImage::ImageRgb32F(buf) => trim(buf),
+        DynamicImage::ImageRgba32F(buf) => trim(buf),
+        _ => trim(&img.to_rgba32f())
+    }
+}
+
+fn trim<P: Pixel>(buf: &ImageBuffer<P, Vec<P::Subpixel>>) -> Option<Sides<u32>> {
+    let (width, height) = buf.dimensions();
+    let channels = P::CHANNEL_COUNT as usize;
+    let stride = width as usize * channels;
+    let raw = buf.as_raw();
+    let corner = raw.get(..channels)?;
+    let row = |y: u32| &raw[y as usize * stride..][..stride];
+
+    // Rows are contiguous, so are compared whole before columns are searched between them
+    let blank_row = |y: u32| row(y).chunks_exact(channels).all(|pixel| pixel == corner);
+    let top = (0..height).find(|&y| !blank_row(y))?;
+    let bottom = (top..height).rev().find(|&y| !blank_row(y))?;
+    let blank_column = |x: u32| {
+        let offset = x as usize * channels;
+        (top..=bottom).all(|y| &row(y)[offset..offset + channels] == corner)
+    };
+    let left = (0..width).find(|&x| !blank_column(x))?;
+    let right = (left..width).rev().find(|&x| !blank_column(x))?;
+    Some(Sides { left, right, top, bottom })
+}
+
+/// Replace the pixels of an image under the nonzero pixels of a mask of the same size with its top
+/// left corner pixel, at the bit depth of the image, so that [`exact_edges`] ignores them
+pub(crate) fn fill_corner(img: &mut DynamicImage, mask: &GrayImage) {
+    match img {
+        DynamicImage::ImageLuma8(buf) => fill(buf, mask),
+        DynamicImage::ImageLumaA8(buf) => fill(buf, mask),
+        DynamicImage::ImageRgb8(buf) => fill(buf, mask),
+        DynamicImage::ImageRgba8(buf) => fill(buf, mask),
+        DynamicImage::ImageLuma16(buf) => fill(buf, mask),
+        DynamicImage::ImageLumaA16(buf) => fill(buf, mask),
+        DynamicImage::ImageRgb16(buf) => fill(buf, mask),
+        DynamicImage::ImageRgba16(buf) => fill(buf, mask),
+        DynamicImage::ImageRgb32F(buf) => fill(buf, mask),
+        DynamicImage::ImageRgba32F(buf) => fill(buf, mask),
+        _ => {
+            let corner = img.get_pixel(0, 0);
+            for (x, y, _) in mask.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[0] != 0) {
+                img.put_pixel(x, y, corner);
+            }
+        }
+    }
+}
+
+fn fill<P: Pixel>(buf: &mut ImageBuffer<P, Vec<P::Subpixel>>, mask: &GrayImage) {
+    let corner = *buf.get_pixel(0, 0);
+    for (x, y, _) in mask.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[0] != 0) {
+        buf.put_pixel(x, y, corner);
+    }
+}
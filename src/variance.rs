@@ -0,0 +1,43 @@
+use image::{DynamicImage, GenericImageView};
+use crate::detect::row_levels;
+use crate::{DetectMode, Sides};
+
+/// Detect the position of each edge of the artwork within an image as the outermost rows and
+/// columns whose standard deviation of luma, in 8-bit levels, exceeds deviation.
+///
+/// Flat backgrounds have little variation whether they are light or dark, so artwork is found
+/// against black as well as white backgrounds. Returns `None` if no row or column exceeds
+/// deviation.
+pub fn variance_edges(img: &DynamicImage, deviation: f32) -> Option<Sides<u32>> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    // Accumulate sums and sums of squares of levels along each row and column
+    let mut rows = vec![(0.0, 0.0); height as usize];
+    let mut columns = vec![(0.0, 0.0); width as usize];
+    let mut levels = Vec::with_capacity(width as usize);
+    for y in 0..height {
+        row_levels(img, y, DetectMode::Luma, &mut levels);
+        for (x, &level) in levels.iter().enumerate() {
+            let level = level as f64 / 257.0;
+            let (row, column) = (&mut rows[y as usize], &mut columns[x]);
+            *row = (row.0 + level, row.1 + level * level);
+            *column = (column.0 + level, column.1 + level * level);
+        }
+    }
+
+    let exceeds = |&(sum, squares): &(f64, f64), length: u32| {
+        let mean = sum / length as f64;
+        (squares / length as f64 - mean * mean).max(0.0).sqrt() > deviation as f64
+    };
+    let above = |sums: &[(f64, f64)], length: u32| {
+        let first = sums.iter().position(|sums| exceeds(sums, length))? as u32;
+        let last = sums.iter().rposition(|sums| exceeds(sums, length))? as u32;
+        Some((first, last))
+    };
+    let (top, bottom) = above(&rows, width)?;
+    let (left, right) = above(&columns, height)?;
+    Some(Sides { left, right, top, bottom })
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use log::{error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rayon::ThreadPool;
+use rayon::prelude::*;
+use crate::Run;
+use crate::sources::{self, Source};
+
+/// Time a file must go without changes before it is considered completely written
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Watch source directories recursively, processing image files once they stop changing
+pub fn watch(run: &Run, pool: &ThreadPool) -> io::Result<()> {
+    let args = run.args;
+    let dirs = args.source.iter()
+        .map(|dir| match dir.is_dir() {
+            true => dir.canonicalize(),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory to watch", dir.display())
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let output = args.output.canonicalize().unwrap_or(args.output.clone());
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::Recursive).map_err(io::Error::other)?;
+        info!("Watching {}", dir.display());
+    }
+
+    // Track when each changed file was last modified, ignoring outputs written within watched
+    // directories
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(SETTLE_TIME / 4) {
+            Ok(Ok(Event { kind: EventKind::Create(_) | EventKind::Modify(_), paths, .. })) => {
+                for path in paths {
+                    if !path.starts_with(&output) && sources::is_image(&path) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            },
+            Ok(Ok(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {},
+            Ok(Err(err)) => error!("Watch error: {}", err),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(())
+        }
+
+        // Process settled files
+        let settled: Vec<_> = pending.iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        let sources: Vec<_> = settled.into_iter()
+            .filter(|path| pending.remove(path).is_some() && path.is_file())
+            .map(|path| {
+                let relative = dirs.iter()
+                    .find_map(|dir| path.strip_prefix(dir).ok())
+                    .unwrap_or(&path)
+                    .to_path_buf();
+                Source { path, relative }
+            })
+            .collect();
+        pool.install(|| sources.par_iter().for_each(|source| {
+            run.process(source);
+        }));
+    }
+}
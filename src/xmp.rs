@@ -0,0 +1,175 @@
+use image::{DynamicImage, ImageFormat};
+use cpar::{CropRect, RestoreMode};
+use crate::save::{self, OutputFormat};
+
+/// Namespace of the properties recording how an image was cropped
+const NAMESPACE: &str = "https://github.com/guileless298/cpar/ns/1.0/";
+
+/// Identifier of an XMP packet in a JPEG APP1 segment
+const JPEG_IDENTIFIER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Keyword of an XMP packet in a PNG iTXt chunk
+const PNG_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+/// Flag of a WebP VP8X chunk marking that the file holds XMP metadata
+const WEBP_XMP_FLAG: u8 = 0x04;
+
+/// Flag of a WebP VP8X chunk marking that the image has transparency
+const WEBP_ALPHA_FLAG: u8 = 0x10;
+
+/// Crop recorded in the XMP packet of a saved image
+pub struct Recorded {
+    /// Dimensions of the original image
+    pub width: u32,
+    pub height: u32,
+    /// Crop of the original image, after correcting its rotation
+    pub rect: CropRect,
+    /// Quarter turn applied by --rotate before cropping, in degrees clockwise
+    pub rotation: Option<u16>,
+    /// Rotation corrected before cropping, in degrees clockwise
+    pub skew: Option<f32>,
+    /// How the original aspect ratio was restored, resizing where not recorded
+    pub restore: RestoreMode
+}
+
+/// XMP packet recording the dimensions of an image as oriented and rotated, the crop applied to it,
+/// the EXIF orientation, quarter turn and skew rotating it before cropping, and how its aspect
+/// ratio was restored, so that the crop can be reproduced or undone given the original
+pub fn packet(
+    width: u32,
+    height: u32,
+    rect: &CropRect,
+    orientation: Option<u8>,
+    rotation: Option<u16>,
+    skew: Option<f32>,
+    restore: RestoreMode
+) -> String {
+    let orientation = orientation.map(|orientation| format!("\n   cpar:Orientation=\"{}\"", orientation));
+    let rotation = rotation.map(|degrees| format!("\n   cpar:Rotation=\"{}\"", degrees));
+    let skew = skew.map(|angle| format!("\n   cpar:Skew=\"{}\"", angle));
+    let rotated = [orientation, rotation, skew].into_iter().flatten().collect::<String>();
+    let restore = match restore {
+        RestoreMode::Resize => "resize",
+        RestoreMode::Pad => "pad",
+        RestoreMode::Crop => "crop"
+    };
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+        <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+        <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+        <rdf:Description rdf:about=\"\"\n   \
+        xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n   \
+        xmlns:cpar=\"{}\"\n   \
+        xmp:CreatorTool=\"cpar {}\"\n   \
+        cpar:OriginalWidth=\"{}\"\n   \
+        cpar:OriginalHeight=\"{}\"\n   \
+        cpar:CropX=\"{}\"\n   \
+        cpar:CropY=\"{}\"\n   \
+        cpar:CropWidth=\"{}\"\n   \
+        cpar:CropHeight=\"{}\"\n   \
+        cpar:Restore=\"{}\"{}/>\n \
+        </rdf:RDF>\n\
+        </x:xmpmeta>\n\
+        <?xpacket end=\"w\"?>",
+        NAMESPACE, env!("CARGO_PKG_VERSION"), width, height, rect.x, rect.y, rect.width, rect.height, restore,
+        rotated
+    )
+}
+
+/// Read the crop recorded by the XMP packet within an image file or sidecar, which is stored
+/// uncompressed by every format it is embedded in
+pub fn read(data: &[u8]) -> Option<Recorded> {
+    let number = |name: &str| attribute(data, name)?.parse::<u32>().ok();
+    let restore = match attribute(data, "Restore") {
+        Some("pad") => RestoreMode::Pad,
+        Some("crop") => RestoreMode::Crop,
+        _ => RestoreMode::Resize
+    };
+    Some(Recorded {
+        width: number("OriginalWidth")?,
+        height: number("OriginalHeight")?,
+        rect: CropRect { x: number("CropX")?, y: number("CropY")?, width: number("CropWidth")?, height: number("CropHeight")? },
+        rotation: attribute(data, "Rotation").and_then(|degrees| degrees.parse::<u16>().ok())
+            .filter(|degrees| [90, 180, 270].contains(degrees)),
+        skew: attribute(data, "Skew").and_then(|angle| angle.parse::<f32>().ok()).filter(|angle| angle.is_finite()),
+        restore
+    })
+}
+
+/// Value of a property in the cpar namespace, as written by [`packet`]
+fn attribute<'a>(data: &'a [u8], name: &str) -> Option<&'a str> {
+    let prefix = format!("cpar:{}=\"", name);
+    let start = data.windows(prefix.len()).position(|window| window == prefix.as_bytes())? + prefix.len();
+    let len = data[start..].iter().position(|&byte| byte == b'"')?;
+    std::str::from_utf8(&data[start..start + len]).ok()
+}
+
+/// Check whether XMP packets can be embedded in images of a format
+pub fn embeds(format: OutputFormat) -> bool {
+    matches!(format, OutputFormat::Image(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP))
+}
+
+/// Embed an XMP packet in an image encoded in a format it [`embeds`] in, leaving other formats as
+/// they are
+pub fn embed(data: Vec<u8>, format: OutputFormat, img: &DynamicImage, packet: &str) -> Vec<u8> {
+    match format {
+        OutputFormat::Image(ImageFormat::Jpeg) => embed_jpeg(data, packet),
+        OutputFormat::Image(ImageFormat::Png) => embed_png(data, packet),
+        OutputFormat::Image(ImageFormat::WebP) => embed_webp(data, img, packet),
+        _ => data
+    }
+}
+
+/// Insert an APP1 segment after the start of image marker, and after the JFIF APP0 segment which
+/// must come first
+fn embed_jpeg(data: Vec<u8>, packet: &str) -> Vec<u8> {
+    let mut at = 2;
+    if data.get(2..4) == Some(&[0xFF, 0xE0]) {
+        at += 2 + u16::from_be_bytes([data[4], data[5]]) as usize;
+    }
+    let length = (2 + JPEG_IDENTIFIER.len() + packet.len()) as u16;
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend(length.to_be_bytes());
+    segment.extend(JPEG_IDENTIFIER);
+    segment.extend(packet.as_bytes());
+    save::splice(data, at, &segment)
+}
+
+/// Insert an uncompressed iTXt chunk after the IHDR chunk
+fn embed_png(data: Vec<u8>, packet: &str) -> Vec<u8> {
+    let mut text = PNG_KEYWORD.to_vec();
+    // Null separator, no compression, and empty language tag and translated keyword
+    text.extend([0, 0, 0, 0, 0]);
+    text.extend(packet.as_bytes());
+    save::splice(data, save::PNG_HEADER_END, &save::png_chunk(b"iTXt", &text))
+}
+
+/// Append an XMP chunk, converting a simple file to the extended format with a VP8X chunk first
+/// where needed
+fn embed_webp(mut data: Vec<u8>, img: &DynamicImage, packet: &str) -> Vec<u8> {
+    if &data[12..16] == b"VP8X" {
+        data[20] |= WEBP_XMP_FLAG;
+    } else {
+        let flags = match img.color().has_alpha() {
+            true => WEBP_XMP_FLAG | WEBP_ALPHA_FLAG,
+            false => WEBP_XMP_FLAG
+        };
+        let mut chunk = b"VP8X".to_vec();
+        chunk.extend(10u32.to_le_bytes());
+        chunk.extend([flags, 0, 0, 0]);
+        // Canvas dimensions are stored less one in 24 bits
+        chunk.extend(&(img.width() - 1).to_le_bytes()[..3]);
+        chunk.extend(&(img.height() - 1).to_le_bytes()[..3]);
+        data = save::splice(data, 12, &chunk);
+    }
+    data.extend(b"XMP ");
+    data.extend((packet.len() as u32).to_le_bytes());
+    data.extend(packet.as_bytes());
+    // Chunks are padded to an even length
+    if packet.len() % 2 == 1 {
+        data.push(0);
+    }
+    let size = (data.len() - 8) as u32;
+    data[4..8].copy_from_slice(&size.to_le_bytes());
+    data
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use image::{GrayImage, ImageFormat, Luma};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Empty directory for a test, cleared of anything left by an earlier run
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cpar-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// PNG of a black rectangle on a white background
+fn artwork() -> Vec<u8> {
+    let img = GrayImage::from_fn(60, 40, |x, y| {
+        Luma([if (10..50).contains(&x) && (10..30).contains(&y) { 0 } else { 255 }])
+    });
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png).unwrap();
+    png.into_inner()
+}
+
+fn cpar(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cpar")).current_dir(dir).args(args).output().unwrap()
+}
+
+/// Dimensions of the artwork once cropped as a file rather than inside an archive
+fn cropped_file(dir: &Path) -> (u32, u32) {
+    fs::write(dir.join("page.png"), artwork()).unwrap();
+    let output = cpar(dir, &["page.png", "pages", "-q", "--crop-only"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    image::image_dimensions(dir.join("pages").join("page.png")).unwrap()
+}
+
+/// Write an archive of the given entries, compressed by the given methods, with a comment after
+/// its central directory
+fn archive(path: &Path, entries: &[(&str, &[u8], CompressionMethod)]) {
+    let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+    for &(name, data, method) in entries {
+        zip.start_file(name, SimpleFileOptions::default().compression_method(method)).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.set_comment("scanned pages").unwrap();
+    zip.finish().unwrap();
+}
+
+/// Names and decompressed data of the entries of an archive, in order
+fn entries(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut zip = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+    (0..zip.len()).map(|index| {
+        let mut entry = zip.by_index(index).unwrap();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        (entry.name().unwrap().into_owned(), data)
+    }).collect()
+}
+
+#[test]
+fn stored_and_deflated_images_are_cropped() {
+    let dir = scratch("archive");
+    let png = artwork();
+    archive(&dir.join("issue.cbz"), &[
+        ("pages/01.png", &png, CompressionMethod::Stored),
+        ("pages/02.png", &png, CompressionMethod::Deflated),
+        ("notes.txt", b"kept as it is", CompressionMethod::Deflated)
+    ]);
+
+    let output = cpar(&dir, &["issue.cbz", "out", "-q", "--crop-only"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let cropped = entries(&dir.join("out").join("issue.cbz"));
+    let names: Vec<_> = cropped.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["pages/01.png", "pages/02.png", "notes.txt"]);
+    let expected = cropped_file(&dir);
+    for (name, data) in &cropped[..2] {
+        let img = image::load_from_memory(data).unwrap();
+        assert_eq!((img.width(), img.height()), expected, "{}", name);
+    }
+    assert_eq!(cropped[2].1, b"kept as it is");
+}
+
+#[test]
+fn written_archives_are_read_back() {
+    let dir = scratch("archive-again");
+    archive(&dir.join("issue.zip"), &[("01.png", &artwork(), CompressionMethod::Deflated)]);
+    let output = cpar(&dir, &["issue.zip", "once", "-q", "--crop-only"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Reporting the crops of the written archive reads it with the same reader
+    let output = cpar(&dir, &["once/issue.zip", "twice", "-q", "-n", "--crop-only"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let (width, height) = cropped_file(&dir);
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.starts_with(&format!("issue.zip/01.png: {}x{} ", width, height)), "{}", report);
+}
+
+#[test]
+fn truncated_archives_fail() {
+    let dir = scratch("archive-truncated");
+    archive(&dir.join("issue.zip"), &[("01.png", &artwork(), CompressionMethod::Stored)]);
+    let data = fs::read(dir.join("issue.zip")).unwrap();
+    fs::write(dir.join("issue.zip"), &data[..data.len() / 2]).unwrap();
+
+    let output = cpar(&dir, &["issue.zip", "out", "-q"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!dir.join("out").join("issue.zip").exists());
+}
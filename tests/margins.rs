@@ -0,0 +1,115 @@
+use cpar::{crop_rect, detect, process, CropParams, CropRect, EdgeDetection, Sides};
+use image::{DynamicImage, GrayImage, Luma};
+
+/// Image of the given size with a black rectangle on a white background
+fn artwork(width: u32, height: u32, x: u32, y: u32, art_width: u32, art_height: u32) -> DynamicImage {
+    let img = GrayImage::from_fn(width, height, |px, py| {
+        let inside = (x..x + art_width).contains(&px) && (y..y + art_height).contains(&py);
+        Luma([if inside { 0 } else { 255 }])
+    });
+    DynamicImage::ImageLuma8(img)
+}
+
+fn params(extra: Sides<i32>) -> CropParams {
+    CropParams {
+        detection: EdgeDetection { extra, ..EdgeDetection::default() },
+        ..CropParams::default()
+    }
+}
+
+fn contained(rect: &CropRect, width: u32, height: u32) -> bool {
+    rect.width >= 1 && rect.height >= 1 && rect.x + rect.width <= width && rect.y + rect.height <= height
+}
+
+#[test]
+fn extra_within_edges_is_removed() {
+    let edges = Sides { left: 10, right: 90, top: 20, bottom: 80 };
+    let rect = crop_rect(&edges, &Sides::all(5), 100, 100);
+    assert_eq!(rect, CropRect { x: 15, y: 25, width: 70, height: 50 });
+}
+
+#[test]
+fn extra_beyond_edges_collapses_within_image() {
+    let edges = Sides { left: 10, right: 90, top: 20, bottom: 80 };
+    for extra in [50, 1000, i32::MAX] {
+        let rect = crop_rect(&edges, &Sides::all(extra), 100, 100);
+        assert!(contained(&rect, 100, 100), "{:?} with extra {}", rect, extra);
+    }
+}
+
+#[test]
+fn extra_on_one_side_only_saturates() {
+    let edges = Sides { left: 10, right: 90, top: 20, bottom: 80 };
+    let extra = Sides { left: 0, right: i32::MAX, top: i32::MAX, bottom: 0 };
+    let rect = crop_rect(&edges, &extra, 100, 100);
+    assert!(contained(&rect, 100, 100), "{:?}", rect);
+    assert_eq!((rect.x, rect.height), (10, 1));
+}
+
+#[test]
+fn negative_extra_keeps_border() {
+    let edges = Sides { left: 10, right: 90, top: 20, bottom: 80 };
+    let extra = Sides { left: -5, right: 0, top: 0, bottom: -10 };
+    let rect = crop_rect(&edges, &extra, 100, 100);
+    assert_eq!(rect, CropRect { x: 5, y: 20, width: 85, height: 70 });
+}
+
+#[test]
+fn negative_extra_beyond_image_is_clamped() {
+    let edges = Sides { left: 10, right: 90, top: 20, bottom: 80 };
+    for extra in [-50, -1000, i32::MIN] {
+        let rect = crop_rect(&edges, &Sides::all(extra), 100, 100);
+        assert_eq!(rect, CropRect { x: 0, y: 0, width: 100, height: 100 }, "with extra {}", extra);
+    }
+}
+
+#[test]
+fn edges_outside_image_are_clamped() {
+    let edges = Sides { left: 500, right: 900, top: 500, bottom: 900 };
+    let rect = crop_rect(&edges, &Sides::all(0), 100, 100);
+    assert!(contained(&rect, 100, 100), "{:?}", rect);
+}
+
+#[test]
+fn single_pixel_image() {
+    let img = artwork(1, 1, 0, 0, 1, 1);
+    let rect = detect(&img, &params(Sides::all(0)).detection).unwrap();
+    assert_eq!(rect, CropRect { x: 0, y: 0, width: 1, height: 1 });
+    let processed = process(&img, &params(Sides::all(i32::MAX))).unwrap();
+    assert_eq!((processed.width(), processed.height()), (1, 1));
+}
+
+#[test]
+fn tiny_images_with_large_extra() {
+    for (width, height) in [(2, 2), (3, 1), (1, 5), (4, 3)] {
+        let img = artwork(width, height, 0, 0, width, height);
+        for extra in [i32::MIN, -1, 1, 2, 10, i32::MAX] {
+            let params = params(Sides::all(extra));
+            let rect = detect(&img, &params.detection).unwrap();
+            assert!(contained(&rect, width, height), "{:?} in {}x{} with extra {}", rect, width, height, extra);
+            let processed = process(&img, &params).unwrap();
+            assert!(processed.width() >= 1 && processed.height() >= 1);
+        }
+    }
+}
+
+#[test]
+fn large_downscale_keeps_a_pixel() {
+    let img = artwork(40, 30, 5, 5, 20, 10);
+    let params = CropParams { downscale: 1000.0, ..params(Sides::all(0)) };
+    let processed = process(&img, &params).unwrap();
+    assert_eq!((processed.width(), processed.height()), (1, 1));
+}
+
+#[test]
+fn lines_between_sampled_rows_are_detected() {
+    // A thin line above the artwork, on a row between those sampled every 8 rows
+    let mut img = artwork(100, 100, 20, 40, 60, 40).into_luma8();
+    for x in 20..80 {
+        img.put_pixel(x, 13, Luma([0]));
+    }
+    let img = DynamicImage::ImageLuma8(img);
+    let sampled = EdgeDetection { sample_stride: 8, ..EdgeDetection::default() };
+    assert_eq!(detect(&img, &sampled), detect(&img, &EdgeDetection::default()));
+    assert_eq!(detect(&img, &sampled).map(|rect| rect.y), Some(13));
+}
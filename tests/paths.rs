@@ -0,0 +1,141 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use image::{GrayImage, Luma};
+
+/// Empty directory for a test, cleared of anything left by an earlier run
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cpar-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Save an image of a black rectangle on a white background
+fn artwork(path: &Path) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let img = GrayImage::from_fn(60, 40, |x, y| {
+        Luma([if (10..50).contains(&x) && (10..30).contains(&y) { 0 } else { 255 }])
+    });
+    img.save(path).unwrap();
+}
+
+fn cpar<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cpar")).current_dir(dir).args(args).output().unwrap()
+}
+
+/// Join a filename given as bytes, which need not be valid UTF-8
+#[cfg(unix)]
+fn join_bytes(dir: &Path, name: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    dir.join(OsStr::from_bytes(name))
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_filenames_are_kept() {
+    let dir = scratch("non-utf8");
+    artwork(&join_bytes(&dir.join("src"), b"scan-\xff.png"));
+
+    let output = cpar(&dir, &["src", "out", "-q"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(join_bytes(&dir.join("out"), b"scan-\xff.png").is_file());
+
+    let output = cpar(&dir, &["src", "out", "-q", "--rename"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(join_bytes(&dir.join("out"), b"scan-\xff-1.png").is_file());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_failures_are_reported_and_listed() {
+    let dir = scratch("non-utf8-failed");
+    let source = join_bytes(&dir.join("src"), b"bad-\xfe.png");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(&source, b"not an image").unwrap();
+
+    let output = cpar(&dir, &["src", "out", "-q", "--report", "report.json", "--failed-list", "failed.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("report.json")).unwrap()).unwrap();
+    assert_eq!(report[0]["source"], "src/bad-\u{FFFD}.png");
+    assert_eq!(fs::read(dir.join("failed.txt")).unwrap(), b"src/bad-\xfe.png\n");
+
+    // The listed path names the same file once it is fixed
+    artwork(&source);
+    let output = cpar(&dir, &["--files-from", "failed.txt", "out", "-q"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(join_bytes(&dir.join("out"), b"bad-\xfe.png").is_file());
+}
+
+#[test]
+fn paths_over_260_characters_are_processed() {
+    let dir = scratch("long");
+    let nested: PathBuf = (0..6).map(|n| format!("{}{}", n, "d".repeat(50))).collect();
+    let source = dir.join("src").join(&nested).join("scan.png");
+    assert!(source.as_os_str().len() > 260);
+    artwork(&source);
+
+    let output = cpar(&dir, &[source.as_os_str(), dir.join("out").join(&nested).as_os_str(), "-q".as_ref()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("out").join(&nested).join("scan.png").is_file());
+
+    let output = cpar(&dir, &["src", "mirrored", "-q", "-P"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("mirrored").join(&nested).join("scan.png").is_file());
+}
+
+#[cfg(windows)]
+#[test]
+fn verbatim_patterns_are_expanded() {
+    let dir = scratch("verbatim");
+    artwork(&dir.join("src").join("scan.png"));
+
+    // Canonical paths take the verbatim \\?\ prefix on Windows
+    let pattern = dir.canonicalize().unwrap().join("src").join("*.png");
+    let output = cpar(&dir, &[pattern.as_os_str(), "out".as_ref(), "-q".as_ref()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("out").join("scan.png").is_file());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_sources_are_unchanged_on_incremental_runs() {
+    let dir = scratch("non-utf8-incremental");
+    artwork(&join_bytes(&dir.join("src"), b"scan-\xfd.png"));
+
+    let output = cpar(&dir, &["src", "out", "-q", "--incremental"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = cpar(&dir, &["src", "out", "--incremental"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unchanged since it was last processed"));
+}
+
+#[test]
+fn resumed_sources_are_told_apart_by_path_and_contents() {
+    let dir = scratch("resume-fingerprint");
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    for name in ["a.bmp", "b.bmp"] {
+        artwork(&dir.join("src").join(name));
+    }
+    // Images of the same size and modification time, differing only in their contents
+    let mut img = image::open(dir.join("src").join("b.bmp")).unwrap().to_luma8();
+    img.put_pixel(0, 0, Luma([254]));
+    img.save(dir.join("src").join("b.bmp")).unwrap();
+    for name in ["a.bmp", "b.bmp"] {
+        fs::File::options().write(true).open(dir.join("src").join(name)).unwrap().set_modified(modified).unwrap();
+    }
+
+    let output = cpar(&dir, &["src/a.bmp", "out", "-q", "--resume", "--state-file", "state.jsonl"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = cpar(&dir, &["src", "out", "-q", "--resume", "--state-file", "state.jsonl"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("out").join("b.bmp").is_file());
+
+    // Renamed images are still recognized by their contents
+    fs::rename(dir.join("src").join("a.bmp"), dir.join("src").join("c.bmp")).unwrap();
+    let output = cpar(&dir, &["src", "out", "--resume", "--state-file", "state.jsonl"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipped c.bmp: completed by an earlier run"));
+    assert!(!dir.join("out").join("c.bmp").exists());
+}
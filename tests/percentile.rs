@@ -0,0 +1,106 @@
+use cpar::{detect_edges, EdgeDetection, Sides};
+use image::{DynamicImage, GrayImage, Luma};
+
+/// Small xorshift generator, so that cases are random but reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as u32
+    }
+}
+
+/// Percentile of values by linear interpolation between closest ranks, as in NumPy's default
+fn reference(values: &[u32], percentile: u8) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = percentile as f64 / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] as f64 + (rank - lower as f64) * (sorted[upper] as f64 - sorted[lower] as f64)
+}
+
+/// Nearest whole depth to a percentile, keeping the shallower depth when halfway between two
+fn nearest(depth: f64) -> u32 {
+    (depth - 0.5).ceil() as u32
+}
+
+/// Image whose rows are artwork from the given depth inward from the left and right sides
+fn rows(width: u32, left: &[u32], right: &[u32]) -> DynamicImage {
+    let img = GrayImage::from_fn(width, left.len() as u32, |x, y| {
+        let artwork = x >= left[y as usize] && x < width - right[y as usize];
+        Luma([if artwork { 0 } else { 255 }])
+    });
+    DynamicImage::ImageLuma8(img)
+}
+
+/// Image whose columns are artwork from the given depth inward from the top and bottom sides
+fn columns(height: u32, top: &[u32], bottom: &[u32]) -> DynamicImage {
+    let img = GrayImage::from_fn(top.len() as u32, height, |x, y| {
+        let artwork = y >= top[x as usize] && y < height - bottom[x as usize];
+        Luma([if artwork { 0 } else { 255 }])
+    });
+    DynamicImage::ImageLuma8(img)
+}
+
+fn detection(percentile: u8) -> EdgeDetection {
+    EdgeDetection { percentile: Sides::all(percentile), ..EdgeDetection::default() }
+}
+
+#[test]
+fn edges_match_reference_percentiles() {
+    let mut rng = Rng(0x5eed);
+    for case in 0..500 {
+        // Keep artwork at least a pixel wide in every row and column, and samples small
+        let count = 1 + rng.below(40);
+        let size = 2 + rng.below(60);
+        let depths = |rng: &mut Rng| (0..count).map(|_| rng.below(size / 2)).collect::<Vec<_>>();
+        let (near, far) = (depths(&mut rng), depths(&mut rng));
+        let percentile = match case % 5 {
+            0 => 0,
+            1 => 100,
+            _ => rng.below(101) as u8
+        };
+
+        let context = format!("case {} at percentile {} of {:?} and {:?} in {}", case, percentile, near, far, size);
+        let edges = detect_edges(&rows(size, &near, &far), &detection(percentile)).expect(&context);
+        assert_eq!(edges.left, nearest(reference(&near, percentile)), "left edge of {}", context);
+        assert_eq!(edges.right, size - 1 - nearest(reference(&far, percentile)), "right edge of {}", context);
+
+        let edges = detect_edges(&columns(size, &near, &far), &detection(percentile)).expect(&context);
+        assert_eq!(edges.top, nearest(reference(&near, percentile)), "top edge of {}", context);
+        assert_eq!(edges.bottom, size - 1 - nearest(reference(&far, percentile)), "bottom edge of {}", context);
+    }
+}
+
+#[test]
+fn extreme_percentiles_select_shallowest_and_deepest() {
+    let depths = [3, 9, 0, 5, 7];
+    let img = rows(30, &depths, &depths);
+    let edges = detect_edges(&img, &detection(0)).unwrap();
+    assert_eq!((edges.left, edges.right), (0, 29));
+    let edges = detect_edges(&img, &detection(100)).unwrap();
+    assert_eq!((edges.left, edges.right), (9, 20));
+}
+
+#[test]
+fn small_samples_are_not_overcropped() {
+    // Ten rows, one of which reaches deeper: the 95th percentile lies between the ninth row and
+    // the outlier, rather than at the outlier
+    let depths = [2, 2, 2, 2, 2, 2, 2, 2, 2, 40];
+    let img = rows(100, &depths, &[0; 10]);
+    let edges = detect_edges(&img, &detection(95)).unwrap();
+    assert_eq!(edges.left, nearest(reference(&depths, 95)));
+    assert!(edges.left < 40, "left edge {} placed at the outlier", edges.left);
+}
+
+#[test]
+fn halfway_percentiles_keep_the_shallower_pixel() {
+    let depths = [4, 5];
+    let img = rows(20, &depths, &depths);
+    let edges = detect_edges(&img, &detection(50)).unwrap();
+    assert_eq!((edges.left, edges.right), (4, 15));
+}
@@ -0,0 +1,122 @@
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use image::{GrayImage, ImageFormat, Luma};
+
+/// Server running on a free port, stopped when dropped
+struct Server {
+    child: Child,
+    port: u16
+}
+
+impl Server {
+    fn start(args: &[&str]) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let child = Command::new(env!("CARGO_BIN_EXE_cpar"))
+            .args(["serve", "--port", &port.to_string(), "-j", "2"])
+            .args(args)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server { child, port };
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("server did not start listening");
+    }
+
+    /// Send a request, returning the status code, headers and body of the response
+    fn request(&self, method: &str, target: &str, body: &[u8]) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method, target, body.len()
+        ).unwrap();
+        // Servers may respond before reading all of a body they refuse
+        let _ = stream.write_all(body);
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|window| window == b"\r\n\r\n").expect("complete response");
+        let head = String::from_utf8_lossy(&response[..split]).into_owned();
+        let status = head.split(' ').nth(1).and_then(|status| status.parse().ok()).expect("status code");
+        (status, head, response[split + 4..].to_vec())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// PNG of a black rectangle on a white background
+fn artwork() -> Vec<u8> {
+    let img = GrayImage::from_fn(60, 40, |x, y| {
+        Luma([if (10..50).contains(&x) && (10..30).contains(&y) { 0 } else { 255 }])
+    });
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png).unwrap();
+    png.into_inner()
+}
+
+/// Value of a header of a response
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+#[test]
+fn uploaded_images_are_cropped() {
+    let server = Server::start(&[]);
+    let (status, head, body) = server.request("POST", "/crop?crop-only&format=webp&verify", &artwork());
+    assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+    assert_eq!(header(&head, "Content-Type"), Some("image/webp"));
+    let img = image::load_from_memory_with_format(&body, ImageFormat::WebP).unwrap();
+    let crop = header(&head, "X-Crop").expect("crop header");
+    assert!(crop.starts_with(&format!("{}x{}+", img.width(), img.height())), "{}", crop);
+}
+
+#[test]
+fn invalid_requests_are_refused() {
+    let server = Server::start(&["--max-size", "1"]);
+    assert_eq!(server.request("POST", "/other", &artwork()).0, 404);
+    assert_eq!(server.request("GET", "/crop", b"").0, 405);
+    assert_eq!(server.request("POST", "/crop?threshold=none", &artwork()).0, 400);
+    assert_eq!(server.request("POST", "/crop?pre-cmd=true", &artwork()).0, 400);
+    assert_eq!(server.request("POST", "/crop", b"not an image").0, 400);
+    assert_eq!(server.request("POST", "/crop", &vec![0; 1024 * 1024 + 1]).0, 413);
+}
+
+#[test]
+fn options_outside_image_parameters_are_refused() {
+    let server = Server::start(&[]);
+    for query in ["param-manifest=/etc/passwd", "mask=/etc/passwd", "report=report.json", "jobs=2"] {
+        let (status, _, body) = server.request("POST", &format!("/crop?{}", query), &artwork());
+        assert_eq!(status, 400, "{}", query);
+        assert!(String::from_utf8_lossy(&body).contains("cannot be requested"), "{}", query);
+    }
+    // Names are decoded before they are looked up, and values are not taken as options
+    for query in ["mask%3D/etc/passwd", "gpu%3Dtrue", "threshold=--mask%3D/etc/passwd", "xmp=--mask%3D/etc/passwd"] {
+        assert_eq!(server.request("POST", &format!("/crop?{}", query), &artwork()).0, 400, "{}", query);
+    }
+    // Aliases of image parameters are accepted
+    assert_eq!(server.request("POST", "/crop?xt=240", &artwork()).0, 200);
+}
+
+#[test]
+fn blank_and_flagged_images_are_unprocessable() {
+    let server = Server::start(&[]);
+    let mut blank = Cursor::new(Vec::new());
+    GrayImage::from_pixel(60, 40, Luma([255])).write_to(&mut blank, ImageFormat::Png).unwrap();
+    assert_eq!(server.request("POST", "/crop", blank.get_ref()).0, 422);
+    assert_eq!(server.request("POST", "/crop?min-width=1000", &artwork()).0, 422);
+}
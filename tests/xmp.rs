@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use image::{GrayImage, Luma};
+
+/// Empty directory for a test, cleared of anything left by an earlier run
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cpar-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Save an image of a black rectangle on a white background
+fn artwork(path: &Path) {
+    let img = GrayImage::from_fn(60, 40, |x, y| {
+        Luma([if (10..50).contains(&x) && (10..30).contains(&y) { 0 } else { 255 }])
+    });
+    img.save(path).unwrap();
+}
+
+fn cpar(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cpar")).current_dir(dir).args(args).output().unwrap()
+}
+
+/// Crop the artwork with its crop recorded in XMP, saving it in a format and returning the file
+fn saved(dir: &Path, format: &str) -> Vec<u8> {
+    artwork(&dir.join("scan.png"));
+    let output = cpar(dir, &["scan.png", "out", "-q", "--xmp", "-f", format]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let path = dir.join("out").join(format!("scan.{}", format));
+    assert!(image::open(&path).is_ok(), "{} does not decode", path.display());
+    fs::read(path).unwrap()
+}
+
+fn records_original(data: &[u8]) -> bool {
+    let contains = |text: &str| data.windows(text.len()).any(|window| window == text.as_bytes());
+    contains("cpar:OriginalWidth=\"60\"") && contains("cpar:OriginalHeight=\"40\"")
+}
+
+/// Restore a saved image to the dimensions of its original
+fn restored(dir: &Path, format: &str) -> (u32, u32) {
+    let output = cpar(dir, &["restore", &format!("out/scan.{}", format), "restored"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    image::image_dimensions(dir.join("restored").join(format!("scan.{}", format))).unwrap()
+}
+
+#[test]
+fn jpeg_holds_packet_in_app1_segment() {
+    let dir = scratch("xmp-jpeg");
+    let data = saved(&dir, "jpg");
+    let identifier = b"http://ns.adobe.com/xap/1.0/\0";
+    let at = data.windows(identifier.len()).position(|window| window == identifier).expect("XMP segment");
+    assert_eq!(data[at - 4..at - 2], [0xFF, 0xE1]);
+    assert!(records_original(&data));
+    assert_eq!(restored(&dir, "jpg"), (60, 40));
+}
+
+#[test]
+fn png_holds_packet_in_itxt_chunk_after_header() {
+    let dir = scratch("xmp-png");
+    let data = saved(&dir, "png");
+    assert_eq!(&data[12..16], b"IHDR");
+    assert_eq!(&data[37..41], b"iTXt");
+    assert!(records_original(&data));
+    assert_eq!(restored(&dir, "png"), (60, 40));
+}
+
+#[test]
+fn webp_holds_packet_in_extended_format() {
+    let dir = scratch("xmp-webp");
+    let data = saved(&dir, "webp");
+    assert_eq!(&data[12..16], b"VP8X");
+    assert_ne!(data[20] & 0x04, 0, "XMP flag is set");
+    assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+    assert!(records_original(&data));
+    assert_eq!(restored(&dir, "webp"), (60, 40));
+}
+
+#[test]
+fn other_formats_hold_packet_in_sidecar() {
+    let dir = scratch("xmp-sidecar");
+    let data = saved(&dir, "tiff");
+    assert!(!records_original(&data));
+    assert!(records_original(&fs::read(dir.join("out").join("scan.tiff.xmp")).unwrap()));
+    assert_eq!(restored(&dir, "tiff"), (60, 40));
+}
+
+#[test]
+fn rotated_images_are_restored_as_their_originals() {
+    let dir = scratch("xmp-rotated");
+    artwork(&dir.join("scan.png"));
+    let output = cpar(&dir, &["scan.png", "out", "-q", "--xmp", "--rotate", "90"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let data = fs::read(dir.join("out").join("scan.png")).unwrap();
+    let contains = |text: &str| data.windows(text.len()).any(|window| window == text.as_bytes());
+    assert!(contains("cpar:OriginalWidth=\"40\"") && contains("cpar:Rotation=\"90\""));
+
+    // The artwork is turned back to where it was cropped from
+    assert_eq!(restored(&dir, "png"), (60, 40));
+    let img = image::open(dir.join("restored").join("scan.png")).unwrap().to_luma8();
+    assert!(img.get_pixel(30, 20)[0] < 128);
+    assert!(img.get_pixel(5, 5)[0] > 128);
+}